@@ -1,21 +1,106 @@
+use crate::capturer;
 use crate::config::Config;
-use crate::movie_maker::MovieMaker;
+use crate::day_index::DayIndex;
+use crate::day_lock::DayLock;
+use crate::days_off::DaysOff;
+use crate::dir_manager::{DirManager, ARCHIVE_FILE_EXTENSION};
+use crate::movie_maker::{MovieError, MovieMaker, VideoSettings};
+use crate::notify;
+use crate::uploader::Uploader;
+use crate::video_name::VIDEO_TYPES;
 
 use anyhow::Error;
-use chrono::{DateTime, Datelike, Local};
+use chrono::{DateTime, Datelike, Local, NaiveDate};
 use glob::glob;
-use log::{info, warn};
-use std::collections::HashSet;
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Component, Path, PathBuf};
 use std::result::Result;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Days the backfiller gave up on, at the top of the shot directory. They're left out of every
+/// backfill after, until they're taken out of here.
+const FAILED_DAYS_FILE: &str = "ompd-failed-days.json";
+
+/// Where a backfill is up to, so if we get stopped partway through the next run picks up the same
+/// days instead of going looking again. Gone once the backfill finishes.
+const QUEUE_FILE: &str = "ompd-backfill-queue.json";
+
+/// How often to look again whether anyone's still at the computer, with backfill_when_idle_secs.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BackfillQueue {
+    pending: BTreeSet<String>,
+    done: BTreeSet<String>,
+    failed: BTreeSet<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct FailedDay {
+    reason: String,
+    failed_at: DateTime<Local>,
+}
+
+/// How far along the backfill is.
+#[derive(Clone, Debug)]
+pub struct BackfillProgress {
+    pub days_done: usize,
+    pub days_total: usize,
+    /// Days whose movies are being made right now.
+    pub working_on: Vec<String>,
+    pub eta: Option<Duration>,
+}
+
+static PROGRESS: Mutex<Option<BackfillProgress>> = Mutex::new(None);
+
+/// How the backfill is going, if there's one going.
+pub fn backfill_progress() -> Option<BackfillProgress> {
+    PROGRESS.lock().unwrap().clone()
+}
+
+/// What a panic said, if it said anything.
+fn panic_message(panicked: &(dyn std::any::Any + Send)) -> String {
+    match panicked.downcast_ref::<&str>() {
+        Some(message) => format!("panicked: {message}"),
+        None => match panicked.downcast_ref::<String>() {
+            Some(message) => format!("panicked: {message}"),
+            None => "panicked".to_string(),
+        },
+    }
+}
+
+/// e.g. "2h 5m", "12m", or "40s".
+fn rough_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        _ => format!("{}h {}m", secs / 3600, secs / 60 % 60),
+    }
+}
+
+/// e.g. "1.4 GB" or "350 MB".
+fn rough_size(bytes: u64) -> String {
+    match bytes {
+        0..1_000_000_000 => format!("{} MB", bytes / 1_000_000),
+        _ => format!("{:.1} GB", bytes as f64 / 1_000_000_000.0),
+    }
+}
 
 pub struct BackFiller {
     config: Config,
     today: Discovered,
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 struct Discovered {
     year: u16,
     month: u8,
@@ -23,6 +108,28 @@ struct Discovered {
 }
 
 impl Discovered {
+    /// Back out of the "2023-04-05" that Display gives us.
+    fn from_key(key: &str) -> Option<Discovered> {
+        let mut parts = key.split('-');
+        Some(Discovered {
+            year: parts.next()?.parse().ok()?,
+            month: parts.next()?.parse().ok()?,
+            day: parts.next()?.parse().ok()?,
+        })
+    }
+
+    fn from_date(date: NaiveDate) -> Discovered {
+        Discovered {
+            year: date.year() as u16,
+            month: date.month() as u8,
+            day: date.day() as u8,
+        }
+    }
+
+    fn to_date(&self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year.into(), self.month.into(), self.day.into())
+    }
+
     fn to_shot_dir_in(&self, root_dir: &Path) -> PathBuf {
         root_dir
             .join(format!("{}", self.year))
@@ -40,75 +147,646 @@ impl fmt::Display for Discovered {
 impl BackFiller {
     pub fn new(config: Config, today: DateTime<Local>) -> BackFiller {
         //shots_root_dir: &Path, vids_root_dir: &Path, ffmpeg: &str, vid_width: u32, vid_height: u32) -> BackFiller {
+        // Before day_starts_at_hour it's still yesterday as far as capturing goes, and that's the
+        // day to leave alone.
+        let today = DirManager::shot_day(&today, config.day_starts_at_hour);
         BackFiller {
             config,
-            today: Discovered {
-                year: today.year() as u16,
-                month: today.month() as u8,
-                day: today.day() as u8,
-            },
+            today: Discovered::from_date(today),
         }
     }
 
     pub fn run(&self) {
-        let mut vid_coverage = match self.discover_vids() {
-            Ok(r) => r,
+        let shared_dirs = self.config.shared_dirs();
+        let Some((shared_shots, shared_vids)) =
+            shared_dirs.filter(|_| self.config.backfill_all_hosts)
+        else {
+            self.run_for_host();
+            return;
+        };
+
+        let hosts = match std::fs::read_dir(&shared_shots) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|name| is_host_dir(name))
+                .collect::<Vec<_>>(),
             Err(e) => {
-                warn!("Couldn't discover videos, giving up!: {e}");
-                return;
+                warn!("Couldn't look for other machines' shots in {shared_shots:?}: {e}");
+                Vec::new()
+            }
+        };
+
+        // config is already this machine's, so back up to the shared one before picking others.
+        let mut shared = self.config.clone();
+        shared.shot_output_dir = shared_shots.to_string_lossy().to_string();
+        shared.vid_output_dir = shared_vids.to_string_lossy().to_string();
+
+        for host in hosts {
+            info!("Backfilling movies for {host}");
+            BackFiller {
+                config: shared.for_host(&host),
+                today: self.today.clone(),
+            }
+            .run_for_host();
+        }
+    }
+
+    fn run_for_host(&self) {
+        let resumed: BackfillQueue = self.load_json(&self.queue_path());
+        let mut to_process = if !resumed.pending.is_empty() {
+            info!(
+                "Picking up the last backfill where it left off, {} days to go and {} done",
+                resumed.pending.len(),
+                resumed.done.len() + resumed.failed.len()
+            );
+            resumed
+                .pending
+                .iter()
+                .filter_map(|day| Discovered::from_key(day))
+                .collect()
+        } else {
+            match self.days_from_index() {
+                Ok(days) => days,
+                Err(e) => {
+                    warn!(
+                        "Couldn't use the day index, looking at everything on disk instead: {e:?}"
+                    );
+                    match self.days_from_disk() {
+                        Ok(days) => days,
+                        Err(e) => {
+                            warn!("Couldn't discover videos, giving up!: {e}");
+                            return;
+                        }
+                    }
+                }
             }
         };
 
-        // Throw in today's video so that when we find the directory below we don't try to start the video process early
-        vid_coverage.insert(self.today.clone());
+        to_process.extend(self.failed_attempts());
 
-        let shot_coverage = match self.discover_shots() {
-            Ok(r) => r,
+        let m = match MovieMaker::new(self.config.clone()) {
+            Ok(m) => m.for_backfill(&self.config),
             Err(e) => {
-                warn!("Couldn't discover videos, giving up!: {e}");
+                warn!("Can't make any movies, not backfilling: {e:?}");
                 return;
             }
         };
+        if self.config.backfill_reencode {
+            match self.outdated_videos(&m.video_settings()) {
+                Ok(outdated) => to_process.extend(outdated),
+                Err(e) => warn!("Couldn't look for out of date movies: {e:?}"),
+            }
+        }
+
+        // Leave today alone so we don't try to start the video process early
+        to_process.remove(&self.today);
 
-        let to_process = shot_coverage.difference(&vid_coverage);
+        let (since, until) = (self.config.backfill_since, self.config.backfill_until);
+        if since.is_some() || until.is_some() {
+            to_process.retain(|day| {
+                day.to_date().is_some_and(|date| {
+                    since.is_none_or(|since| date >= since)
+                        && until.is_none_or(|until| date <= until)
+                })
+            });
+        }
 
-        let m = MovieMaker::new(self.config.clone());
+        let days_off = DaysOff::from_config(&self.config);
+        to_process.retain(|day| day.to_date().is_none_or(|date| !days_off.contains(date)));
 
+        let failed_days: BTreeMap<String, FailedDay> = self.load_json(&self.failed_days_path());
+        let before = to_process.len();
+        to_process.retain(|day| !failed_days.contains_key(&day.to_string()));
+        if to_process.len() < before {
+            info!(
+                "Leaving out {} days that failed before, take them out of {:?} to try them again",
+                before - to_process.len(),
+                self.failed_days_path()
+            );
+        }
+
+        if self.config.backfill_dry_run {
+            self.dry_run(&m, &to_process);
+            return;
+        }
+
+        // Oldest first, handed out to however many jobs as they come free.
+        let mut days: Vec<Discovered> = to_process.into_iter().collect();
+        days.sort_by(|a, b| b.cmp(a));
+        let days_total = days.len();
+        let saved_queue = Mutex::new(BackfillQueue {
+            pending: days.iter().map(Discovered::to_string).collect(),
+            ..resumed
+        });
+        self.save_json(&self.queue_path(), &*saved_queue.lock().unwrap());
+        let queue = Mutex::new(days);
+        *PROGRESS.lock().unwrap() = Some(BackfillProgress {
+            days_done: 0,
+            days_total,
+            working_on: Vec::new(),
+            eta: None,
+        });
+        let started = Instant::now();
         let root_shot_dir = PathBuf::from(&self.config.shot_output_dir);
-        for dir in to_process {
-            info!("Launching movie maker for {dir}");
-            m.make_movie_from(&dir.to_shot_dir_in(&root_shot_dir));
+        let failed_days = Mutex::new(failed_days);
+        let newly_failed = Mutex::new(Vec::new());
+        let days_made = AtomicUsize::new(0);
+        let bytes_made = AtomicU64::new(0);
+        thread::scope(|scope| {
+            for _ in 0..self.config.backfill_jobs {
+                scope.spawn(|| loop {
+                    self.wait_for_idle();
+                    // Let go of the queue before getting to work, so other jobs can take from it.
+                    let Some(dir) = queue.lock().unwrap().pop() else {
+                        break;
+                    };
+                    let day = dir.to_string();
+                    Self::starting_day(&day);
+                    info!("Launching movie maker for {dir}");
+                    // One day going wrong shouldn't hold up the rest.
+                    let made = Self::make_movie(&m, &dir, &root_shot_dir);
+                    if let Ok(Some(bytes)) = made {
+                        days_made.fetch_add(1, Ordering::Relaxed);
+                        bytes_made.fetch_add(bytes, Ordering::Relaxed);
+                    }
+                    if let Err(reason) = &made {
+                        warn!("Couldn't make the movie for {dir}, leaving it out: {reason}");
+                        let mut failed_days = failed_days.lock().unwrap();
+                        failed_days.insert(
+                            day.clone(),
+                            FailedDay {
+                                reason: reason.clone(),
+                                failed_at: Local::now(),
+                            },
+                        );
+                        self.save_json(&self.failed_days_path(), &*failed_days);
+                        newly_failed
+                            .lock()
+                            .unwrap()
+                            .push(format!("{day} ({reason})"));
+                    }
+
+                    let mut saved_queue = saved_queue.lock().unwrap();
+                    saved_queue.pending.remove(&day);
+                    match made {
+                        Ok(_) => saved_queue.done.insert(day.clone()),
+                        Err(_) => saved_queue.failed.insert(day.clone()),
+                    };
+                    self.save_json(&self.queue_path(), &*saved_queue);
+                    drop(saved_queue);
+
+                    Self::finished_day(&day, started);
+                });
+            }
+        });
+        *PROGRESS.lock().unwrap() = None;
+        if let Err(e) = std::fs::remove_file(self.queue_path()) {
+            warn!("Couldn't clear out {:?}: {e}", self.queue_path());
+        }
+
+        let mut newly_failed = newly_failed.into_inner().unwrap();
+        let summary = format!(
+            "{days_total} days took {}: {} movies made ({}), {} failed",
+            rough_duration(started.elapsed()),
+            days_made.into_inner(),
+            rough_size(bytes_made.into_inner()),
+            newly_failed.len()
+        );
+        info!("Done backfilling movies, {summary}");
+        // Nobody needs to hear about every startup that found nothing to do.
+        if days_total > 0 {
+            notify::desktop("ompd is done backfilling", &summary);
+        }
+        if !newly_failed.is_empty() {
+            newly_failed.sort();
+            warn!(
+                "{} days failed and were added to {:?}: {}",
+                newly_failed.len(),
+                self.failed_days_path(),
+                newly_failed.join(", ")
+            );
+        }
+
+        // A share copy that didn't get made along with its movie can still be made from it.
+        if self.config.share_copy.is_some() {
+            let days = self.discover_vids().unwrap_or_default();
+            for date in days.iter().filter_map(Discovered::to_date) {
+                m.make_share_copy_if_missing(date);
+            }
+        }
+
+        // Catch up on reviews for any years that ended while we weren't running.
+        if self.config.year_review.is_some() {
+            let years: BTreeSet<u16> = self
+                .discover_vids()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|day| day.year)
+                .filter(|year| *year < self.today.year)
+                .collect();
+            for year in years {
+                m.make_year_review_if_missing(year.into());
+            }
         }
 
-        info!("Done backfilling movies");
+        // Same for months.
+        if self.config.month_montage.is_some() {
+            let this_month = (self.today.year, self.today.month);
+            let months: BTreeSet<(u16, u8)> = self
+                .discover_vids()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|day| (day.year, day.month))
+                .filter(|month| *month < this_month)
+                .collect();
+            for (year, month) in months {
+                m.make_month_montage_if_missing(year.into(), month.into());
+            }
+        }
+
+        DirManager::expire_shots(&self.config);
+        DirManager::expire_share_copies(&self.config);
+
+        Uploader::new(&self.config).upload_pending(
+            Path::new(&self.config.vid_output_dir),
+            &self.config.uploaded_video_name(),
+        );
     }
 
-    fn discover_vids(&self) -> Result<HashSet<Discovered>, Error> {
-        let mut discovered = HashSet::new();
+    /// Makes the movie for `day` and says how big it came out, None if there wasn't enough to
+    /// make one. Otherwise says why it couldn't, even if it panicked.
+    fn make_movie(
+        m: &MovieMaker,
+        day: &Discovered,
+        root_shot_dir: &Path,
+    ) -> Result<Option<u64>, String> {
+        let made = panic::catch_unwind(AssertUnwindSafe(|| {
+            m.make_movie_from(&day.to_shot_dir_in(root_shot_dir))
+        }));
+        match made {
+            Ok(Ok(movie)) => Ok(Some(movie.metadata().map(|m| m.len()).unwrap_or_default())),
+            Ok(Err(e @ MovieError::TooFewFrames { .. })) => {
+                info!("Skipping {day}, {e}");
+                Ok(None)
+            }
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(panicked) => Err(panic_message(panicked.as_ref())),
+        }
+    }
 
-        let video_glob = PathBuf::from(&self.config.vid_output_dir).join(format!(
-            "ompd-[0-9][0-9][0-9][0-9]-[0-9][0-9]-[0-9][0-9].{}",
-            self.config.video_type
-        ));
-        let ok_matches = glob(video_glob.to_str().unwrap())
-            .unwrap()
+    /// With backfill_when_idle_secs, waits until nobody's touched the computer for that long.
+    fn wait_for_idle(&self) {
+        let Some(idle_secs) = self.config.backfill_when_idle_secs else {
+            return;
+        };
+        let wanted = Duration::from_secs(idle_secs);
+        let mut waited = false;
+        loop {
+            match capturer::idle_time() {
+                Ok(idle_for) if idle_for < wanted => {
+                    if !waited {
+                        info!("Someone's using the computer, holding off on backfilling");
+                        waited = true;
+                    }
+                    thread::sleep(IDLE_CHECK_INTERVAL.min(wanted - idle_for));
+                }
+                Ok(_) => {
+                    if waited {
+                        info!("Nobody's around anymore, back to backfilling");
+                    }
+                    return;
+                }
+                Err(e) => {
+                    debug!("Couldn't tell whether anyone's around, backfilling anyway: {e:?}");
+                    return;
+                }
+            }
+        }
+    }
+
+    fn failed_days_path(&self) -> PathBuf {
+        Path::new(&self.config.shot_output_dir).join(FAILED_DAYS_FILE)
+    }
+
+    fn queue_path(&self) -> PathBuf {
+        Path::new(&self.config.shot_output_dir).join(QUEUE_FILE)
+    }
+
+    /// What's saved at `path`, or the default if there's nothing there we can make sense of.
+    fn load_json<T: DeserializeOwned + Default>(&self, path: &Path) -> T {
+        let Ok(raw) = std::fs::read(path) else {
+            return T::default();
+        };
+        serde_json::from_slice(&raw).unwrap_or_else(|e| {
+            warn!("Couldn't read {path:?}, starting over: {e}");
+            T::default()
+        })
+    }
+
+    fn save_json<T: Serialize>(&self, path: &Path, value: &T) {
+        let saved = serde_json::to_vec_pretty(value)
+            .map_err(Error::from)
+            .and_then(|raw| Ok(DirManager::write_atomically(path, &raw)?));
+        if let Err(e) = saved {
+            warn!("Couldn't save {path:?}: {e:?}");
+        }
+    }
+
+    fn starting_day(day: &str) {
+        if let Some(progress) = PROGRESS.lock().unwrap().as_mut() {
+            progress.working_on.push(day.to_string());
+        }
+    }
+
+    /// Counts `day` as done, and guesses how much longer the rest will take going by how long
+    /// it's been since `started`.
+    fn finished_day(day: &str, started: Instant) {
+        let mut progress = PROGRESS.lock().unwrap();
+        let Some(progress) = progress.as_mut() else {
+            return;
+        };
+        progress.working_on.retain(|working_on| working_on != day);
+        progress.days_done += 1;
+
+        let remaining = progress.days_total - progress.days_done;
+        let eta = started
+            .elapsed()
+            .mul_f64(remaining as f64 / progress.days_done as f64);
+        progress.eta = Some(eta);
+        if remaining > 0 {
+            info!(
+                "Backfilled day {} of {}, ~{} remaining",
+                progress.days_done,
+                progress.days_total,
+                rough_duration(eta)
+            );
+        }
+    }
+
+    /// Logs what run_for_host would make movies of, without making any.
+    fn dry_run(&self, m: &MovieMaker, to_process: &HashSet<Discovered>) {
+        let root_shot_dir = PathBuf::from(&self.config.shot_output_dir);
+        let mut days: Vec<&Discovered> = to_process.iter().collect();
+        days.sort();
+
+        let (mut movies, mut total_frames, mut total_secs) = (0, 0, 0.0);
+        for day in &days {
+            let frames = match DirManager::count_frames(&day.to_shot_dir_in(&root_shot_dir)) {
+                Ok((frames, _)) => frames,
+                Err(e) => {
+                    warn!("Would make a movie for {day}, but couldn't count its frames: {e:?}");
+                    continue;
+                }
+            };
+            if frames < self.config.min_frames_for_video {
+                info!("Would skip {day}, it only has {frames} frames");
+                continue;
+            }
+            let secs = m.movie_seconds(frames as usize);
+            info!("Would make a movie for {day}: {frames} frames, about {secs:.0}s long");
+            movies += 1;
+            total_frames += frames;
+            total_secs += secs;
+        }
+
+        let each_mb = match self.config.video_target_mb {
+            Some(target_mb) => Some((target_mb, "video_target_mb".to_string())),
+            None => {
+                let made: Vec<u64> = self
+                    .discover_vids()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(Discovered::to_date)
+                    .filter_map(|date| {
+                        let video = PathBuf::from(&self.config.vid_output_dir)
+                            .join(self.config.video_name().for_day(date));
+                        video.metadata().ok().map(|m| m.len())
+                    })
+                    .collect();
+                (!made.is_empty()).then(|| {
+                    let mb = made.iter().sum::<u64>() as f64 / made.len() as f64 / 1_000_000.0;
+                    (mb, format!("the {} already made", made.len()))
+                })
+            }
+        };
+        info!(
+            "Would make {} movies out of {total_frames} frames, about {:.1} hours of movie",
+            movies,
+            total_secs / 3600.0
+        );
+        if let Some((mb, going_by)) = each_mb {
+            info!(
+                "That's around {:.0} MB, going by {going_by}",
+                mb * movies as f64
+            );
+        }
+    }
+
+    /// Days whose movies weren't made with `current`, and still have shots to make them again.
+    fn outdated_videos(&self, current: &VideoSettings) -> Result<HashSet<Discovered>, Error> {
+        let root_shot_dir = PathBuf::from(&self.config.shot_output_dir);
+        let index = DayIndex::open(&root_shot_dir)?;
+
+        let mut outdated = HashSet::new();
+        for (key, settings) in index.days_with_video()? {
+            let made_with = settings.and_then(|s| serde_json::from_str::<VideoSettings>(&s).ok());
+            if made_with.is_some_and(|made_with| made_with.same_output_as(current)) {
+                continue;
+            }
+            let Some(day) = Discovered::from_key(&key) else {
+                continue;
+            };
+            let day_dir = day.to_shot_dir_in(&root_shot_dir);
+            if !day_dir.is_dir() && !DirManager::archive_path_for(&day_dir).exists() {
+                continue;
+            }
+            info!("The movie for {day} was made with different settings, making it again");
+            outdated.insert(day);
+        }
+        Ok(outdated)
+    }
+
+    fn days_from_index(&self) -> Result<HashSet<Discovered>, Error> {
+        let index = DayIndex::open(Path::new(&self.config.shot_output_dir))?;
+        if !index.is_seeded()? {
+            info!("Filling in the day index from what's on disk");
+            self.seed_index(&index)?;
+            index.mark_seeded()?;
+        } else {
+            self.reconcile_index(&index)?;
+        }
+
+        Ok(index
+            .days_without_video(self.config.min_frames_for_video)?
+            .iter()
+            .filter_map(|day| Discovered::from_key(day))
+            .collect())
+    }
+
+    fn seed_index(&self, index: &DayIndex) -> Result<(), Error> {
+        let root_shot_dir = PathBuf::from(&self.config.shot_output_dir);
+        for day in self.discover_shots()? {
+            let (frames, bytes) = DirManager::count_frames(&day.to_shot_dir_in(&root_shot_dir))?;
+            index.set_day(&day.to_string(), frames, bytes)?;
+        }
+
+        for (day, video) in self.discover_vid_paths()? {
+            index.mark_video(&day.to_string(), &video)?;
+        }
+
+        Ok(())
+    }
+
+    /*
+    The index only hears about what ompd does, so catch it up on anything done behind our back:
+    movies deleted or dropped in, shots copied over, archives restored, days cleaned out. Going by
+    names on disk only, so it's cheap enough to do on every run.
+    */
+    fn reconcile_index(&self, index: &DayIndex) -> Result<(), Error> {
+        let root_shot_dir = PathBuf::from(&self.config.shot_output_dir);
+        let shots = self.discover_shots()?;
+        let videos = self.discover_vid_paths()?;
+        let indexed: HashSet<Discovered> = index
+            .days()?
+            .iter()
+            .filter_map(|day| Discovered::from_key(day))
+            .collect();
+        let indexed_videos: HashSet<Discovered> = index
+            .days_with_video()?
+            .iter()
+            .filter_map(|(day, _)| Discovered::from_key(day))
+            .collect();
+
+        for day in shots.difference(&indexed) {
+            debug!("Shots for {day} turned up, adding them to the day index");
+            let (frames, bytes) = DirManager::count_frames(&day.to_shot_dir_in(&root_shot_dir))?;
+            index.set_day(&day.to_string(), frames, bytes)?;
+        }
+        // Whatever's left of them, there's nothing to make a movie out of anymore.
+        for day in indexed.difference(&shots) {
+            index.set_day(&day.to_string(), 0, 0)?;
+        }
+
+        for (day, video) in &videos {
+            if !indexed_videos.contains(day) {
+                debug!("Found a movie for {day} the day index didn't know about");
+                index.mark_video(&day.to_string(), video)?;
+            }
+        }
+        for day in indexed_videos
+            .iter()
+            .filter(|day| !videos.contains_key(day))
+        {
+            info!("The movie for {day} is gone, it'll be made again");
+            index.clear_video(&day.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    fn days_from_disk(&self) -> Result<HashSet<Discovered>, Error> {
+        let vid_coverage = self.discover_vids()?;
+        let shot_coverage = self.discover_shots()?;
+
+        Ok(shot_coverage.difference(&vid_coverage).cloned().collect())
+    }
+
+    /// Days with a movie that never got finished: a partial one left behind by a crash, or an
+    /// empty one. Gets rid of them so they're made again from scratch.
+    fn failed_attempts(&self) -> HashSet<Discovered> {
+        let mut failed = HashSet::new();
+        let vid_dir = PathBuf::from(&self.config.vid_output_dir);
+        let root_shot_dir = PathBuf::from(&self.config.shot_output_dir);
+
+        let video_name = self.config.video_name();
+        let video_glob = vid_dir.join(video_name.glob());
+        let partial_glob = DirManager::partial_path(&video_glob);
+        let candidates = [video_glob, partial_glob]
+            .into_iter()
+            .flat_map(|pattern| glob(pattern.to_str().unwrap()).unwrap())
             .filter_map(Result::ok);
 
-        for entry in ok_matches {
-            if !entry.is_file() {
-                info!("Found {entry:?} which apparently isn't a file, skipping");
+        for video in candidates {
+            let name = video.file_name().unwrap().to_string_lossy().to_string();
+            let is_partial = name.starts_with('.');
+            if !is_partial && video.metadata().is_ok_and(|m| m.len() > 0) {
                 continue;
             }
 
-            let file_name = entry.file_stem().unwrap().to_string_lossy();
-            let file_parts: Vec<&str> = file_name.split('-').collect();
+            let Some(day) = video_name
+                .parse(name.strip_prefix('.').unwrap_or(&name))
+                .map(Discovered::from_date)
+            else {
+                continue;
+            };
 
-            discovered.insert(Discovered {
-                // Remember that the first bit is "ompd"
-                year: file_parts[1].parse::<u16>().unwrap(),
-                month: file_parts[2].parse::<u8>().unwrap(),
-                day: file_parts[3].parse::<u8>().unwrap(),
-            });
+            // Whoever holds the day is still working on it.
+            match DayLock::try_acquire(&day.to_shot_dir_in(&root_shot_dir)) {
+                Ok(Some(_lock)) => {}
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Couldn't check whether {day} is being worked on: {e:?}");
+                    continue;
+                }
+            }
+
+            if self.config.backfill_dry_run {
+                info!("Found an unfinished movie for {day} at {video:?}, would make it again");
+                failed.insert(day);
+                continue;
+            }
+
+            info!("Found an unfinished movie for {day} at {video:?}, making it again");
+            if let Err(e) = std::fs::remove_file(&video) {
+                warn!("Couldn't remove {video:?}: {e}");
+                continue;
+            }
+
+            let cleared = DayIndex::open(&root_shot_dir)
+                .and_then(|index| index.clear_video(&day.to_string()));
+            if let Err(e) = cleared {
+                warn!("Couldn't forget about the movie for {day} in the day index: {e:?}");
+            }
+            failed.insert(day);
+        }
+
+        failed
+    }
+
+    fn discover_vids(&self) -> Result<HashSet<Discovered>, Error> {
+        Ok(self.discover_vid_paths()?.into_keys().collect())
+    }
+
+    /// Every day's movie, whatever container it's in. The one video_type says wins if a day has
+    /// more than one.
+    fn discover_vid_paths(&self) -> Result<BTreeMap<Discovered, PathBuf>, Error> {
+        let mut discovered = BTreeMap::new();
+
+        let video_type = self.config.video_type.as_str();
+        let video_types = VIDEO_TYPES.iter().filter(|ext| **ext != video_type);
+        for ext in std::iter::once(&video_type).chain(video_types) {
+            let video_name = self.config.video_name().with_ext(ext);
+            let video_glob = PathBuf::from(&self.config.vid_output_dir).join(video_name.glob());
+            let ok_matches = glob(video_glob.to_str().unwrap())?.filter_map(Result::ok);
+
+            for entry in ok_matches {
+                if !entry.is_file() {
+                    info!("Found {entry:?} which apparently isn't a file, skipping");
+                    continue;
+                }
+
+                let file_name = entry.file_name().unwrap().to_string_lossy().to_string();
+                match video_name.parse(&file_name) {
+                    Some(day) => {
+                        discovered
+                            .entry(Discovered::from_date(day))
+                            .or_insert(entry);
+                    }
+                    None => info!("{entry:?} isn't a day's movie, skipping"),
+                }
+            }
         }
 
         Ok(discovered)
@@ -146,6 +824,34 @@ impl BackFiller {
             });
         }
 
+        // Days that got packed up still have shots, they're just in one file now.
+        let archive_glob = PathBuf::from(&self.config.shot_output_dir)
+            .join("[0-9][0-9][0-9][0-9]")
+            .join("[0-1][0-9]")
+            .join(format!(
+                "[0-9][0-9][0-9][0-9]-[0-1][0-9]-[0-3][0-9].{ARCHIVE_FILE_EXTENSION}"
+            ));
+
+        for entry in glob(archive_glob.to_str().unwrap())
+            .unwrap()
+            .filter_map(Result::ok)
+        {
+            let file_name = entry.file_name().unwrap().to_string_lossy();
+            if let Some(day) = file_name
+                .strip_suffix(&format!(".{ARCHIVE_FILE_EXTENSION}"))
+                .and_then(Discovered::from_key)
+            {
+                discovered.insert(day);
+            }
+        }
+
         Ok(discovered)
     }
 }
+
+/// Whether `name` under the shared shot dir could be another machine's shots, rather than a year
+/// of days from before there were hosts, or something hidden like .DS_Store or .Trash.
+fn is_host_dir(name: &str) -> bool {
+    let looks_like_year = name.len() == 4 && name.bytes().all(|b| b.is_ascii_digit());
+    !looks_like_year && !name.starts_with('.')
+}
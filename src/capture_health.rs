@@ -0,0 +1,64 @@
+use log::{error, info, warn};
+use std::time::Duration;
+
+use crate::config::CaptureRetry;
+use crate::notify;
+
+/*
+A GPU driver reset or a broken display server can leave every capture failing, and without this we'd
+quietly log the same error every interval forever. Back off so we're not hammering something that's
+broken, and shout once it's clearly not a blip.
+*/
+pub struct CaptureHealth {
+    interval: Duration,
+    settings: CaptureRetry,
+    consecutive_failures: u32,
+    alerted: bool,
+}
+
+impl CaptureHealth {
+    pub fn new(interval: Duration, settings: &CaptureRetry) -> CaptureHealth {
+        CaptureHealth {
+            interval,
+            settings: settings.clone(),
+            consecutive_failures: 0,
+            alerted: false,
+        }
+    }
+
+    pub fn succeeded(&mut self) {
+        if self.consecutive_failures > 0 {
+            info!(
+                "Capture is working again after {} failures",
+                self.consecutive_failures
+            );
+        }
+        self.consecutive_failures = 0;
+        self.alerted = false;
+    }
+
+    /// Note a failed capture, and find out how long to wait before the next try.
+    pub fn failed(&mut self, e: &anyhow::Error) -> Duration {
+        self.consecutive_failures += 1;
+        warn!(
+            "Capture failed ({} in a row): {e:?}",
+            self.consecutive_failures
+        );
+
+        if self.consecutive_failures >= self.settings.alert_after && !self.alerted {
+            self.alerted = true;
+            let message = format!(
+                "Screen capture has failed {} times in a row, last error: {e}",
+                self.consecutive_failures
+            );
+            error!("{message}");
+            if self.settings.notify {
+                notify::desktop("ompd isn't recording", &message);
+            }
+        }
+
+        let doublings = (self.consecutive_failures - 1).min(16);
+        let backoff = self.interval.saturating_mul(1 << doublings);
+        backoff.min(Duration::from_secs(self.settings.max_backoff_secs))
+    }
+}
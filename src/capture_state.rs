@@ -0,0 +1,28 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+pub const CAPTURE_STATE_FILE: &str = "capture-state.json";
+
+/// Where capture left off in a day's directory, so a restart can pick up without re-counting.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CaptureState {
+    pub next_frame: u32,
+    pub last_capture: DateTime<Local>,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub fn load(day_dir: &Path) -> Option<CaptureState> {
+    let raw = fs::read(day_dir.join(CAPTURE_STATE_FILE)).ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+pub fn save(day_dir: &Path, state: &CaptureState) -> Result<(), anyhow::Error> {
+    // Write then rename, so dying halfway through doesn't leave a state file we can't read.
+    let tmp_path = day_dir.join(format!("{CAPTURE_STATE_FILE}.tmp"));
+    fs::write(&tmp_path, serde_json::to_vec(state)?)?;
+    fs::rename(tmp_path, day_dir.join(CAPTURE_STATE_FILE))?;
+    Ok(())
+}
@@ -4,45 +4,368 @@ mod windows;
 #[cfg(not(target_os = "windows"))]
 mod not_windows;
 
-use anyhow::Error;
-use chrono::{DateTime, Datelike, Local};
-use image::io::Reader;
-use image::{ImageBuffer, Rgba};
-use log::{debug, error, info};
-use rusttype::{Font, Scale};
+#[cfg(target_os = "windows")]
+pub(crate) use windows::idle_time;
+#[cfg(target_os = "windows")]
+use windows::{foreground_app, foreground_window_title, on_battery, window_titles};
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) use not_windows::idle_time;
+#[cfg(not(target_os = "windows"))]
+use not_windows::{foreground_app, foreground_window_title, on_battery, window_titles};
+
+#[cfg(target_os = "linux")]
+mod x11;
+
+pub mod backend;
+pub mod card;
+mod pacer;
+mod redact;
+mod timestamp;
+mod tone_map;
+pub mod webcam;
+
+use anyhow::{Context, Error};
+use backend::CaptureBackend;
+use chrono::{DateTime, Local};
+use image::codecs::webp::{WebPEncoder, WebPQuality};
+use image::imageops::FilterType;
+use image::{imageops, DynamicImage, ImageFormat, ImageOutputFormat, Rgba, RgbaImage};
+use log::{debug, error, info, warn};
+use pacer::Pacer;
+use ravif::{Img, RGB8};
+use rusttype::Font;
 use screenshots::Screen;
+use std::fmt;
 use std::io::Cursor;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use symlink::symlink_file;
+use webcam::Webcam;
 
-#[cfg(target_os = "windows")]
-use windows::get_screenshot;
+use crate::capture_state;
+use crate::config::{
+    Config, FillerFrame, OnBattery, RedactRegion, TimestampOverlay, WebcamOverlay,
+};
+use crate::crypto::{FrameCipher, ENCRYPTED_FILE_EXTENSION};
+use crate::dir_manager::{Codec, DirManager};
+use crate::frame_metadata::{self, FrameMetadata};
+use crate::frame_writer::FrameWriter;
 
-#[cfg(not(target_os = "windows"))]
-use not_windows::get_screenshot;
+pub type FrameCounter = u32;
 
-use crate::dir_manager::DirManager;
+fn app_blacklist(config: &Config) -> Vec<String> {
+    config
+        .app_blacklist
+        .iter()
+        .map(|app| app.to_lowercase())
+        .collect()
+}
 
-pub type FrameCounter = u32;
+fn meeting_window_patterns(config: &Config) -> Vec<String> {
+    if !config.pause_during_meetings {
+        return Vec::new();
+    }
+    config
+        .meeting_window_patterns
+        .iter()
+        .map(|p| p.to_lowercase())
+        .collect()
+}
+
+fn start_webcam(config: &Config) -> Option<Webcam> {
+    let overlay = config.webcam_overlay.as_ref()?;
+    Some(Webcam::start(&config.ffmpeg, &overlay.device))
+}
+
+const POWER_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
 
 pub struct Capturer {
     sleep_interval: std::time::Duration,
+    pacer: Option<Pacer>,
     curr_frame: u32,
     shot_type: String,
+    shot_quality: u8,
+    shot_lossless: bool,
+    shot_speed: u8,
+    cipher: Option<FrameCipher>,
+    writer: FrameWriter,
+    backend: Box<dyn CaptureBackend>,
+    pause_when_idle: Option<std::time::Duration>,
+    idle_since: Option<DateTime<Local>>,
+    app_blacklist: Vec<String>,
+    app_blacklist_action: String,
+    meeting_window_patterns: Vec<String>,
+    redact_regions: Vec<RedactRegion>,
+    capture_scale: f32,
+    hdr_tone_map: bool,
+    all_monitors: bool,
+    /// The other monitors' frames to go along with the next stored one, left to right. None for
+    /// any that wouldn't capture.
+    other_monitors: Vec<Option<RgbaImage>>,
+    timestamp_overlay: Option<TimestampOverlay>,
+    font: Font<'static>,
+    filler_frame: FillerFrame,
+    ffmpeg: String,
+    webcam_overlay: Option<WebcamOverlay>,
+    webcam: Option<Webcam>,
+    filler_font: Font<'static>,
+    last_dimensions: Option<(u32, u32)>,
+    last_scale_factor: Option<f32>,
+    last_capture: Option<DateTime<Local>>,
+    on_battery: Option<OnBattery>,
+    /// When we last checked, and whether we were on battery then.
+    power: Option<(Instant, bool)>,
 }
 
+/// We didn't capture on purpose (locked, asleep, something private on screen), as opposed to
+/// capture being broken.
+#[derive(Debug)]
+pub struct Skipped(pub String);
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Skipped {}
+
 pub enum ChangeType {
     Nop,
     NewDay,
 }
 
+pub enum IdleState {
+    Active,
+    Idle,
+    Resumed(DateTime<Local>),
+}
+
+/// How big the primary screen is, if there's one to find.
+pub fn primary_screen_size() -> Option<(u32, u32)> {
+    let info = Capturer::rediscover_screen().ok()?.display_info;
+    Some((info.width, info.height))
+}
+
 impl Capturer {
-    pub fn new(sleep_interval: &std::time::Duration, shot_type: &str) -> Capturer {
-        Capturer {
-            sleep_interval: sleep_interval.to_owned(),
+    pub fn new(config: &Config) -> Result<Capturer, Error> {
+        let backend = backend::from_name(&config.capture_backend).unwrap_or_else(|e| {
+            warn!(
+                "Couldn't start '{}' capture backend, falling back to '{}': {e:?}",
+                config.capture_backend,
+                backend::DEFAULT_BACKEND
+            );
+            Box::new(backend::ScreenshotsBackend)
+        });
+        info!("Capturing with the '{}' backend", backend.name());
+
+        let cipher = if config.encrypt_shots {
+            let key_file = Path::new(&config.encryption_key_file);
+            let cipher = FrameCipher::from_key_file(key_file)
+                .with_context(|| format!("Couldn't load the shot encryption key {key_file:?}"))?;
+            Some(cipher)
+        } else {
+            None
+        };
+
+        Ok(Capturer {
+            sleep_interval: std::time::Duration::from_secs(config.interval),
+            pacer: config
+                .adaptive_interval
+                .as_ref()
+                .map(|a| Pacer::new(a, std::time::Duration::from_secs(config.interval))),
             curr_frame: 0,
-            shot_type: shot_type.to_string(),
+            shot_type: config.shot_type.clone(),
+            shot_quality: config.shot_quality,
+            shot_lossless: config.shot_lossless,
+            shot_speed: config.shot_speed,
+            cipher,
+            writer: FrameWriter::spawn(
+                PathBuf::from(&config.shot_output_dir),
+                config.shot_spool_dir.as_ref().map(PathBuf::from),
+            ),
+            backend,
+            pause_when_idle: config
+                .pause_when_idle_secs
+                .map(std::time::Duration::from_secs),
+            idle_since: None,
+            app_blacklist: app_blacklist(config),
+            app_blacklist_action: config.app_blacklist_action.clone(),
+            meeting_window_patterns: meeting_window_patterns(config),
+            redact_regions: config.redact_regions.clone(),
+            capture_scale: config.capture_scale,
+            hdr_tone_map: config.hdr_tone_map,
+            all_monitors: config.capture_monitors == "all",
+            other_monitors: Vec::new(),
+            timestamp_overlay: config.timestamp_overlay.clone(),
+            font: card::default_font(),
+            filler_frame: config.filler_frame.clone(),
+            ffmpeg: config.ffmpeg.clone(),
+            webcam_overlay: config.webcam_overlay.clone(),
+            webcam: start_webcam(config),
+            filler_font: card::font(&config.filler_frame),
+            last_dimensions: None,
+            last_scale_factor: None,
+            last_capture: None,
+            on_battery: config.on_battery.clone(),
+            power: None,
+        })
+    }
+
+    /// Picks up the settings that changed from `old` to `new` that can change between frames.
+    pub fn reconfigure(&mut self, old: &Config, new: &Config) {
+        self.sleep_interval = std::time::Duration::from_secs(new.interval);
+        // Starting the pacer over would lose how busy it thinks the screen is.
+        if new.interval != old.interval || new.adaptive_interval != old.adaptive_interval {
+            self.pacer = new
+                .adaptive_interval
+                .as_ref()
+                .map(|a| Pacer::new(a, self.sleep_interval));
+        }
+        self.shot_quality = new.shot_quality;
+        self.shot_lossless = new.shot_lossless;
+        self.shot_speed = new.shot_speed;
+        self.pause_when_idle = new.pause_when_idle_secs.map(std::time::Duration::from_secs);
+        self.app_blacklist = app_blacklist(new);
+        self.app_blacklist_action = new.app_blacklist_action.clone();
+        self.meeting_window_patterns = meeting_window_patterns(new);
+        self.redact_regions = new.redact_regions.clone();
+        self.hdr_tone_map = new.hdr_tone_map;
+        self.all_monitors = new.capture_monitors == "all";
+        self.timestamp_overlay = new.timestamp_overlay.clone();
+        self.filler_frame = new.filler_frame.clone();
+        self.filler_font = card::font(&new.filler_frame);
+        let device = |overlay: &Option<WebcamOverlay>| overlay.as_ref().map(|o| o.device.clone());
+        if self.ffmpeg != new.ffmpeg || device(&self.webcam_overlay) != device(&new.webcam_overlay)
+        {
+            // Dropping the old one closes its ffmpeg.
+            self.webcam = None;
+            self.webcam = start_webcam(new);
+        }
+        self.ffmpeg = new.ffmpeg.clone();
+        self.webcam_overlay = new.webcam_overlay.clone();
+        self.on_battery = new.on_battery.clone();
+    }
+
+    /// How long to wait after a stored frame before capturing the next one.
+    pub fn next_interval(&self) -> std::time::Duration {
+        let interval = match &self.pacer {
+            Some(pacer) => pacer.current(),
+            None => self.sleep_interval,
+        };
+
+        match self.battery_policy().and_then(|b| b.interval_secs) {
+            Some(secs) => interval.max(std::time::Duration::from_secs(secs)),
+            None => interval,
+        }
+    }
+
+    /// How to behave on battery, if we're on battery right now and care.
+    fn battery_policy(&self) -> Option<&OnBattery> {
+        self.on_battery
+            .as_ref()
+            .filter(|_| self.power.as_ref().is_some_and(|(_, battery)| *battery))
+    }
+
+    fn refresh_power_state(&mut self) {
+        if self.on_battery.is_none() {
+            return;
+        }
+
+        // Checking is a process spawn on some platforms, and nobody unplugs that often.
+        let fresh = self
+            .power
+            .is_some_and(|(checked, _)| checked.elapsed() < POWER_CHECK_INTERVAL);
+        if fresh {
+            return;
+        }
+
+        let battery = on_battery().unwrap_or_else(|e| {
+            debug!("Couldn't tell if we're on battery, assuming not: {e:?}");
+            false
+        });
+        if self.power.map(|(_, was)| was) != Some(battery) {
+            info!(
+                "Running on {}",
+                if battery { "battery" } else { "AC power" }
+            );
+        }
+        self.power = Some((Instant::now(), battery));
+    }
+
+    fn shot_quality(&self) -> u8 {
+        self.battery_policy()
+            .and_then(|b| b.shot_quality)
+            .unwrap_or(self.shot_quality)
+    }
+
+    pub fn idle_state(&mut self) -> IdleState {
+        let Some(threshold) = self.pause_when_idle else {
+            return IdleState::Active;
+        };
+
+        let idle_for = match idle_time() {
+            Ok(d) => d,
+            Err(e) => {
+                debug!("Couldn't figure out how long we've been idle, assuming we aren't: {e:?}");
+                return IdleState::Active;
+            }
+        };
+
+        if idle_for >= threshold {
+            if self.idle_since.is_none() {
+                info!("No input for {idle_for:?}, pausing capture until there is");
+                self.idle_since =
+                    Some(Local::now() - chrono::Duration::from_std(idle_for).unwrap());
+            }
+            return IdleState::Idle;
+        }
+
+        match self.idle_since.take() {
+            Some(since) => {
+                info!("Welcome back! Resuming capture");
+                IdleState::Resumed(since)
+            }
+            None => IdleState::Active,
+        }
+    }
+
+    pub fn deal_with_idle(
+        &mut self,
+        idle_secs: u64,
+        dir_manager: &DirManager,
+    ) -> Result<(), Error> {
+        // Unlike a blackout we don't want to pad out the video to be true-to-time, the whole point
+        // is to skip the boring bits. So just the one frame to mark the spot.
+        let text = Self::filler_text(&self.filler_frame.idle_text, idle_secs);
+        self.write_marker_frame(&text, Some(idle_secs), dir_manager)
+    }
+
+    pub fn deal_with_disk_full(&mut self, dir_manager: &DirManager) -> Result<(), Error> {
+        let text = self.filler_frame.disk_full_text.clone();
+        self.write_marker_frame(&text, None, dir_manager)
+    }
+
+    /// A single filler frame with some text on it, to mark where something happened.
+    fn write_marker_frame(
+        &mut self,
+        text: &str,
+        away_secs: Option<u64>,
+        dir_manager: &DirManager,
+    ) -> Result<(), Error> {
+        let filler_frame_path = self.claim_free_frame(dir_manager);
+
+        info!("Creating \"{text}\" frame @ {filler_frame_path:?}");
+        let (width, height) = self.filler_dimensions(dir_manager);
+        let filler_frame = self.encode_frame(self.create_filler_frame(text, width, height))?;
+        self.write_frame(&filler_frame_path, filler_frame)?;
+        if let Some(away_secs) = away_secs {
+            self.record_away(away_secs, (width, height), dir_manager);
         }
+
+        self.curr_frame += 1;
+        Ok(())
     }
 
     pub fn deal_with_change(
@@ -51,7 +374,7 @@ impl Capturer {
         prev_time: &DateTime<Local>,
         curr_time: &DateTime<Local>,
     ) -> Result<ChangeType, Error> {
-        if curr_time.ordinal() != prev_time.ordinal() {
+        if dir_manager.day_of(curr_time) != dir_manager.day_of(prev_time) {
             // Obviously this could be a new month, or even new year. Whatever, we'll be fine either way!
             // The point is it simply not the same day as it was last time we did something.
             Ok(ChangeType::NewDay)
@@ -63,6 +386,17 @@ impl Capturer {
     }
 
     pub fn discover_current_frame(&mut self, dir_manager: &mut DirManager) {
+        if let Some(state) = capture_state::load(dir_manager.current_shot_dir()) {
+            info!(
+                "Picking up from frame {}, last captured at {}",
+                state.next_frame, state.last_capture
+            );
+            self.curr_frame = state.next_frame;
+            self.last_dimensions = Some((state.width, state.height));
+            self.last_capture = Some(state.last_capture);
+            return;
+        }
+
         let curr_frame = self.get_curr_frame(dir_manager);
         match curr_frame {
             Ok(new_curr_frame) => {
@@ -75,42 +409,468 @@ impl Capturer {
         }
     }
 
+    /*
+    Counting frames on startup can come up short after some crash/restart sequences (a hole in the
+    middle, say), which would have us overwrite an existing frame. If the frame we're about to write
+    is already there, skip past everything on disk instead.
+    */
+    fn claim_free_frame(&mut self, dir_manager: &DirManager) -> PathBuf {
+        let dir = dir_manager.current_shot_dir();
+        let path = dir.join(self.frame_file_name(self.curr_frame));
+        if !path.exists() {
+            return path;
+        }
+
+        let next_free = self.highest_frame_in(dir).map_or(0, |f| f + 1);
+        let next_free = next_free.max(self.curr_frame + 1);
+        warn!(
+            "Frame {} already exists in {dir:?}, moving on to frame {next_free}",
+            self.curr_frame
+        );
+        self.curr_frame = next_free;
+
+        dir.join(self.frame_file_name(self.curr_frame))
+    }
+
+    fn highest_frame_in(&self, dir: &Path) -> Option<FrameCounter> {
+        let frame_suffix = format!(".{}", self.frame_extension());
+
+        std::fs::read_dir(dir)
+            .ok()?
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                // Compressed frames still count, they'll be back when we make the movie.
+                let name = entry.file_name().to_string_lossy().to_string();
+                let name = Codec::strip_extension(&name);
+                name.strip_suffix(&frame_suffix)?
+                    .parse::<FrameCounter>()
+                    .ok()
+            })
+            .max()
+    }
+
+    /// When the last frame was stored, if we picked up where a previous run left off.
+    pub fn last_capture(&self) -> Option<DateTime<Local>> {
+        self.last_capture
+    }
+
     pub fn set_current_frame(&mut self, new_curr_frame: u32) {
         self.curr_frame = new_curr_frame;
     }
 
-    pub fn capture_screen(&self) -> Result<screenshots::Image, anyhow::Error> {
+    pub fn capture_screen(&mut self) -> Result<RgbaImage, anyhow::Error> {
         // At any given point we may not have the same primary screen as we
         // did. For example, we may have switched from an external display to a
         // laptop primary display. So, reacquire the screen with (0, 0) every time.
-        get_screenshot(Screen::from_point(0, 0).expect("Couldn't get screen at origin?!"))
+        let screen = Self::current_screen()?;
+
+        self.refresh_power_state();
+        if self.battery_policy().is_some_and(|b| b.pause) {
+            return Err(Skipped("On battery, not capturing".to_string()).into());
+        }
+
+        let mut primary_id = screen.display_info.id;
+        let blacked_out = self.blacklisted_app_in_foreground()?;
+        let img = if let Some(app) = &blacked_out {
+            if self.app_blacklist_action != "blackout" {
+                return Err(
+                    Skipped(format!("{app} is in the foreground, not capturing it")).into(),
+                );
+            }
+
+            debug!("{app} is in the foreground, blacking out this frame");
+            let info = screen.display_info;
+            self.last_scale_factor = Some(info.scale_factor);
+            RgbaImage::from_pixel(
+                (info.width as f32 * info.scale_factor) as u32,
+                (info.height as f32 * info.scale_factor) as u32,
+                Rgba([0, 0, 0, 255]),
+            )
+        } else {
+            if let Some(title) = self.meeting_window() {
+                return Err(Skipped(format!("Looks like a meeting is going on ({title})")).into());
+            }
+
+            self.backend
+                .check_screen_state()
+                .map_err(|e| Skipped(e.to_string()))?;
+            let (img, screen) = match self.backend.capture(&screen) {
+                Ok(img) => (img, screen),
+                Err(e) => {
+                    // Docking, undocking, or a resolution change can all leave us holding a
+                    // screen that doesn't exist anymore. Take a fresh look and try once more.
+                    info!("Capture failed ({e:?}), re-checking screens and trying again");
+                    let screen = Self::rediscover_screen()?;
+                    (self.backend.capture(&screen)?, screen)
+                }
+            };
+            primary_id = screen.display_info.id;
+            self.last_scale_factor = Some(screen.display_info.scale_factor);
+            let mut img = Self::to_physical_pixels(img, &screen);
+            if self.hdr_tone_map {
+                tone_map::tone_map(&mut img);
+            }
+            redact::redact(&mut img, &self.redact_regions);
+            img
+        };
+
+        self.other_monitors = match self.all_monitors {
+            true => self.capture_other_monitors(primary_id, blacked_out.is_some()),
+            false => Vec::new(),
+        };
+
+        let mut img = self.scale(img);
+        if let (Some(overlay), Some(webcam)) = (&self.webcam_overlay, &self.webcam) {
+            match webcam.latest() {
+                Ok(cam) => webcam::picture_in_picture(&mut img, &cam, overlay),
+                Err(e) => debug!("Skipping webcam this frame: {e:?}"),
+            }
+        }
+        if let Some(overlay) = &self.timestamp_overlay {
+            timestamp::burn_in(&mut img, &self.font, overlay, &Local::now());
+        }
+
+        Ok(img)
+    }
+
+    /// Every monitor but the one with `primary_id`, left to right, tone mapped and scaled like the
+    /// main one. Redacting, the webcam, and the timestamp only go on the main one.
+    fn capture_other_monitors(
+        &mut self,
+        primary_id: u32,
+        blacked_out: bool,
+    ) -> Vec<Option<RgbaImage>> {
+        let mut others: Vec<Screen> = match Screen::all() {
+            Ok(screens) => screens
+                .into_iter()
+                .filter(|s| s.display_info.id != primary_id)
+                .collect(),
+            Err(e) => {
+                debug!("Couldn't look for other monitors: {e:?}");
+                return Vec::new();
+            }
+        };
+        others.sort_by_key(|s| (s.display_info.x, s.display_info.y));
+
+        let mut captured = Vec::new();
+        for screen in &others {
+            let info = screen.display_info;
+            let img = if blacked_out {
+                RgbaImage::from_pixel(
+                    (info.width as f32 * info.scale_factor) as u32,
+                    (info.height as f32 * info.scale_factor) as u32,
+                    Rgba([0, 0, 0, 255]),
+                )
+            } else {
+                match self.backend.capture(screen) {
+                    Ok(img) => {
+                        let mut img = Self::to_physical_pixels(img, screen);
+                        if self.hdr_tone_map {
+                            tone_map::tone_map(&mut img);
+                        }
+                        img
+                    }
+                    Err(e) => {
+                        debug!("Couldn't capture monitor {}, skipping it: {e:?}", info.id);
+                        captured.push(None);
+                        continue;
+                    }
+                }
+            };
+            captured.push(Some(self.scale(img)));
+        }
+        captured
     }
 
-    pub fn store(&mut self, capture_result: screenshots::Image, dir: &Path) {
-        debug!("Going to store a screenshots::Image");
-        let filename = format!("{:05}.{}", self.curr_frame, self.shot_type);
-        let filepath = dir.join(filename);
+    fn current_screen() -> Result<Screen, Error> {
+        Screen::from_point(0, 0).or_else(|e| {
+            debug!("Couldn't get screen at origin ({e:?}), looking around");
+            Self::rediscover_screen()
+        })
+    }
+
+    fn rediscover_screen() -> Result<Screen, Error> {
+        let screens = Screen::all()?;
+        debug!("Found screens: {screens:?}");
 
-        assert!(!filepath.exists(), "I'm trying to overwrite myself!");
+        screens
+            .iter()
+            .find(|s| s.display_info.is_primary)
+            .or_else(|| screens.first())
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Couldn't find any screens at all?!"))
+    }
 
-        // We know that the screenshots::Image is a PNG, that's hard-coded.
-        // So, it's safe to decode it as such.
-        let image_reader = Reader::with_format(
-            Cursor::new(capture_result.buffer()),
-            image::ImageFormat::Png,
+    /*
+    Depending on the backend and the platform, a HiDPI screen can come back in either logical or
+    physical pixels. Always store physical, so frames from the same screen are all the same size.
+    */
+    fn to_physical_pixels(img: RgbaImage, screen: &Screen) -> RgbaImage {
+        let info = screen.display_info;
+        let width = (info.width as f32 * info.scale_factor) as u32;
+        let height = (info.height as f32 * info.scale_factor) as u32;
+
+        if img.dimensions() == (width, height) || width == 0 || height == 0 {
+            return img;
+        }
+
+        debug!(
+            "Captured {}x{}, scaling to {width}x{height} physical pixels",
+            img.width(),
+            img.height()
         );
-        debug!("Made a reader");
+        imageops::resize(&img, width, height, FilterType::Triangle)
+    }
+
+    fn scale(&self, img: RgbaImage) -> RgbaImage {
+        if self.capture_scale >= 1.0 {
+            return img;
+        }
+
+        let (width, height) = img.dimensions();
+        let new_width = ((width as f32 * self.capture_scale) as u32).max(1);
+        let new_height = ((height as f32 * self.capture_scale) as u32).max(1);
+        imageops::resize(&img, new_width, new_height, FilterType::Triangle)
+    }
+
+    fn filler_dimensions(&self, dir_manager: &DirManager) -> (u32, u32) {
+        // Match whatever we've been capturing, so ffmpeg doesn't have to stretch anything. If we
+        // just started up, go with what we've mostly been capturing today, or failing that what
+        // we'd capture right now.
+        if let Some(dimensions) = self.last_dimensions {
+            return dimensions;
+        }
+
+        if let Some(dimensions) =
+            frame_metadata::most_common_dimensions(dir_manager.current_shot_dir())
+        {
+            return dimensions;
+        }
+
+        match Self::current_screen() {
+            Ok(screen) => {
+                let info = screen.display_info;
+                let scale = info.scale_factor * self.capture_scale;
+                (
+                    ((info.width as f32 * scale) as u32).max(1),
+                    ((info.height as f32 * scale) as u32).max(1),
+                )
+            }
+            Err(e) => {
+                debug!("Couldn't size filler frame off the screen: {e:?}");
+                (860, 360)
+            }
+        }
+    }
+
+    fn blacklisted_app_in_foreground(&self) -> Result<Option<String>, Error> {
+        if self.app_blacklist.is_empty() {
+            return Ok(None);
+        }
+
+        // If we can't tell what's in front, err on the side of not capturing something private.
+        let app = foreground_app().map_err(|e| {
+            Skipped(format!(
+                "Couldn't find the foreground app, playing it safe: {e}"
+            ))
+        })?;
+
+        Ok(self.is_blacklisted(&app).then_some(app))
+    }
+
+    /// Title of any window that looks like a meeting or a screen share.
+    fn meeting_window(&self) -> Option<String> {
+        if self.meeting_window_patterns.is_empty() {
+            return None;
+        }
+
+        // Unlike the blacklist, not being able to look isn't a reason to stop capturing entirely.
+        let titles = match window_titles() {
+            Ok(t) => t,
+            Err(e) => {
+                debug!("Couldn't list windows to look for meetings: {e:?}");
+                return None;
+            }
+        };
+
+        titles.into_iter().find(|title| {
+            let title_lower = title.to_lowercase();
+            self.meeting_window_patterns
+                .iter()
+                .any(|pattern| title_lower.contains(pattern.as_str()))
+        })
+    }
+
+    fn is_blacklisted(&self, app: &str) -> bool {
+        let app_lower = app.to_lowercase();
+        self.app_blacklist
+            .iter()
+            .any(|blacklisted| app_lower.contains(blacklisted.as_str()))
+    }
 
-        let new_img = image_reader
-            .decode()
-            .expect("decoding shouldn't be able to fail at this point!");
-        debug!("Done decoding it");
+    pub fn store(&mut self, new_img: RgbaImage, dir_manager: &DirManager) {
+        debug!("Going to store a captured frame");
+        let (width, height) = new_img.dimensions();
+        match self.last_dimensions {
+            Some((last_width, last_height)) if (last_width, last_height) != (width, height) => {
+                info!("Screen changed from {last_width}x{last_height} to {width}x{height}");
+            }
+            _ => {}
+        }
+        self.last_dimensions = Some((width, height));
 
-        debug!("Writing out a file to {filepath:?}");
-        new_img.save(&filepath).expect("Couldn't save screenshot!");
+        if let Some(pacer) = &mut self.pacer {
+            pacer.observe(&new_img);
+        }
+
+        // One bad frame isn't worth giving up on capturing over.
+        let contents = match self.encode_frame(new_img).and_then(|e| self.seal(e)) {
+            Ok(contents) => contents,
+            Err(e) => {
+                warn!("Couldn't encode or encrypt this frame, skipping it: {e:?}");
+                self.other_monitors.clear();
+                return;
+            }
+        };
+        let filepath = self.claim_free_frame(dir_manager);
+
+        // A blacked out app's window title can be just as private as what's in the window.
+        let app = foreground_app().ok();
+        let window_title = match &app {
+            Some(app) if self.is_blacklisted(app) => None,
+            _ => foreground_window_title()
+                .ok()
+                .and_then(|title| self.seal_text(title)),
+        };
+
+        self.writer.write(
+            filepath,
+            contents,
+            dir_manager.get_current_shot_dir(),
+            FrameMetadata {
+                frame: self.curr_frame,
+                captured_at: Local::now(),
+                width,
+                height,
+                app,
+                window_title,
+                idle_secs: idle_time().ok().map(|d| d.as_secs()),
+                scale_factor: self.last_scale_factor,
+                away_secs: None,
+            },
+        );
+        self.store_other_monitors(dir_manager);
         self.curr_frame += 1;
     }
 
+    /// The other monitors' frames from the last capture, under the same frame number.
+    fn store_other_monitors(&mut self, dir_manager: &DirManager) {
+        let day_dir = dir_manager.current_shot_dir();
+        for (n, img) in std::mem::take(&mut self.other_monitors)
+            .into_iter()
+            .enumerate()
+        {
+            let Some(img) = img else {
+                continue;
+            };
+            let path =
+                DirManager::monitor_dir(day_dir, n + 1).join(self.frame_file_name(self.curr_frame));
+            match self
+                .encode_frame(img)
+                .and_then(|encoded| self.seal(encoded))
+            {
+                Ok(contents) => self.writer.write_monitor(path, contents),
+                Err(e) => warn!("Couldn't save {path:?}: {e:?}"),
+            }
+        }
+    }
+
+    /// Wait for every stored frame to actually be on disk.
+    pub fn flush(&self) {
+        self.writer.flush();
+    }
+
+    fn frame_extension(&self) -> String {
+        match self.cipher {
+            Some(_) => format!("{}.{ENCRYPTED_FILE_EXTENSION}", self.shot_type),
+            None => self.shot_type.clone(),
+        }
+    }
+
+    fn frame_file_name(&self, frame: FrameCounter) -> String {
+        format!("{frame:05}.{}", self.frame_extension())
+    }
+
+    fn seal(&self, encoded: Vec<u8>) -> Result<Vec<u8>, Error> {
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(&encoded),
+            None => Ok(encoded),
+        }
+    }
+
+    /// `text` as it should go next to the frames, None if it can't be kept safe.
+    fn seal_text(&self, text: String) -> Option<String> {
+        match &self.cipher {
+            Some(cipher) => cipher
+                .encrypt_text(&text)
+                .map_err(|e| warn!("Couldn't encrypt the window title, leaving it out: {e:?}"))
+                .ok(),
+            None => Some(text),
+        }
+    }
+
+    fn write_frame(&self, path: &Path, encoded: Vec<u8>) -> Result<(), Error> {
+        Ok(DirManager::write_atomically(path, &self.seal(encoded)?)?)
+    }
+
+    fn encode_frame(&self, img: RgbaImage) -> Result<Vec<u8>, Error> {
+        let mut encoded = Cursor::new(Vec::new());
+
+        if self.shot_type == "webp" {
+            let quality = if self.shot_lossless {
+                WebPQuality::lossless()
+            } else {
+                WebPQuality::lossy(self.shot_quality())
+            };
+            let (width, height) = img.dimensions();
+            WebPEncoder::new_with_quality(&mut encoded, quality).encode(
+                &img,
+                width,
+                height,
+                image::ColorType::Rgba8,
+            )?;
+            return Ok(encoded.into_inner());
+        }
+
+        if self.shot_type == "avif" {
+            let (width, height) = img.dimensions();
+            let pixels: Vec<RGB8> = img
+                .pixels()
+                .map(|p| RGB8::new(p.0[0], p.0[1], p.0[2]))
+                .collect();
+            let avif = ravif::Encoder::new()
+                .with_quality(self.shot_quality() as f32)
+                .with_speed(self.shot_speed)
+                .encode_rgb(Img::new(&pixels[..], width as usize, height as usize))?;
+            return Ok(avif.avif_file);
+        }
+
+        let format = ImageFormat::from_extension(&self.shot_type)
+            .ok_or_else(|| anyhow::anyhow!("Unknown shot type {}", self.shot_type))?;
+        let output_format = match format {
+            ImageFormat::Jpeg => ImageOutputFormat::Jpeg(self.shot_quality()),
+            other => ImageOutputFormat::from(other),
+        };
+
+        // Not everything can store an alpha channel (looking at you, JPEG), and screenshots don't
+        // have a meaningful one anyway.
+        DynamicImage::ImageRgba8(img)
+            .into_rgb8()
+            .write_to(&mut encoded, output_format)?;
+
+        Ok(encoded.into_inner())
+    }
+
     fn deal_with_blackout(
         &mut self,
         elapsed_secs: u64,
@@ -118,25 +878,23 @@ impl Capturer {
     ) -> Result<(), Error> {
         info!("Looks like we've been away for a while ({elapsed_secs:?} seconds).");
 
-        let filler_frame_path = dir_manager
-            .current_shot_dir()
-            .join(format!("{:05}.{}", self.curr_frame, self.shot_type));
+        let filler_frame_path = self.claim_free_frame(dir_manager);
 
         info!("Creating filler frame @ {filler_frame_path:?}");
-        Self::create_filler_frame(elapsed_secs, 860, 360)
-            .save(&filler_frame_path)
-            .expect("Couldn't create filler frame!");
+        let text = Self::filler_text(&self.filler_frame.gap_text, elapsed_secs);
+        let (width, height) = self.filler_dimensions(dir_manager);
+        let filler_frame = self.encode_frame(self.create_filler_frame(&text, width, height))?;
+        self.write_frame(&filler_frame_path, filler_frame)?;
+        self.record_away(elapsed_secs, (width, height), dir_manager);
 
         let missed_frames = (elapsed_secs / self.sleep_interval.as_secs()) as u32;
         debug!("Going to create {missed_frames:?} frames");
         for n in 1..missed_frames {
             symlink_file(
                 &filler_frame_path,
-                dir_manager.current_shot_dir().join(format!(
-                    "{:05}.{}",
-                    self.curr_frame + n,
-                    self.shot_type
-                )),
+                dir_manager
+                    .current_shot_dir()
+                    .join(self.frame_file_name(self.curr_frame + n)),
             )?;
         }
 
@@ -145,6 +903,27 @@ impl Capturer {
         Ok(())
     }
 
+    /// Note that the current frame is filler for time spent away, so the movie can mark it.
+    fn record_away(&self, away_secs: u64, (width, height): (u32, u32), dir_manager: &DirManager) {
+        let metadata = FrameMetadata {
+            frame: self.curr_frame,
+            captured_at: Local::now() - chrono::Duration::seconds(away_secs as i64),
+            width,
+            height,
+            app: None,
+            window_title: None,
+            idle_secs: None,
+            scale_factor: None,
+            away_secs: Some(away_secs),
+        };
+        if let Err(e) = frame_metadata::append(dir_manager.current_shot_dir(), &metadata) {
+            warn!(
+                "Couldn't note the time away at frame {}: {e:?}",
+                self.curr_frame
+            );
+        }
+    }
+
     fn get_curr_frame(&self, dir_manager: &mut DirManager) -> std::io::Result<FrameCounter> {
         let dir = dir_manager.current_shot_dir();
 
@@ -157,7 +936,8 @@ impl Capturer {
                 continue;
             }
 
-            if entry.path().extension().unwrap().to_str().unwrap() != self.shot_type {
+            let frame_suffix = format!(".{}", self.frame_extension());
+            if !entry.file_name().to_string_lossy().ends_with(&frame_suffix) {
                 continue;
             }
             count += 1;
@@ -166,37 +946,17 @@ impl Capturer {
         Ok(count)
     }
 
-    fn create_filler_frame(
-        duration_secs: u64,
-        width: u32,
-        height: u32,
-    ) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-        let black = Rgba([0, 0, 0, 255]);
-        let white = Rgba([255, 255, 255, 255]);
-        let mut img = ImageBuffer::from_pixel(width, height, black);
-
-        let duration_str = format!("{:#} go by", Self::human_duration(duration_secs));
-        let font_data = include_bytes!("Ubuntu-Regular.ttf");
-        let font = Font::try_from_bytes(font_data as &[u8]).unwrap();
-        let font_size = 80.0;
-        let scale = Scale::uniform(font_size);
-        let (text_w, text_h) = imageproc::drawing::text_size(scale, &font, &duration_str);
-        let offset_x = (width as f32 / 2.0) - (text_w as f32 / 2.0);
-        let offset_y = (height as f32 / 2.0) - (text_h as f32 / 2.0);
-
-        // Write the text to the image
-        imageproc::drawing::draw_text_mut(
-            &mut img,
-            white,
-            offset_x as i32,
-            offset_y as i32,
-            scale,
-            &font,
-            &duration_str,
-        );
+    fn filler_text(template: &str, duration_secs: u64) -> String {
+        template.replace("{duration}", &Self::human_duration(duration_secs))
+    }
 
-        // return the resulting image
-        img
+    fn create_filler_frame(&self, text: &str, width: u32, height: u32) -> RgbaImage {
+        card::draw(
+            &[text],
+            (width, height),
+            &self.filler_frame,
+            &self.filler_font,
+        )
     }
 
     fn human_duration(duration_secs: u64) -> String {
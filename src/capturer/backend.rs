@@ -0,0 +1,155 @@
+use anyhow::{anyhow, Error};
+use image::io::Reader;
+use image::{Rgba, RgbaImage};
+#[cfg(target_os = "macos")]
+use log::debug;
+use screenshots::Screen;
+use std::io::Cursor;
+
+#[cfg(target_os = "windows")]
+use super::windows::check_screen_state;
+
+#[cfg(not(target_os = "windows"))]
+use super::not_windows::check_screen_state;
+
+#[cfg(target_os = "linux")]
+use super::x11::XShmBackend;
+
+#[cfg(target_os = "macos")]
+use super::not_windows::ScreenCaptureKitBackend;
+
+pub const DEFAULT_BACKEND: &str = "screenshots";
+pub const AUTO_BACKEND: &str = "auto";
+
+pub trait CaptureBackend {
+    fn name(&self) -> &'static str;
+
+    /// Make sure the screen is worth capturing right now, e.g. it isn't locked or asleep.
+    fn check_screen_state(&mut self) -> Result<(), Error> {
+        check_screen_state()
+    }
+
+    fn capture(&mut self, screen: &Screen) -> Result<RgbaImage, Error>;
+}
+
+struct Registration {
+    name: &'static str,
+    make: fn() -> Result<Box<dyn CaptureBackend>, Error>,
+}
+
+/*
+Every backend this platform knows how to build. Anything that's only available on some platforms
+gets a cfg here and nowhere else, the rest of Capturer doesn't care which one it gets.
+*/
+fn registry() -> Vec<Registration> {
+    vec![
+        Registration {
+            name: AUTO_BACKEND,
+            make: || Ok(best_available()),
+        },
+        Registration {
+            name: DEFAULT_BACKEND,
+            make: || Ok(Box::new(ScreenshotsBackend)),
+        },
+        Registration {
+            name: "synthetic",
+            make: || Ok(Box::new(SyntheticBackend { frames: 0 })),
+        },
+        #[cfg(target_os = "linux")]
+        Registration {
+            name: "x11",
+            make: || Ok(Box::new(XShmBackend::new()?)),
+        },
+        #[cfg(target_os = "macos")]
+        Registration {
+            name: "screencapturekit",
+            make: || Ok(Box::new(ScreenCaptureKitBackend::new()?)),
+        },
+    ]
+}
+
+pub fn known_backends() -> Vec<&'static str> {
+    registry().iter().map(|r| r.name).collect()
+}
+
+pub fn from_name(name: &str) -> Result<Box<dyn CaptureBackend>, Error> {
+    let registration = registry()
+        .into_iter()
+        .find(|r| r.name == name)
+        .ok_or_else(|| {
+            anyhow!(
+                "Unknown capture backend '{name}', pick from: {:?}",
+                known_backends()
+            )
+        })?;
+
+    (registration.make)()
+}
+
+/// The original capture path, via the `screenshots` crate.
+pub struct ScreenshotsBackend;
+
+impl CaptureBackend for ScreenshotsBackend {
+    fn name(&self) -> &'static str {
+        DEFAULT_BACKEND
+    }
+
+    fn capture(&mut self, screen: &Screen) -> Result<RgbaImage, Error> {
+        let shot = screen.capture()?;
+
+        // We know that the screenshots::Image is a PNG, that's hard-coded.
+        // So, it's safe to decode it as such.
+        let image_reader = Reader::with_format(Cursor::new(shot.buffer()), image::ImageFormat::Png);
+
+        Ok(image_reader.decode()?.into_rgba8())
+    }
+}
+
+/// Doesn't look at the screen at all, just makes up a frame of the right size. Handy for headless
+/// machines and for poking at everything downstream of capture.
+pub struct SyntheticBackend {
+    frames: u32,
+}
+
+impl CaptureBackend for SyntheticBackend {
+    fn name(&self) -> &'static str {
+        "synthetic"
+    }
+
+    fn check_screen_state(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn capture(&mut self, screen: &Screen) -> Result<RgbaImage, Error> {
+        let info = screen.display_info;
+        let width = (info.width as f32 * info.scale_factor) as u32;
+        let height = (info.height as f32 * info.scale_factor) as u32;
+        let shift = self.frames;
+        self.frames = self.frames.wrapping_add(1);
+
+        Ok(RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([
+                (x.wrapping_add(shift) % 256) as u8,
+                (y.wrapping_add(shift) % 256) as u8,
+                (shift % 256) as u8,
+                255,
+            ])
+        }))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn best_available() -> Box<dyn CaptureBackend> {
+    match ScreenCaptureKitBackend::new() {
+        Ok(b) => Box::new(b),
+        Err(e) => {
+            debug!("Not using ScreenCaptureKit: {e:?}");
+            Box::new(ScreenshotsBackend)
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn best_available() -> Box<dyn CaptureBackend> {
+    Box::new(ScreenshotsBackend)
+}
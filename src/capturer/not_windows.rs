@@ -1,15 +1,497 @@
 use anyhow::{anyhow, Error};
-use screenshots::{Image, Screen};
+#[cfg(target_os = "linux")]
+use log::debug;
+#[cfg(target_os = "macos")]
+use screenshots::Screen;
+use std::time::Duration;
+
+#[cfg(target_os = "linux")]
+pub use super::x11::{foreground_app, foreground_window_title, idle_time, window_titles};
+
+#[cfg(target_os = "linux")]
+use dbus::blocking::{stdintf::org_freedesktop_dbus::Properties, Connection};
+
+#[cfg(target_os = "linux")]
+const LOGIND_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[cfg(target_os = "macos")]
-use std::process::Command;
+use {
+    super::backend::CaptureBackend,
+    block2::RcBlock,
+    dispatch2::DispatchQueue,
+    image::{Rgba, RgbaImage},
+    objc2::rc::Retained,
+    objc2::runtime::{AnyObject, NSObject, NSObjectProtocol, ProtocolObject},
+    objc2::{define_class, msg_send, AllocAnyThread, DefinedClass, Message},
+    objc2_app_kit::NSWorkspace,
+    objc2_core_foundation::CFArray,
+    objc2_core_graphics::{
+        kCGNullWindowID, CGDataProvider, CGEventSource, CGEventSourceStateID, CGEventType, CGImage,
+        CGWindowListCopyWindowInfo, CGWindowListOption,
+    },
+    objc2_core_media::CMSampleBuffer,
+    objc2_core_video::{
+        kCVPixelFormatType_32BGRA, CVPixelBuffer, CVPixelBufferGetBaseAddress,
+        CVPixelBufferGetBytesPerRow, CVPixelBufferGetHeight, CVPixelBufferGetWidth,
+        CVPixelBufferLockBaseAddress, CVPixelBufferLockFlags, CVPixelBufferUnlockBaseAddress,
+    },
+    objc2_foundation::{ns_string, NSArray, NSDictionary, NSError, NSNumber, NSString},
+    objc2_screen_capture_kit::{
+        SCContentFilter, SCScreenshotManager, SCShareableContent, SCStream, SCStreamConfiguration,
+        SCStreamOutput, SCStreamOutputType,
+    },
+    std::process::Command,
+    std::ptr::NonNull,
+    std::sync::{mpsc, Mutex},
+};
 
-pub fn get_screenshot(screen: Screen) -> Result<Image, Error> {
+#[cfg(target_os = "macos")]
+const SCREEN_CAPTURE_KIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub fn check_screen_state() -> Result<(), Error> {
     if !have_graphics() {
         return Err(anyhow!("Don't appear to have graphics!"));
     }
 
-    screen.capture()
+    if session_locked() {
+        return Err(anyhow!("Session is locked, do not want."));
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+pub fn idle_time() -> Result<Duration, Error> {
+    // kCGAnyInputEventType isn't a real event type, so it doesn't get a constant.
+    let any_input = CGEventType(u32::MAX);
+    let idle_secs = CGEventSource::seconds_since_last_event_type(
+        CGEventSourceStateID::CombinedSessionState,
+        any_input,
+    );
+
+    Ok(Duration::from_secs_f64(idle_secs.max(0.0)))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn idle_time() -> Result<Duration, Error> {
+    Err(anyhow!("Don't know how to detect idle time here"))
+}
+
+#[cfg(target_os = "macos")]
+pub fn foreground_app() -> Result<String, Error> {
+    let app = NSWorkspace::sharedWorkspace()
+        .frontmostApplication()
+        .ok_or_else(|| anyhow!("Nothing is in the foreground?!"))?;
+
+    app.localizedName()
+        .map(|name| name.to_string())
+        .ok_or_else(|| anyhow!("Foreground app doesn't have a name"))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn foreground_app() -> Result<String, Error> {
+    Err(anyhow!("Don't know how to find the foreground app here"))
+}
+
+#[cfg(target_os = "macos")]
+type WindowInfo = NSDictionary<NSString, AnyObject>;
+
+#[cfg(target_os = "macos")]
+fn on_screen_windows() -> Result<Retained<NSArray<WindowInfo>>, Error> {
+    let windows = CGWindowListCopyWindowInfo(
+        CGWindowListOption::OptionOnScreenOnly | CGWindowListOption::ExcludeDesktopElements,
+        kCGNullWindowID,
+    )
+    .ok_or_else(|| anyhow!("Couldn't list windows"))?;
+
+    // A CFArray of CFDictionary is toll-free bridged to the Foundation equivalents, which are a
+    // lot nicer to dig around in.
+    let windows: &NSArray<WindowInfo> = unsafe { &*(&*windows as *const CFArray).cast() };
+    Ok(windows.retain())
+}
+
+#[cfg(target_os = "macos")]
+fn window_number(window: &WindowInfo, key: &NSString) -> Option<i32> {
+    window
+        .objectForKey(key)
+        .and_then(|v| v.downcast::<NSNumber>().ok())
+        .map(|n| n.intValue())
+}
+
+#[cfg(target_os = "macos")]
+fn window_name(window: &WindowInfo) -> Option<String> {
+    window
+        .objectForKey(ns_string!("kCGWindowName"))
+        .and_then(|v| v.downcast::<NSString>().ok())
+        .map(|name| name.to_string())
+}
+
+#[cfg(target_os = "macos")]
+pub fn foreground_window_title() -> Result<String, Error> {
+    let pid = NSWorkspace::sharedWorkspace()
+        .frontmostApplication()
+        .ok_or_else(|| anyhow!("Nothing is in the foreground?!"))?
+        .processIdentifier();
+
+    // Windows come front to back, so the first normal one the app owns is the one in front.
+    let windows = on_screen_windows()?;
+    let window = windows
+        .iter()
+        .find(|w| {
+            window_number(w, ns_string!("kCGWindowOwnerPID")) == Some(pid)
+                && window_number(w, ns_string!("kCGWindowLayer")) == Some(0)
+        })
+        .ok_or_else(|| anyhow!("Foreground app doesn't have a window on screen"))?;
+
+    window_name(&window).ok_or_else(|| anyhow!("Foreground window doesn't have a title"))
+}
+
+#[cfg(target_os = "macos")]
+pub fn window_titles() -> Result<Vec<String>, Error> {
+    Ok(on_screen_windows()?
+        .iter()
+        .filter_map(|w| window_name(&w))
+        .collect())
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn window_titles() -> Result<Vec<String>, Error> {
+    Err(anyhow!("Don't know how to list windows here"))
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn foreground_window_title() -> Result<String, Error> {
+    Err(anyhow!("Don't know how to find the foreground window here"))
+}
+
+/*
+The CoreGraphics path the screenshots crate uses is deprecated on recent macOS, slow, and keeps
+re-triggering the screen recording permission prompt. ScreenCaptureKit doesn't have those problems.
+Single frame capture (SCScreenshotManager) only showed up in macOS 14, so from 12.3 through 13 we
+start an SCStream, keep the first frame that has a picture in it, and stop it again. Anything older
+keeps using the screenshots backend.
+*/
+#[cfg(target_os = "macos")]
+pub struct ScreenCaptureKitBackend {
+    /// Whether SCScreenshotManager is around, otherwise it's one frame off an SCStream.
+    screenshot_manager: bool,
+}
+
+#[cfg(target_os = "macos")]
+impl ScreenCaptureKitBackend {
+    pub fn new() -> Result<ScreenCaptureKitBackend, Error> {
+        if !objc2::available!(macos = 12.3) {
+            return Err(anyhow!("ScreenCaptureKit needs macOS 12.3 or newer"));
+        }
+
+        Ok(ScreenCaptureKitBackend {
+            screenshot_manager: objc2::available!(macos = 14.0),
+        })
+    }
+}
+
+#[cfg(target_os = "macos")]
+type FrameSender = mpsc::Sender<Result<RgbaImage, String>>;
+
+#[cfg(target_os = "macos")]
+struct FrameCatcherIvars {
+    tx: Mutex<Option<FrameSender>>,
+    /// Kept here until we're done waiting on it, so it can be stopped however that went.
+    stream: Mutex<Option<Retained<SCStream>>>,
+}
+
+#[cfg(target_os = "macos")]
+define_class!(
+    // Receives an SCStream's frames, and hands the first whole one back to whoever's waiting.
+    #[unsafe(super(NSObject))]
+    #[name = "OmpdFrameCatcher"]
+    #[ivars = FrameCatcherIvars]
+    struct FrameCatcher;
+
+    unsafe impl NSObjectProtocol for FrameCatcher {}
+
+    unsafe impl SCStreamOutput for FrameCatcher {
+        #[unsafe(method(stream:didOutputSampleBuffer:ofType:))]
+        unsafe fn stream_did_output(
+            &self,
+            _stream: &SCStream,
+            sample_buffer: &CMSampleBuffer,
+            r#type: SCStreamOutputType,
+        ) {
+            if r#type != SCStreamOutputType::Screen {
+                return;
+            }
+            // Frames where nothing changed come through without a picture, wait for one with.
+            let Some(pixels) = (unsafe { sample_buffer.image_buffer() }) else {
+                return;
+            };
+            if let Some(tx) = self.ivars().tx.lock().unwrap().take() {
+                let _ = tx.send(pixel_buffer_to_rgba(&pixels));
+            }
+        }
+    }
+);
+
+#[cfg(target_os = "macos")]
+impl FrameCatcher {
+    fn new(tx: FrameSender) -> Retained<FrameCatcher> {
+        let this = FrameCatcher::alloc().set_ivars(FrameCatcherIvars {
+            tx: Mutex::new(Some(tx)),
+            stream: Mutex::new(None),
+        });
+        unsafe { msg_send![super(this), init] }
+    }
+
+    /// Start streaming `filter` into ourselves.
+    fn start(&self, filter: &SCContentFilter, config: &SCStreamConfiguration) {
+        let fail = |why: String| {
+            if let Some(tx) = self.ivars().tx.lock().unwrap().take() {
+                let _ = tx.send(Err(why));
+            }
+        };
+
+        let stream = unsafe {
+            SCStream::initWithFilter_configuration_delegate(SCStream::alloc(), filter, config, None)
+        };
+        let queue = DispatchQueue::new("ompd.screencapturekit", None);
+        let added = unsafe {
+            stream.addStreamOutput_type_sampleHandlerQueue_error(
+                ProtocolObject::from_ref(self),
+                SCStreamOutputType::Screen,
+                Some(&queue),
+            )
+        };
+        if let Err(e) = added {
+            fail(e.localizedDescription().to_string());
+            return;
+        }
+
+        let tx = self.ivars().tx.lock().unwrap().clone();
+        let started = RcBlock::new(move |error: *mut NSError| {
+            // Nothing to say if it started fine, or if a frame already made it back.
+            if error.is_null() {
+                return;
+            }
+            if let Some(tx) = &tx {
+                let _ = tx.send(Err(describe_error(error)));
+            }
+        });
+        unsafe { stream.startCaptureWithCompletionHandler(Some(&started)) };
+        *self.ivars().stream.lock().unwrap() = Some(stream);
+    }
+
+    fn stop(&self) {
+        let Some(stream) = self.ivars().stream.lock().unwrap().take() else {
+            return;
+        };
+        // Hang on to the stream until it's actually stopped.
+        let stopping = stream.clone();
+        let stopped = RcBlock::new(move |_: *mut NSError| {
+            let _ = &stopping;
+        });
+        unsafe { stream.stopCaptureWithCompletionHandler(Some(&stopped)) };
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl CaptureBackend for ScreenCaptureKitBackend {
+    fn name(&self) -> &'static str {
+        "screencapturekit"
+    }
+
+    fn capture(&mut self, screen: &Screen) -> Result<RgbaImage, Error> {
+        let info = screen.display_info;
+        let (tx, rx) = mpsc::channel::<Result<RgbaImage, String>>();
+        let catcher = (!self.screenshot_manager).then(|| FrameCatcher::new(tx.clone()));
+        let stream_catcher = catcher.clone();
+
+        // Everything in ScreenCaptureKit is callback based, so chain the calls together and
+        // wait for the image (or an error) to come out the other end.
+        let content_handler = RcBlock::new(
+            move |content: *mut SCShareableContent, error: *mut NSError| {
+                let tx = tx.clone();
+                let Some(content) = (unsafe { Retained::retain(content) }) else {
+                    let _ = tx.send(Err(describe_error(error)));
+                    return;
+                };
+
+                let displays = unsafe { content.displays() };
+                let Some(display) = displays
+                    .iter()
+                    .find(|d| unsafe { d.displayID() } == info.id)
+                else {
+                    let _ = tx.send(Err(format!(
+                        "ScreenCaptureKit doesn't know about display {}",
+                        info.id
+                    )));
+                    return;
+                };
+
+                let filter = unsafe {
+                    SCContentFilter::initWithDisplay_excludingWindows(
+                        SCContentFilter::alloc(),
+                        &display,
+                        &NSArray::new(),
+                    )
+                };
+
+                let config = unsafe { SCStreamConfiguration::new() };
+                unsafe {
+                    config.setWidth((info.width as f32 * info.scale_factor) as usize);
+                    config.setHeight((info.height as f32 * info.scale_factor) as usize);
+                    config.setShowsCursor(true);
+                    config.setPixelFormat(kCVPixelFormatType_32BGRA);
+                }
+
+                if let Some(catcher) = &stream_catcher {
+                    catcher.start(&filter, &config);
+                    return;
+                }
+
+                let image_handler =
+                    RcBlock::new(move |image: *mut CGImage, error: *mut NSError| {
+                        let result = match NonNull::new(image) {
+                            Some(image) => cg_image_to_rgba(unsafe { image.as_ref() })
+                                .map_err(|e| e.to_string()),
+                            None => Err(describe_error(error)),
+                        };
+                        let _ = tx.send(result);
+                    });
+
+                unsafe {
+                    SCScreenshotManager::captureImageWithFilter_configuration_completionHandler(
+                        &filter,
+                        &config,
+                        Some(&image_handler),
+                    )
+                };
+            },
+        );
+
+        unsafe { SCShareableContent::getShareableContentWithCompletionHandler(&content_handler) };
+
+        let image = rx.recv_timeout(SCREEN_CAPTURE_KIT_TIMEOUT);
+        if let Some(catcher) = catcher {
+            catcher.stop();
+        }
+
+        image?.map_err(|e| anyhow!("ScreenCaptureKit capture failed: {e}"))
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn describe_error(error: *mut NSError) -> String {
+    match unsafe { error.as_ref() } {
+        Some(e) => e.localizedDescription().to_string(),
+        None => "no error given?!".to_string(),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn pixel_buffer_to_rgba(pixels: &CVPixelBuffer) -> Result<RgbaImage, String> {
+    let locked = unsafe { CVPixelBufferLockBaseAddress(pixels, CVPixelBufferLockFlags::ReadOnly) };
+    if locked != 0 {
+        return Err(format!(
+            "Couldn't get at the captured frame (CVReturn {locked})"
+        ));
+    }
+
+    let width = CVPixelBufferGetWidth(pixels);
+    let height = CVPixelBufferGetHeight(pixels);
+    let bytes_per_row = CVPixelBufferGetBytesPerRow(pixels);
+    let base = CVPixelBufferGetBaseAddress(pixels) as *const u8;
+    let img = (!base.is_null()).then(|| {
+        let data = unsafe { std::slice::from_raw_parts(base, bytes_per_row * height) };
+        bgra_to_rgba(data, width, height, bytes_per_row)
+    });
+
+    unsafe { CVPixelBufferUnlockBaseAddress(pixels, CVPixelBufferLockFlags::ReadOnly) };
+    img.ok_or_else(|| "Captured frame doesn't have any pixels?!".to_string())
+}
+
+/// ScreenCaptureKit gives us BGRA, with possible padding at the end of each row.
+#[cfg(target_os = "macos")]
+fn bgra_to_rgba(data: &[u8], width: usize, height: usize, bytes_per_row: usize) -> RgbaImage {
+    let mut img = RgbaImage::new(width as u32, height as u32);
+    for (y, row) in data.chunks(bytes_per_row).take(height).enumerate() {
+        for (x, raw) in row.chunks_exact(4).take(width).enumerate() {
+            img.put_pixel(x as u32, y as u32, Rgba([raw[2], raw[1], raw[0], 255]));
+        }
+    }
+    img
+}
+
+#[cfg(target_os = "macos")]
+fn cg_image_to_rgba(image: &CGImage) -> Result<RgbaImage, Error> {
+    let width = CGImage::width(Some(image));
+    let height = CGImage::height(Some(image));
+    let bytes_per_row = CGImage::bytes_per_row(Some(image));
+
+    if CGImage::bits_per_pixel(Some(image)) != 32 {
+        return Err(anyhow!(
+            "Don't know what to do with {} bits per pixel",
+            CGImage::bits_per_pixel(Some(image))
+        ));
+    }
+
+    let provider = CGImage::data_provider(Some(image))
+        .ok_or_else(|| anyhow!("Captured image doesn't have any data?!"))?;
+    let data = CGDataProvider::data(Some(&provider))
+        .ok_or_else(|| anyhow!("Couldn't copy captured image data"))?
+        .to_vec();
+
+    Ok(bgra_to_rgba(&data, width, height, bytes_per_row))
+}
+
+#[cfg(target_os = "macos")]
+pub fn on_battery() -> Result<bool, Error> {
+    let batt = Command::new("/usr/bin/pmset")
+        .args(["-g", "batt"])
+        .output()?;
+    let on_battery = String::from_utf8_lossy(&batt.stdout).contains("'Battery Power'");
+
+    let settings = Command::new("/usr/bin/pmset").arg("-g").output()?;
+    let low_power = String::from_utf8_lossy(&settings.stdout)
+        .lines()
+        .any(|line| line.split_whitespace().collect::<Vec<_>>() == ["lowpowermode", "1"]);
+
+    Ok(on_battery || low_power)
+}
+
+/*
+Anything with a "Mains" supply tells us whether it's plugged in. Desktops without any power supplies
+listed at all are assumed to be plugged in, since they don't have a battery to be on.
+*/
+#[cfg(target_os = "linux")]
+pub fn on_battery() -> Result<bool, Error> {
+    let mut have_battery = false;
+    let mut mains_online = false;
+
+    for entry in std::fs::read_dir("/sys/class/power_supply")? {
+        let supply = entry?.path();
+        let read = |name: &str| {
+            std::fs::read_to_string(supply.join(name))
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default()
+        };
+
+        match read("type").as_str() {
+            "Mains" => mains_online |= read("online") == "1",
+            "Battery" => have_battery = true,
+            _ => {}
+        }
+    }
+
+    let low_power = std::fs::read_to_string("/sys/firmware/acpi/platform_profile")
+        .map(|p| p.trim() == "low-power")
+        .unwrap_or(false);
+
+    Ok((have_battery && !mains_online) || low_power)
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn on_battery() -> Result<bool, Error> {
+    Err(anyhow!("Don't know how to check the power source here"))
 }
 
 #[cfg(target_os = "macos")]
@@ -34,3 +516,34 @@ fn have_graphics() -> bool {
 fn have_graphics() -> bool {
     true
 }
+
+/*
+Without this we'd happily capture the lock screen all night. logind knows whether the session is
+locked, as long as the screen locker bothers to tell it (GNOME, KDE, and xss-lock all do). The "auto"
+session is whichever one we're running in, or the user's graphical session if we're not in one.
+*/
+#[cfg(target_os = "linux")]
+fn session_locked() -> bool {
+    let locked_hint = || -> Result<bool, dbus::Error> {
+        let conn = Connection::new_system()?;
+        let session = conn.with_proxy(
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1/session/auto",
+            LOGIND_TIMEOUT,
+        );
+        session.get("org.freedesktop.login1.Session", "LockedHint")
+    };
+
+    match locked_hint() {
+        Ok(locked) => locked,
+        Err(e) => {
+            debug!("Couldn't ask logind if we're locked, assuming not: {e:?}");
+            false
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn session_locked() -> bool {
+    false
+}
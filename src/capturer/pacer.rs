@@ -0,0 +1,79 @@
+use image::imageops::{self, FilterType};
+use image::{GrayImage, RgbaImage};
+use log::debug;
+use std::time::Duration;
+
+use crate::config::AdaptiveInterval;
+
+/*
+Compare a tiny greyscale thumbnail of each frame to the last one. That's plenty to tell a scrolling
+browser or a video from someone staring at a wall of text, and it's cheap enough to do every frame.
+*/
+
+const THUMB_WIDTH: u32 = 64;
+const THUMB_HEIGHT: u32 = 36;
+
+// Average per-pixel change (0-1) above which we speed up, and below which we slow down.
+const BUSY_CHANGE: f32 = 0.05;
+const QUIET_CHANGE: f32 = 0.01;
+
+pub struct Pacer {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+    last_thumb: Option<GrayImage>,
+}
+
+impl Pacer {
+    pub fn new(settings: &AdaptiveInterval, start: Duration) -> Pacer {
+        let min = Duration::from_secs(settings.min_secs);
+        let max = Duration::from_secs(settings.max_secs);
+
+        Pacer {
+            min,
+            max,
+            current: start.clamp(min, max),
+            last_thumb: None,
+        }
+    }
+
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// Look at the frame we just stored and figure out how long to wait for the next one.
+    pub fn observe(&mut self, img: &RgbaImage) -> Duration {
+        let thumb = imageops::grayscale(&imageops::resize(
+            img,
+            THUMB_WIDTH,
+            THUMB_HEIGHT,
+            FilterType::Triangle,
+        ));
+
+        if let Some(last) = &self.last_thumb {
+            let total: u64 = thumb
+                .pixels()
+                .zip(last.pixels())
+                .map(|(a, b)| a.0[0].abs_diff(b.0[0]) as u64)
+                .sum();
+            let change = total as f32 / (255.0 * (THUMB_WIDTH * THUMB_HEIGHT) as f32);
+
+            let next = if change > BUSY_CHANGE {
+                self.current / 2
+            } else if change < QUIET_CHANGE {
+                self.current * 3 / 2
+            } else {
+                self.current
+            };
+            let next = next.clamp(self.min, self.max);
+
+            if next != self.current {
+                debug!("Screen changed {change:.3}, capture interval now {next:?}");
+            }
+            self.current = next;
+        }
+
+        self.last_thumb = Some(thumb);
+        self.current
+    }
+}
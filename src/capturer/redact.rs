@@ -0,0 +1,34 @@
+use image::{imageops, Rgba, RgbaImage};
+use log::debug;
+
+use crate::config::RedactRegion;
+
+const BLUR_SIGMA: f32 = 12.0;
+
+pub fn redact(img: &mut RgbaImage, regions: &[RedactRegion]) {
+    let (img_width, img_height) = img.dimensions();
+
+    for region in regions {
+        // Screens change size, so clip anything hanging off the edge rather than blowing up.
+        if region.x >= img_width || region.y >= img_height {
+            debug!("{region:?} is entirely off a {img_width}x{img_height} frame, skipping");
+            continue;
+        }
+        let width = region.width.min(img_width - region.x);
+        let height = region.height.min(img_height - region.y);
+
+        match region.style.as_str() {
+            "blur" => {
+                let blurred = imageops::blur(
+                    &imageops::crop_imm(img, region.x, region.y, width, height).to_image(),
+                    BLUR_SIGMA,
+                );
+                imageops::replace(img, &blurred, region.x as i64, region.y as i64);
+            }
+            _ => {
+                let black = RgbaImage::from_pixel(width, height, Rgba([0, 0, 0, 255]));
+                imageops::replace(img, &black, region.x as i64, region.y as i64);
+            }
+        }
+    }
+}
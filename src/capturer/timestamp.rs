@@ -0,0 +1,49 @@
+use chrono::{DateTime, Local};
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_text_mut, text_size};
+use imageproc::rect::Rect;
+use rusttype::{Font, Scale};
+
+use crate::config::TimestampOverlay;
+
+pub fn burn_in(
+    img: &mut RgbaImage,
+    font: &Font,
+    overlay: &TimestampOverlay,
+    when: &DateTime<Local>,
+) {
+    let text = when.format(&overlay.format).to_string();
+    let (width, height) = img.dimensions();
+
+    let font_size = overlay
+        .font_size
+        .unwrap_or((height as f32 / 30.0).max(12.0));
+    let scale = Scale::uniform(font_size);
+    let (text_w, text_h) = text_size(scale, font, &text);
+    let padding = (font_size / 4.0) as i32;
+
+    let box_w = text_w + padding * 2;
+    let box_h = text_h + padding * 2;
+    let (box_x, box_y) = match overlay.corner.as_str() {
+        "top-left" => (0, 0),
+        "top-right" => (width as i32 - box_w, 0),
+        "bottom-left" => (0, height as i32 - box_h),
+        _ => (width as i32 - box_w, height as i32 - box_h),
+    };
+
+    // Dark backing box so the text is readable no matter what's on screen.
+    draw_filled_rect_mut(
+        img,
+        Rect::at(box_x, box_y).of_size(box_w.max(1) as u32, box_h.max(1) as u32),
+        Rgba([0, 0, 0, 255]),
+    );
+    draw_text_mut(
+        img,
+        Rgba([255, 255, 255, 255]),
+        box_x + padding,
+        box_y + padding,
+        scale,
+        font,
+        &text,
+    );
+}
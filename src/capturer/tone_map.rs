@@ -0,0 +1,32 @@
+use image::RgbaImage;
+
+/*
+With HDR turned on, Windows hands GDI captures back already sRGB encoded, and then everything
+downstream (image viewers, ffmpeg) encodes them again, so frames come out looking washed out. Undoing
+one round of the sRGB transfer function gets the colors back to roughly what was on screen.
+*/
+pub fn tone_map(img: &mut RgbaImage) {
+    let lut = srgb_to_linear_lut();
+
+    for pixel in img.pixels_mut() {
+        pixel[0] = lut[pixel[0] as usize];
+        pixel[1] = lut[pixel[1] as usize];
+        pixel[2] = lut[pixel[2] as usize];
+    }
+}
+
+fn srgb_to_linear_lut() -> [u8; 256] {
+    let mut lut = [0u8; 256];
+
+    for (i, entry) in lut.iter_mut().enumerate() {
+        let encoded = i as f32 / 255.0;
+        let linear = if encoded <= 0.04045 {
+            encoded / 12.92
+        } else {
+            ((encoded + 0.055) / 1.055).powf(2.4)
+        };
+        *entry = (linear * 255.0).round() as u8;
+    }
+
+    lut
+}
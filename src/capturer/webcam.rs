@@ -0,0 +1,201 @@
+use anyhow::{anyhow, Error};
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use log::{debug, info};
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::WebcamOverlay;
+
+/*
+We already lean on ffmpeg for making the movies, and it knows how to talk to cameras on all three
+platforms, so have it stream frames rather than pulling in a whole camera stack. One ffmpeg stays
+open on its own thread for as long as the overlay's on, sending a small frame a second as PPM (which
+says how big it is up front, so they're easy to split up), and capturing just takes whatever came
+in last. If the camera is busy (say, in a video call) ffmpeg bails, and we go without until it's
+free again instead of holding up capture waiting on it.
+*/
+
+/// Older than this and the camera's probably stopped sending, so leave it out.
+const STALE_AFTER: Duration = Duration::from_secs(5);
+
+/// How long to wait before trying the camera again after ffmpeg gives up on it.
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Frames only ever go in a corner, so there's no use for anything bigger.
+const FRAME_WIDTH: u32 = 640;
+
+#[cfg(target_os = "linux")]
+const INPUT_FORMAT: &str = "v4l2";
+
+#[cfg(target_os = "macos")]
+const INPUT_FORMAT: &str = "avfoundation";
+
+#[cfg(target_os = "windows")]
+const INPUT_FORMAT: &str = "dshow";
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+const INPUT_FORMAT: &str = "video4linux2";
+
+pub fn default_device() -> String {
+    #[cfg(target_os = "linux")]
+    return "/dev/video0".to_string();
+
+    #[cfg(target_os = "macos")]
+    return "0".to_string();
+
+    #[cfg(target_os = "windows")]
+    return "video=Integrated Camera".to_string();
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    return "/dev/video0".to_string();
+}
+
+/// A camera being read in the background. Stops once it's dropped.
+pub struct Webcam {
+    device: String,
+    latest: Arc<Mutex<Option<(Instant, RgbaImage)>>>,
+    running: Arc<Mutex<Option<Child>>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Webcam {
+    pub fn start(ffmpeg: &str, device: &str) -> Webcam {
+        let webcam = Webcam {
+            device: device.to_string(),
+            latest: Arc::new(Mutex::new(None)),
+            running: Arc::new(Mutex::new(None)),
+            stop: Arc::new(AtomicBool::new(false)),
+        };
+
+        let (ffmpeg, device) = (ffmpeg.to_string(), device.to_string());
+        let (latest, running, stop) = (
+            webcam.latest.clone(),
+            webcam.running.clone(),
+            webcam.stop.clone(),
+        );
+        let spawned = thread::Builder::new().name("webcam".into()).spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                if let Err(e) = stream(&ffmpeg, &device, &latest, &running, &stop) {
+                    debug!("Lost webcam {device}, trying again in {RETRY_AFTER:?}: {e:?}");
+                }
+                let waiting_since = Instant::now();
+                while !stop.load(Ordering::Relaxed) && waiting_since.elapsed() < RETRY_AFTER {
+                    thread::sleep(Duration::from_millis(250));
+                }
+            }
+        });
+        if let Err(e) = spawned {
+            info!("Couldn't start reading webcam {}: {e:?}", webcam.device);
+        }
+        webcam
+    }
+
+    /// What the camera showed most recently, if it's shown anything lately.
+    pub fn latest(&self) -> Result<RgbaImage, Error> {
+        match &*self.latest.lock().unwrap() {
+            Some((at, frame)) if at.elapsed() < STALE_AFTER => Ok(frame.clone()),
+            Some(_) => Err(anyhow!("Nothing new from webcam {} lately", self.device)),
+            None => Err(anyhow!("Nothing from webcam {} yet", self.device)),
+        }
+    }
+}
+
+impl Drop for Webcam {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Its thread finds out once ffmpeg's gone and its output ends.
+        if let Some(child) = self.running.lock().unwrap().as_mut() {
+            let _ = child.kill();
+        }
+    }
+}
+
+/// Runs ffmpeg on `device`, keeping `latest` up to date, until it stops or we're told to.
+fn stream(
+    ffmpeg: &str,
+    device: &str,
+    latest: &Mutex<Option<(Instant, RgbaImage)>>,
+    running: &Mutex<Option<Child>>,
+    stop: &AtomicBool,
+) -> Result<(), Error> {
+    let mut child = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error"])
+        .args(["-f", INPUT_FORMAT, "-i", device])
+        .args(["-vf", &format!("fps=1,scale={FRAME_WIDTH}:-2")])
+        .args(["-f", "image2pipe", "-vcodec", "ppm", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    *running.lock().unwrap() = Some(child);
+
+    let read = loop {
+        if stop.load(Ordering::Relaxed) {
+            break Ok(());
+        }
+        match read_ppm(&mut stdout) {
+            Ok(frame) => *latest.lock().unwrap() = Some((Instant::now(), frame)),
+            Err(e) => break Err(e),
+        }
+    };
+
+    if let Some(mut child) = running.lock().unwrap().take() {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+    read
+}
+
+/// One binary PPM (P6) frame: a header with its size, then that many RGB pixels.
+fn read_ppm(stdout: &mut BufReader<ChildStdout>) -> Result<RgbaImage, Error> {
+    let mut fields = Vec::new();
+    while fields.len() < 4 {
+        let mut line = String::new();
+        if stdout.read_line(&mut line)? == 0 {
+            return Err(anyhow!("ffmpeg stopped sending frames"));
+        }
+        fields.extend(line.split_whitespace().map(String::from));
+    }
+    if fields[0] != "P6" || fields[3] != "255" {
+        return Err(anyhow!("Expected an 8 bit PPM from ffmpeg, got {fields:?}"));
+    }
+    let (width, height): (u32, u32) = (fields[1].parse()?, fields[2].parse()?);
+
+    let mut rgb = vec![0; (width * height * 3) as usize];
+    stdout.read_exact(&mut rgb)?;
+    let rgb = image::RgbImage::from_raw(width, height, rgb)
+        .ok_or_else(|| anyhow!("Webcam frame didn't fit {width}x{height}"))?;
+    Ok(image::DynamicImage::ImageRgb8(rgb).into_rgba8())
+}
+
+pub fn picture_in_picture(img: &mut RgbaImage, cam: &RgbaImage, overlay: &WebcamOverlay) {
+    let (width, height) = img.dimensions();
+    let (cam_w, cam_h) = cam.dimensions();
+    if cam_w == 0 || cam_h == 0 {
+        return;
+    }
+
+    let pip_w = ((width as f32 * overlay.size) as u32).max(1);
+    let pip_h = ((pip_w as f32 * cam_h as f32 / cam_w as f32) as u32).clamp(1, height);
+    let pip = imageops::resize(cam, pip_w, pip_h, FilterType::Triangle);
+
+    let margin = (width.min(height) / 50) as i64;
+    let (x, y) = match overlay.corner.as_str() {
+        "top-left" => (margin, margin),
+        "top-right" => (width as i64 - pip_w as i64 - margin, margin),
+        "bottom-left" => (margin, height as i64 - pip_h as i64 - margin),
+        _ => (
+            width as i64 - pip_w as i64 - margin,
+            height as i64 - pip_h as i64 - margin,
+        ),
+    };
+
+    debug!("Putting a {pip_w}x{pip_h} webcam frame at ({x}, {y})");
+    imageops::overlay(img, &pip, x, y);
+}
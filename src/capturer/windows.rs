@@ -1,7 +1,31 @@
 use anyhow::{anyhow, Error};
-use screenshots::{Image, Screen};
+use log::{debug, info, warn};
 use serde::Deserialize;
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, OnceLock};
+use std::thread;
+use std::time::Duration;
+use windows::core::{PCWSTR, PWSTR};
+use windows::w;
+use windows::Win32::Foundation::{CloseHandle, BOOL, HWND, LPARAM, LRESULT, WPARAM};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+use windows::Win32::System::RemoteDesktop::{
+    WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+};
+use windows::Win32::System::SystemInformation::GetTickCount;
+use windows::Win32::System::Threading::{
+    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DispatchMessageW, EnumWindows, GetForegroundWindow,
+    GetMessageW, GetWindowTextW, GetWindowThreadProcessId, IsWindowVisible, RegisterClassW,
+    TranslateMessage, HMENU, HWND_MESSAGE, MSG, WINDOW_EX_STYLE, WINDOW_STYLE,
+    WM_WTSSESSION_CHANGE, WNDCLASSW, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+};
 use wmi::connection::WMIConnection;
 use wmi::query::FilterValue;
 use wmi::COMLibrary;
@@ -13,32 +37,250 @@ struct Process {
     process_id: u32,
 }
 
-pub fn get_screenshot(screen: Screen) -> Result<Image, Error> {
-    /*
-    First, just try to capture the screen. The main reason we wouldn't be able to is that the screen
-    saver is running. Even running as administrator you can't capture the screen saver.
-    */
-
-    let capture = screen.capture()?;
-
-    /*
-    OK, that worked. Now let's make sure we didn't capture anything strange because the lock screen
-    is active. Once the lock screen activates and the display goes into standby, we get a simple
-    solid color. So just to be safe, if LogonUI.exe is running, return an Error.
-    */
-    let wmi_con = WMIConnection::new(COMLibrary::new().unwrap()).unwrap();
-    let logons: Vec<Process> = wmi_con
-        .filtered_query(&HashMap::from([(
-            "Name".to_owned(),
-            FilterValue::Str("LogonUI.exe"),
-        )]))
-        .unwrap();
-    if !logons.is_empty() {
-        return Err(anyhow!(
-            "Lock screen is active (pid = {:?}), do not want.",
-            logons.first().unwrap().process_id
-        ));
-    }
-
-    Ok(capture)
+/*
+Capturing will flat out fail if the screen saver is running, even as administrator, so that takes
+care of itself. What doesn't is the lock screen. Once the lock screen activates and the display goes
+into standby, we get a simple solid color.
+
+Rather than asking WMI about LogonUI.exe every frame (slow, and it occasionally falls over with COM
+errors) we have Windows tell us when the session locks and unlocks. WMI is only used to figure out
+where we're starting from, or as a fallback if we can't get notifications.
+*/
+static SESSION_LOCKED: AtomicBool = AtomicBool::new(false);
+static SESSION_WATCHER_RUNNING: OnceLock<bool> = OnceLock::new();
+
+pub fn check_screen_state() -> Result<(), Error> {
+    let watching = *SESSION_WATCHER_RUNNING.get_or_init(start_session_watcher);
+
+    let locked = if watching {
+        SESSION_LOCKED.load(Ordering::SeqCst)
+    } else {
+        logon_ui_running()?
+    };
+
+    if locked {
+        return Err(anyhow!("Workstation is locked, do not want."));
+    }
+
+    Ok(())
+}
+
+fn logon_ui_running() -> Result<bool, Error> {
+    let wmi_con = WMIConnection::new(COMLibrary::new()?)?;
+    let logons: Vec<Process> = wmi_con.filtered_query(&HashMap::from([(
+        "Name".to_owned(),
+        FilterValue::Str("LogonUI.exe"),
+    )]))?;
+
+    if let Some(logon) = logons.first() {
+        debug!("LogonUI.exe is running (pid = {:?})", logon.process_id);
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+fn start_session_watcher() -> bool {
+    match logon_ui_running() {
+        Ok(locked) => SESSION_LOCKED.store(locked, Ordering::SeqCst),
+        Err(e) => warn!("Couldn't figure out if we're starting out locked: {e:?}"),
+    }
+
+    let (started_tx, started_rx) = mpsc::channel();
+    let spawned = thread::Builder::new()
+        .name("session-watcher".into())
+        .spawn(move || {
+            let window = match create_session_window() {
+                Ok(w) => {
+                    let _ = started_tx.send(true);
+                    w
+                }
+                Err(e) => {
+                    warn!("Couldn't sign up for session lock notifications: {e:?}");
+                    let _ = started_tx.send(false);
+                    return;
+                }
+            };
+
+            let mut msg = MSG::default();
+            // GetMessageW is 0 on WM_QUIT and -1 on errors, either way we're done.
+            while unsafe { GetMessageW(&mut msg, window, 0, 0) }.0 > 0 {
+                unsafe {
+                    TranslateMessage(&msg);
+                    DispatchMessageW(&msg);
+                }
+            }
+            warn!("Stopped getting session lock notifications");
+        });
+
+    if let Err(e) = spawned {
+        warn!("Couldn't spawn session watcher thread! {e:?}");
+        return false;
+    }
+
+    started_rx.recv().unwrap_or(false)
+}
+
+fn create_session_window() -> Result<HWND, Error> {
+    let instance = unsafe { GetModuleHandleW(PCWSTR::null()) }?;
+    let class_name = w!("ompd-session-watcher");
+
+    let class = WNDCLASSW {
+        lpfnWndProc: Some(session_window_proc),
+        hInstance: instance,
+        lpszClassName: class_name,
+        ..Default::default()
+    };
+    if unsafe { RegisterClassW(&class) } == 0 {
+        return Err(windows::core::Error::from_win32().into());
+    }
+
+    // A message-only window, it never shows up anywhere.
+    let window = unsafe {
+        CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            class_name,
+            w!(""),
+            WINDOW_STYLE::default(),
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            HMENU::default(),
+            instance,
+            None,
+        )
+    };
+    if window.0 == 0 {
+        return Err(windows::core::Error::from_win32().into());
+    }
+
+    if !unsafe { WTSRegisterSessionNotification(window, NOTIFY_FOR_THIS_SESSION) }.as_bool() {
+        return Err(windows::core::Error::from_win32().into());
+    }
+
+    Ok(window)
+}
+
+unsafe extern "system" fn session_window_proc(
+    window: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if msg == WM_WTSSESSION_CHANGE {
+        match wparam.0 as u32 {
+            WTS_SESSION_LOCK => {
+                info!("Workstation locked");
+                SESSION_LOCKED.store(true, Ordering::SeqCst);
+            }
+            WTS_SESSION_UNLOCK => {
+                info!("Workstation unlocked");
+                SESSION_LOCKED.store(false, Ordering::SeqCst);
+            }
+            _ => {}
+        }
+        return LRESULT(0);
+    }
+
+    DefWindowProcW(window, msg, wparam, lparam)
+}
+
+pub fn idle_time() -> Result<Duration, Error> {
+    let mut last_input = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+
+    if !unsafe { GetLastInputInfo(&mut last_input) }.as_bool() {
+        return Err(anyhow!("GetLastInputInfo failed"));
+    }
+
+    // Both of these are milliseconds since boot, and wrap around after ~49 days.
+    let now = unsafe { GetTickCount() };
+    Ok(Duration::from_millis(
+        now.wrapping_sub(last_input.dwTime) as u64
+    ))
+}
+
+pub fn foreground_app() -> Result<String, Error> {
+    let window = unsafe { GetForegroundWindow() };
+    if window.0 == 0 {
+        return Err(anyhow!("Nothing is in the foreground?!"));
+    }
+
+    let mut pid = 0u32;
+    unsafe { GetWindowThreadProcessId(window, Some(&mut pid)) };
+
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }?;
+    let mut exe_path = [0u16; 1024];
+    let mut exe_path_len = exe_path.len() as u32;
+    let found = unsafe {
+        QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(exe_path.as_mut_ptr()),
+            &mut exe_path_len,
+        )
+    };
+    unsafe { CloseHandle(process) };
+    found.ok()?;
+
+    // Just "KeePassXC" out of "C:\Program Files\KeePassXC\KeePassXC.exe"
+    let exe_path = String::from_utf16_lossy(&exe_path[..exe_path_len as usize]);
+    Ok(Path::new(&exe_path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or(exe_path))
+}
+
+pub fn foreground_window_title() -> Result<String, Error> {
+    let window = unsafe { GetForegroundWindow() };
+    if window.0 == 0 {
+        return Err(anyhow!("Nothing is in the foreground?!"));
+    }
+
+    let mut title = [0u16; 512];
+    let title_len = unsafe { GetWindowTextW(window, &mut title) };
+    if title_len <= 0 {
+        return Err(anyhow!("Foreground window doesn't have a title"));
+    }
+
+    Ok(String::from_utf16_lossy(&title[..title_len as usize]))
+}
+
+pub fn window_titles() -> Result<Vec<String>, Error> {
+    unsafe extern "system" fn collect(window: HWND, titles: LPARAM) -> BOOL {
+        let titles = &mut *(titles.0 as *mut Vec<String>);
+
+        if IsWindowVisible(window).as_bool() {
+            let mut title = [0u16; 512];
+            let title_len = GetWindowTextW(window, &mut title);
+            if title_len > 0 {
+                titles.push(String::from_utf16_lossy(&title[..title_len as usize]));
+            }
+        }
+
+        true.into()
+    }
+
+    let mut titles: Vec<String> = Vec::new();
+    unsafe {
+        EnumWindows(
+            Some(collect),
+            LPARAM(&mut titles as *mut Vec<String> as isize),
+        )
+    }
+    .ok()?;
+
+    Ok(titles)
+}
+
+pub fn on_battery() -> Result<bool, Error> {
+    let mut status = SYSTEM_POWER_STATUS::default();
+    unsafe { GetSystemPowerStatus(&mut status) }.ok()?;
+
+    // ACLineStatus is 0 when unplugged, SystemStatusFlag is 1 when battery saver is on.
+    Ok(status.ACLineStatus == 0 || status.SystemStatusFlag == 1)
 }
@@ -0,0 +1,303 @@
+use anyhow::{anyhow, Error};
+use image::{Rgba, RgbaImage};
+use log::{debug, warn};
+use screenshots::Screen;
+use std::cell::RefCell;
+use std::ptr;
+use std::time::Duration;
+use xcb::{screensaver, shm, x};
+
+use super::backend::CaptureBackend;
+
+/*
+Grabbing the root window with a plain GetImage means the X server serializes the whole frame down
+the socket to us every time, which is where most of the CPU goes. With MIT-SHM the server writes
+straight into a shared memory segment we keep around between captures instead.
+
+Idle time and what's in front get asked about every frame, so those share one connection per
+thread (which is to say, the capturer's) rather than connecting all over again each time.
+*/
+
+thread_local! {
+    static SESSION: RefCell<Option<(xcb::Connection, x::Window)>> = const { RefCell::new(None) };
+}
+
+struct Segment {
+    seg: shm::Seg,
+    addr: *mut libc::c_void,
+    size: usize,
+}
+
+pub struct XShmBackend {
+    conn: xcb::Connection,
+    root: x::Window,
+    segment: Option<Segment>,
+}
+
+impl XShmBackend {
+    pub fn new() -> Result<XShmBackend, Error> {
+        let (conn, root) = connect_to_root(&[xcb::Extension::Shm], &[])?;
+
+        debug!("Connected to X server for XShm capture");
+        Ok(XShmBackend {
+            conn,
+            root,
+            segment: None,
+        })
+    }
+
+    fn segment_of_at_least(&mut self, size: usize) -> Result<&Segment, Error> {
+        let big_enough = matches!(&self.segment, Some(s) if s.size >= size);
+
+        if !big_enough {
+            self.release_segment();
+
+            let shmid = unsafe { libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600) };
+            if shmid < 0 {
+                return Err(anyhow!(
+                    "Couldn't allocate shared memory: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+
+            let addr = unsafe { libc::shmat(shmid, ptr::null(), libc::SHM_RDONLY) };
+
+            // Mark it for removal right away, it'll stick around until both of us detach.
+            unsafe { libc::shmctl(shmid, libc::IPC_RMID, ptr::null_mut()) };
+
+            if addr as isize == -1 {
+                return Err(anyhow!(
+                    "Couldn't attach shared memory: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+
+            let seg = self.conn.generate_id();
+            let attached = self.conn.send_and_check_request(&shm::Attach {
+                shmseg: seg,
+                shmid: shmid as u32,
+                read_only: false,
+            });
+
+            if let Err(e) = attached {
+                unsafe { libc::shmdt(addr) };
+                return Err(e.into());
+            }
+
+            debug!("Attached a {size} byte shared memory segment");
+            self.segment = Some(Segment { seg, addr, size });
+        }
+
+        Ok(self.segment.as_ref().unwrap())
+    }
+
+    fn release_segment(&mut self) {
+        if let Some(old) = self.segment.take() {
+            if let Err(e) = self
+                .conn
+                .send_and_check_request(&shm::Detach { shmseg: old.seg })
+            {
+                warn!("Couldn't detach old shared memory segment: {e:?}");
+            }
+            unsafe { libc::shmdt(old.addr) };
+        }
+    }
+}
+
+impl Drop for XShmBackend {
+    fn drop(&mut self) {
+        self.release_segment();
+    }
+}
+
+impl CaptureBackend for XShmBackend {
+    fn name(&self) -> &'static str {
+        "x11"
+    }
+
+    fn capture(&mut self, screen: &Screen) -> Result<RgbaImage, Error> {
+        let info = screen.display_info;
+        let x = (info.x as f32 * info.scale_factor) as i16;
+        let y = (info.y as f32 * info.scale_factor) as i16;
+        let width = (info.width as f32 * info.scale_factor) as u32;
+        let height = (info.height as f32 * info.scale_factor) as u32;
+        let size = (width * height * 4) as usize;
+
+        let root = self.root;
+        let (seg, addr) = {
+            let segment = self.segment_of_at_least(size)?;
+            (segment.seg, segment.addr)
+        };
+
+        let cookie = self.conn.send_request(&shm::GetImage {
+            drawable: x::Drawable::Window(root),
+            x,
+            y,
+            width: width as u16,
+            height: height as u16,
+            plane_mask: u32::MAX,
+            format: x::ImageFormat::ZPixmap as u8,
+            shmseg: seg,
+            offset: 0,
+        });
+        let reply = self.conn.wait_for_reply(cookie)?;
+
+        if reply.depth() < 24 {
+            return Err(anyhow!(
+                "Don't know how to handle a {} bit deep screen",
+                reply.depth()
+            ));
+        }
+
+        // ZPixmap at 24/32 bits deep is BGRx, one u32 per pixel.
+        let bgrx = unsafe { std::slice::from_raw_parts(addr as *const u8, size) };
+        let mut img = RgbaImage::new(width, height);
+        for (pixel, raw) in img.pixels_mut().zip(bgrx.chunks_exact(4)) {
+            *pixel = Rgba([raw[2], raw[1], raw[0], 255]);
+        }
+
+        Ok(img)
+    }
+}
+
+fn connect_to_root(
+    mandatory: &[xcb::Extension],
+    optional: &[xcb::Extension],
+) -> Result<(xcb::Connection, x::Window), Error> {
+    let (conn, screen_num) = xcb::Connection::connect_with_extensions(None, mandatory, optional)?;
+
+    let root = conn
+        .get_setup()
+        .roots()
+        .nth(screen_num as usize)
+        .ok_or_else(|| anyhow!("X server doesn't have screen {screen_num}?!"))?
+        .root();
+
+    Ok((conn, root))
+}
+
+/// Run `f` with this thread's connection, making it first if need be.
+fn with_session<T>(
+    f: impl FnOnce(&xcb::Connection, x::Window) -> Result<T, Error>,
+) -> Result<T, Error> {
+    SESSION.with(|session| {
+        let mut session = session.borrow_mut();
+        if session.is_none() {
+            *session = Some(connect_to_root(&[], &[xcb::Extension::ScreenSaver])?);
+            debug!("Connected to X server for idle time and windows");
+        }
+
+        let (conn, root) = session.as_ref().unwrap();
+        let result = f(conn, *root);
+        // The X server went away (or restarted), connect again next time.
+        if conn.has_error().is_err() {
+            *session = None;
+        }
+        result
+    })
+}
+
+fn get_property(
+    conn: &xcb::Connection,
+    window: x::Window,
+    name: &str,
+    r#type: x::Atom,
+    long_length: u32,
+) -> Result<x::GetPropertyReply, Error> {
+    let atom = conn
+        .wait_for_reply(conn.send_request(&x::InternAtom {
+            only_if_exists: true,
+            name: name.as_bytes(),
+        }))?
+        .atom();
+
+    Ok(conn.wait_for_reply(conn.send_request(&x::GetProperty {
+        delete: false,
+        window,
+        property: atom,
+        r#type,
+        long_offset: 0,
+        long_length,
+    }))?)
+}
+
+pub fn idle_time() -> Result<Duration, Error> {
+    with_session(|conn, root| {
+        if !conn
+            .active_extensions()
+            .any(|e| e == xcb::Extension::ScreenSaver)
+        {
+            return Err(anyhow!(
+                "X server doesn't have the MIT-SCREEN-SAVER extension"
+            ));
+        }
+
+        let cookie = conn.send_request(&screensaver::QueryInfo {
+            drawable: x::Drawable::Window(root),
+        });
+        let reply = conn.wait_for_reply(cookie)?;
+
+        Ok(Duration::from_millis(reply.ms_since_user_input() as u64))
+    })
+}
+
+fn active_window(conn: &xcb::Connection, root: x::Window) -> Result<x::Window, Error> {
+    let active = get_property(conn, root, "_NET_ACTIVE_WINDOW", x::ATOM_WINDOW, 1)?;
+    active
+        .value::<x::Window>()
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow!("Window manager didn't tell us the active window"))
+}
+
+pub fn foreground_app() -> Result<String, Error> {
+    with_session(|conn, root| {
+        let window = active_window(conn, root)?;
+
+        // WM_CLASS is "instance\0class\0", the class is the nicer looking one (e.g. "KeePassXC").
+        let class = get_property(conn, window, "WM_CLASS", x::ATOM_STRING, 1024)?;
+        let class_name = class
+            .value::<u8>()
+            .split(|b| *b == 0)
+            .rfind(|part| !part.is_empty())
+            .ok_or_else(|| anyhow!("Active window doesn't have a WM_CLASS"))?;
+
+        Ok(String::from_utf8_lossy(class_name).to_string())
+    })
+}
+
+fn window_title(conn: &xcb::Connection, window: x::Window) -> Result<Option<String>, Error> {
+    // _NET_WM_NAME is UTF-8, WM_NAME is whatever the app felt like. Prefer the former.
+    for name in ["_NET_WM_NAME", "WM_NAME"] {
+        let title = get_property(conn, window, name, x::ATOM_ANY, 1024)?;
+        if !title.value::<u8>().is_empty() {
+            return Ok(Some(
+                String::from_utf8_lossy(title.value::<u8>()).to_string(),
+            ));
+        }
+    }
+
+    Ok(None)
+}
+
+pub fn foreground_window_title() -> Result<String, Error> {
+    with_session(|conn, root| {
+        let window = active_window(conn, root)?;
+        window_title(conn, window)?.ok_or_else(|| anyhow!("Active window doesn't have a title"))
+    })
+}
+
+pub fn window_titles() -> Result<Vec<String>, Error> {
+    with_session(|conn, root| {
+        let clients = get_property(conn, root, "_NET_CLIENT_LIST", x::ATOM_WINDOW, 4096)?;
+        let mut titles = Vec::new();
+        for window in clients.value::<x::Window>() {
+            // Windows can disappear between listing them and asking about them, that's fine.
+            if let Ok(Some(title)) = window_title(conn, *window) {
+                titles.push(title);
+            }
+        }
+
+        Ok(titles)
+    })
+}
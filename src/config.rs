@@ -1,126 +1,2188 @@
+use anyhow::{anyhow, Error};
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
 use core::panic;
 use home::home_dir;
-use log::{debug, error, warn};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::collections::HashSet;
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use which::which;
 
-use crate::movie_maker::MovieMaker;
+mod secs;
+
+use crate::capturer::{self, backend, webcam};
+use crate::dir_manager::{Codec, DirManager};
+use crate::movie_maker::{
+    MovieMaker, BUILTIN_VIDEO_TYPE, MONITOR_LAYOUTS, QUALITY_PRESETS, SHARE_VIDEO_TYPE,
+    SUBTITLE_CODECS, VALID_ROTATIONS,
+};
+use crate::video_name::{self, VideoName};
+
+/// Settings from the command line. They win over the config file, but never get written to it.
+#[derive(Clone, Debug, Default)]
+pub struct Overrides {
+    /// Read the config from here instead of the usual place.
+    pub config_path: Option<PathBuf>,
+    pub interval: Option<u64>,
+    pub shot_output_dir: Option<String>,
+    pub vid_output_dir: Option<String>,
+    pub no_backfill: bool,
+}
+
+static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+
+/// What configs look like now. Ones from older versions of ompd get upgraded when they're read.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Fields that got a new name, with the config_version they got it in and their old name.
+const RENAMED_FIELDS: &[(u32, &str, &str)] = &[];
+
+/// Brings `fields` from an older config up to CONFIG_VERSION, filling in any it doesn't have from
+/// `defaults`. Says which version it was, if anything needed doing.
+fn upgrade(fields: &mut Map<String, Value>, defaults: &Map<String, Value>) -> Option<u32> {
+    // Before there were versions.
+    let version = fields
+        .get("config_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32;
+    let missing = defaults.keys().any(|field| !fields.contains_key(field));
+    if version >= CONFIG_VERSION && !missing {
+        return None;
+    }
+
+    for (renamed_in, old, new) in RENAMED_FIELDS {
+        if version < *renamed_in {
+            if let Some(value) = fields.remove(*old) {
+                fields.entry(new.to_string()).or_insert(value);
+            }
+        }
+    }
+    for (field, value) in defaults {
+        fields.entry(field.clone()).or_insert_with(|| value.clone());
+    }
+    fields.insert("config_version".into(), version.max(CONFIG_VERSION).into());
+    Some(version)
+}
+
+/// `fields` with what serde fills in for any that are missing. Never new_config's, which goes by
+/// what's on this machine (like whether ffmpeg is on the PATH) and could disagree with what's set.
+fn serde_defaults(fields: &Map<String, Value>) -> Result<Map<String, Value>, Error> {
+    let config: Config = serde_json::from_value(Value::Object(fields.clone()))?;
+    match serde_json::to_value(config)? {
+        Value::Object(defaults) => Ok(defaults),
+        _ => Ok(Map::new()),
+    }
+}
+
+/// Whether `field`, unset in `defaults`, is one that holds text, going by whether it'll take `raw`
+/// as it is.
+fn takes_text(defaults: &Map<String, Value>, field: &str, raw: &str) -> bool {
+    let mut fields = defaults.clone();
+    fields.insert(field.to_string(), Value::String(raw.to_string()));
+    serde_json::from_value::<Config>(Value::Object(fields)).is_ok()
+}
+
+/// Environment variables named this and then a field, like OMPD_INTERVAL or OMPD_SHOT_OUTPUT_DIR,
+/// win over the config file, and the command line wins over them. Values are JSON, except for
+/// fields that hold text, which take them as they are.
+const ENV_PREFIX: &str = "OMPD_";
+
+/// For when there's no screen to go by.
+const FALLBACK_VID_SIZE: (u32, u32) = (860, 360);
+
+/// New configs get movies this tall, and as wide as keeps the primary screen's shape.
+const DEFAULT_VID_HEIGHT: u32 = 360;
+
+fn default_vid_size() -> (u32, u32) {
+    match capturer::primary_screen_size() {
+        Some((width, height)) if width > 0 && height > 0 => {
+            let aspect = width as f32 / height as f32;
+            let vid_width = ((DEFAULT_VID_HEIGHT as f32 * aspect).round() as u32).max(2) & !1;
+            (vid_width, DEFAULT_VID_HEIGHT)
+        }
+        _ => FALLBACK_VID_SIZE,
+    }
+}
+
+/// Why files couldn't go in `dir`, if they couldn't. Only looks, so checking a config never
+/// leaves anything behind.
+fn check_writable(dir: &Path) -> Option<String> {
+    let metadata = match std::fs::metadata(dir) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Some("it doesn't exist".to_string())
+        }
+        Err(e) => return Some(e.to_string()),
+    };
+    if !metadata.is_dir() {
+        return Some("it isn't a directory".to_string());
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let Ok(c_dir) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+            return Some("it has a NUL in its name".to_string());
+        };
+        // What the kernel thinks of us in particular, the mode bits alone don't say.
+        if unsafe { libc::access(c_dir.as_ptr(), libc::W_OK | libc::X_OK) } != 0 {
+            return Some(std::io::Error::last_os_error().to_string());
+        }
+    }
+    #[cfg(not(unix))]
+    if metadata.permissions().readonly() {
+        return Some("it's read only".to_string());
+    }
+
+    None
+}
+
+/// Where the config used to live, before it moved to the platform's config directory.
+const LEGACY_CONFIG_FILE: &str = ".ompd-config.json";
+
+/// ~/.config/ompd on Linux, %APPDATA%\ompd on Windows, ~/Library/Application Support/ompd on macOS.
+fn ompd_config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("ompd"))
+}
+
+/// Where the config lives when the command line doesn't say, moving it there from
+/// ~/.ompd-config.json the first time.
+fn default_config_path(home: &Path) -> PathBuf {
+    let legacy = home.join(LEGACY_CONFIG_FILE);
+    let Some(config_path) = ompd_config_dir().map(|dir| dir.join("config.json")) else {
+        return legacy;
+    };
+    if !legacy.is_file() {
+        return config_path;
+    }
+    if config_path.exists() {
+        warn!("Using {config_path:?}, {legacy:?} is left over from before and can go");
+        return config_path;
+    }
+
+    info!("Moving {legacy:?} to {config_path:?}");
+    let moved = config_path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| {
+            // A rename won't work across filesystems.
+            std::fs::rename(&legacy, &config_path).or_else(|_| {
+                std::fs::copy(&legacy, &config_path)?;
+                std::fs::remove_file(&legacy)
+            })
+        });
+    match moved {
+        Ok(_) => config_path,
+        Err(e) => {
+            warn!("Couldn't move the config, sticking with {legacy:?}: {e}");
+            legacy
+        }
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
+    /// What the config looked like when it was last saved, see CONFIG_VERSION.
+    #[serde(default)]
+    pub config_version: u32,
+    /// Seconds between shots. This and the rest of the seconds can also be like "20s" or "3m".
+    #[serde(deserialize_with = "secs::deserialize")]
     pub interval: u64,
+    #[serde(deserialize_with = "secs::deserialize")]
     pub max_sleep_secs: i64,
+    /// Hour (0-23) a new day starts at, so working past midnight still counts as the day before.
+    #[serde(default)]
+    pub day_starts_at_hour: u32,
     pub shot_output_dir: String,
+    /// Somewhere local to hold on to shots while shot_output_dir (say, on a NAS) isn't writable.
+    /// They get moved over once it's back.
+    #[serde(default)]
+    pub shot_spool_dir: Option<String>,
+    /// Put everything under a directory named for this machine, for when several machines share
+    /// (or sync) the same shot_output_dir and vid_output_dir.
+    #[serde(default)]
+    pub per_host_dirs: bool,
+    /// With per_host_dirs, backfill movies for every machine instead of just this one.
+    #[serde(default)]
+    pub backfill_all_hosts: bool,
+    /// Only log which days the backfiller would make movies of, and roughly what would come out,
+    /// without making any. Nothing gets expired or uploaded either.
+    #[serde(default)]
+    pub backfill_dry_run: bool,
+    /// Have the backfiller make movies again if they were made with a different size, frame
+    /// rate, codec, CRF, or video_type than the config says now. Movies from before ompd kept
+    /// track count as different.
+    #[serde(default)]
+    pub backfill_reencode: bool,
+    /// Only backfill days from this one on, e.g. "2021-01-01", for working through a big archive
+    /// a chunk at a time.
+    #[serde(default)]
+    pub backfill_since: Option<NaiveDate>,
+    /// Only backfill days up to and including this one.
+    #[serde(default)]
+    pub backfill_until: Option<NaiveDate>,
+    /// How many days the backfiller makes movies of at once. Each one is its own ffmpeg, so more
+    /// than the number of cores won't help.
+    #[serde(default = "default_backfill_jobs")]
+    pub backfill_jobs: u32,
+    /// Run the backfiller's ffmpegs at low CPU (and on Linux, disk) priority, so a big backfill
+    /// doesn't make the machine hard to use.
+    #[serde(default = "default_backfill_low_priority")]
+    pub backfill_low_priority: bool,
+    /// How many threads each of the backfiller's ffmpegs can encode with. ffmpeg picks otherwise.
+    #[serde(default)]
+    pub backfill_ffmpeg_threads: Option<u32>,
+    /// Hold off on starting each backfilled day until there's been no keyboard or mouse input for
+    /// this many seconds, so the backfill mostly happens while nobody's at the computer.
+    #[serde(default, deserialize_with = "secs::deserialize_opt")]
+    pub backfill_when_idle_secs: Option<u64>,
     pub vid_output_dir: String,
     pub ffmpeg: String,
     pub handle_old_dirs_on_startup: bool,
     pub vid_width: u32,
     pub vid_height: u32,
+    /// Size movies to this fraction of the primary screen instead, e.g. 0.5 for half size.
+    #[serde(default)]
+    pub vid_scale_factor: Option<f32>,
     pub shot_type: String,
     pub compress_shots: bool,
+    /// Once the movie is made, pack the day's shots into one YYYY-MM-DD.tar.zst instead.
+    #[serde(default)]
+    pub archive_shots: bool,
+    /// Get rid of a day's shots some time after its movie is made. Movies are kept forever.
+    #[serde(default)]
+    pub shot_retention: Option<ShotRetention>,
+    /// How hard to squeeze shots when compressing or archiving them.
+    #[serde(default)]
+    pub compression: Compression,
     pub video_type: String,
+    /// What to call each day's movie. Can use {hostname}, {year}, {month}, {day}, and {ext}, and
+    /// has to end in .{ext}.
+    #[serde(default = "default_video_name_template")]
+    pub video_name_template: String,
+    /// How frames that aren't the movie's shape get fit into it.
+    #[serde(default)]
+    pub video_fit: VideoFit,
+    /// Put the other monitors' frames (from capture_monitors "all") in the movie too, next to the
+    /// main one's: "hstack" for side by side, or "vstack" for on top of each other. Goes best
+    /// with a video_fit mode of "contain". Needs the ffmpeg video_backend.
+    #[serde(default)]
+    pub monitor_layout: Option<String>,
+    /// Degrees clockwise to turn frames by in movies, for a monitor on its side: 0, 90, 180, or
+    /// 270. Shots are stored as captured, and a day's manifest.json can set its own "rotation".
+    #[serde(default)]
+    pub video_rotation: u16,
+    /// "even" to show every frame for the same time, or "captured" to go by when they were
+    /// actually captured, so slower stretches of capturing take up more of the movie. Needs the
+    /// ffmpeg video_backend.
+    #[serde(default = "default_video_timing")]
+    pub video_timing: String,
+    /// Play stretches where nothing changes, like filler for time away or a screen nobody's
+    /// touching, this many times faster than the rest.
+    #[serde(default)]
+    pub gap_speedup: Option<u32>,
+    /// Leave filler for time away out of movies entirely, so they're only what actually happened.
+    /// Wins out over gap_speedup.
+    #[serde(default)]
+    pub skip_gaps: bool,
+    /// With skip_gaps, keep the one frame saying how long each gap was.
+    #[serde(default)]
+    pub keep_gap_cards: bool,
+    /// Leave out frames that look the same as the last one kept, so a day spent in one
+    /// spreadsheet doesn't drag. Goes on top of skip_gaps or gap_speedup.
+    #[serde(default)]
+    pub drop_static: Option<DropStatic>,
+    /// "auto" to use a hardware encoder if there's one around, "software" to never, or the name
+    /// of any ffmpeg encoder (e.g. "h264_nvenc").
+    #[serde(default = "default_video_encoder")]
+    pub video_encoder: String,
+    /// "ffmpeg", or "builtin" to make AV1 .ivf movies without needing ffmpeg at all.
+    #[serde(default = "default_video_backend")]
+    pub video_backend: String,
+    /// Software encoder to use instead of ffmpeg's default, e.g. "libx265". Setting this skips
+    /// hardware auto detection.
+    #[serde(default)]
+    pub video_codec: Option<String>,
+    /// Encoders to try, in order, if the one picked above doesn't work on this machine.
+    #[serde(default = "default_video_encoder_fallbacks")]
+    pub video_encoder_fallbacks: Vec<String>,
+    /// "tiny", "share", or "archive", to pick a video_codec, video_crf, and video_preset (for mp4,
+    /// mkv, mov, and m4v movies) and a video_fps that go together, instead of setting them one by
+    /// one. Any of those set by hand still win. tiny also halves vid_width and vid_height.
+    #[serde(default)]
+    pub quality_preset: Option<String>,
+    /// Frames per second for movies. Defaults to 27, a minute for 9 hours of shots 20s apart.
+    #[serde(default)]
+    pub video_fps: Option<u32>,
+    /// Make every movie this long, however many frames there are. Overrides video_fps.
+    #[serde(default)]
+    pub target_video_seconds: Option<u32>,
+    /// Constant rate factor for software encoders, lower is better looking and bigger (0-51).
+    #[serde(default)]
+    pub video_crf: Option<u8>,
+    /// Encoder preset for software encoders, e.g. "veryslow" for smaller files or "ultrafast".
+    #[serde(default)]
+    pub video_preset: Option<String>,
+    /// Target bitrate, e.g. "2M". Works with hardware encoders too.
+    #[serde(default)]
+    pub video_bitrate: Option<String>,
+    /// Aim for movies about this many MB, e.g. to fit under an attachment limit. Software
+    /// encoders do two passes to get there. Overrides video_crf and video_bitrate.
+    #[serde(default)]
+    pub video_target_mb: Option<f64>,
+    /// Encode long movies this many pieces at once and join them up after, for machines with
+    /// cores to spare. Mostly helps backfills, which otherwise make one movie at a time.
+    #[serde(default)]
+    pub video_segments: Option<u32>,
+    /// Days with fewer frames than this don't get a movie, like when the laptop was only opened
+    /// for a minute.
+    #[serde(default = "default_min_frames_for_video")]
+    pub min_frames_for_video: u64,
+    /// Extra arguments for ffmpeg, before the frames are read in.
+    #[serde(default)]
+    pub ffmpeg_input_args: Vec<String>,
+    /// Extra arguments for ffmpeg, right before the output file, e.g. ["-tag:v", "hvc1"].
+    #[serde(default)]
+    pub ffmpeg_output_args: Vec<String>,
+    /// Audio to play under movies, or a directory to pick some from each day. Gets looped or cut
+    /// off to fit.
+    #[serde(default)]
+    pub background_audio: Option<String>,
+    /// Once a year is over, make one movie out of a slice of every day's.
+    #[serde(default)]
+    pub year_review: Option<YearReview>,
+    /// Once a month is over, make a calendar of it with every day's movie playing at once.
+    #[serde(default)]
+    pub month_montage: Option<MonthMontage>,
+    #[serde(default = "default_capture_backend")]
+    pub capture_backend: String,
+    /// Encoder quality for lossy shot types (avif, jpeg, webp), 1-100.
+    #[serde(default = "default_shot_quality")]
+    pub shot_quality: u8,
+    /// Only applies to webp shots, ignores shot_quality.
+    #[serde(default)]
+    pub shot_lossless: bool,
+    /// Only applies to avif shots, 1 (smallest files, slowest) to 10 (fastest).
+    #[serde(default = "default_shot_speed")]
+    pub shot_speed: u8,
+    /// Encrypt frames on disk, with the key in encryption_key_file (made for you if it's missing).
+    #[serde(default)]
+    pub encrypt_shots: bool,
+    #[serde(default = "default_encryption_key_file")]
+    pub encryption_key_file: String,
+    #[serde(default, deserialize_with = "secs::deserialize_opt")]
+    pub pause_when_idle_secs: Option<u64>,
+    /// Only capture during these hours, e.g. weekdays from 08:00 to 18:00. The day's movie gets
+    /// made as soon as it's over.
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
+    /// Holidays, time off, and other days not to capture or make movies of, e.g. ["2024-07-04"].
+    #[serde(default)]
+    pub days_off: Vec<NaiveDate>,
+    /// More days off, from the all-day events in this calendar file.
+    #[serde(default)]
+    pub days_off_ics: Option<String>,
+    #[serde(default)]
+    pub app_blacklist: Vec<String>,
+    /// What to do when a blacklisted app is in the foreground: "skip" or "blackout".
+    #[serde(default = "default_app_blacklist_action")]
+    pub app_blacklist_action: String,
+    /// Don't capture while it looks like we're in a meeting or sharing the screen.
+    #[serde(default = "default_pause_during_meetings")]
+    pub pause_during_meetings: bool,
+    /// Window titles (case insensitive substrings) that mean a meeting or screen share.
+    #[serde(default = "default_meeting_window_patterns")]
+    pub meeting_window_patterns: Vec<String>,
+    #[serde(default)]
+    pub redact_regions: Vec<RedactRegion>,
+    /// Shrink frames by this much before writing them, e.g. 0.5 for half size.
+    #[serde(default = "default_capture_scale")]
+    pub capture_scale: f32,
+    /// Fix up washed out captures from an HDR display.
+    #[serde(default)]
+    pub hdr_tone_map: bool,
+    /// "primary" for just the main monitor, or "all" to also keep every other monitor's frames,
+    /// numbered left to right, in monitor-1, monitor-2... under the day. See monitor_layout.
+    #[serde(default = "default_capture_monitors")]
+    pub capture_monitors: String,
+    #[serde(default)]
+    pub timestamp_overlay: Option<TimestampOverlay>,
+    /// Like timestamp_overlay, but drawn onto the movie by ffmpeg instead of into every shot.
+    #[serde(default)]
+    pub video_clock: Option<TimestampOverlay>,
+    /// A subtitle track saying when each frame was captured and what was in front, for players to
+    /// show or hide instead of having a clock burned in. mkv, mp4, mov, and m4v movies only.
+    #[serde(default)]
+    pub video_subtitles: Option<VideoSubtitles>,
+    /// A card with the date on it at the start of each movie. Looks like filler_frame.
+    #[serde(default)]
+    pub title_card: Option<TitleCard>,
+    /// Also make a small H.264 (or H.265) copy of each movie for sharing, named like it but
+    /// ending in .share.mp4. Uploads send the share copy, and the movie itself becomes the master
+    /// to keep, made as if quality_preset were "archive" unless it says otherwise.
+    #[serde(default)]
+    pub share_copy: Option<ShareCopy>,
+    /// An image to put over every movie, e.g. a logo for ones that get published.
+    #[serde(default)]
+    pub watermark: Option<Watermark>,
+    /// Save a still from each movie next to it, as a .jpg with the same name.
+    #[serde(default = "default_video_poster")]
+    pub video_poster: bool,
+    #[serde(default)]
+    pub filler_frame: FillerFrame,
+    #[serde(default)]
+    pub webcam_overlay: Option<WebcamOverlay>,
+    /// Stop capturing when there's less than this much free space for shots, 0 to never stop.
+    #[serde(default = "default_min_free_disk_mb")]
+    pub min_free_disk_mb: u64,
+    #[serde(default)]
+    pub s3_upload: Option<S3Upload>,
+    #[serde(default)]
+    pub sftp_upload: Option<SftpUpload>,
+    #[serde(default)]
+    pub webdav_upload: Option<WebDavUpload>,
+    /// How many times to try each upload before leaving it for next startup.
+    #[serde(default = "default_upload_attempts")]
+    pub upload_attempts: u32,
+    #[serde(default)]
+    pub capture_retry: CaptureRetry,
+    /// What to change while running on battery (or in a low power mode).
+    #[serde(default)]
+    pub on_battery: Option<OnBattery>,
+    /// Capture more often when the screen is busy and less when it isn't, instead of every interval.
+    #[serde(default)]
+    pub adaptive_interval: Option<AdaptiveInterval>,
+}
+
+/// Somewhere S3 compatible to send finished movies.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct S3Upload {
+    /// e.g. "https://s3.us-east-1.amazonaws.com" or "http://nas.local:9000"
+    pub endpoint: String,
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    pub bucket: String,
+    /// Put in front of the file name to make the object key, e.g. "ompd/"
+    #[serde(default)]
+    pub prefix: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+/// An SSH server to send finished movies to, via the system's sftp.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SftpUpload {
+    /// Anything ssh understands, e.g. "me@nas.local" or a Host from ~/.ssh/config
+    pub host: String,
+    pub remote_dir: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub identity_file: Option<String>,
+    #[serde(default = "default_sftp")]
+    pub sftp: String,
+}
+
+/// A WebDAV collection to send finished movies to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebDavUpload {
+    /// e.g. "https://cloud.example.com/remote.php/dav/files/me/ompd"
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DropStatic {
+    /// How different a frame has to look from the last one kept to stay in, as the average
+    /// difference per pixel out of 255. 0 only leaves out exact repeats.
+    #[serde(default = "default_drop_static_threshold")]
+    pub threshold: f32,
+    /// Keep at least this many frames for each minute of capturing, however little changed, so
+    /// long quiet stretches don't vanish entirely. 0 for no minimum.
+    #[serde(default = "default_drop_static_min_per_minute")]
+    pub min_per_minute: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShotRetention {
+    /// How many days to hang on to shots after their movie exists.
+    pub keep_days: u32,
+    /// "delete" or "archive"
+    #[serde(default = "default_shot_retention_action")]
+    pub action: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoFit {
+    /// "stretch" to fill the movie no matter what, "contain" to show all of it with bars around
+    /// it, or "cover" to fill the movie and crop off whatever doesn't fit.
+    pub mode: String,
+    /// Where it sits between the bars, or what's kept when cropping: "center", "top", "bottom",
+    /// "left", "right", or a corner like "top-left".
+    pub align: String,
+    /// RGB, for the bars.
+    pub pad_color: [u8; 3],
+}
+
+impl VideoFit {
+    /// How far across and down the picture sits in whatever room there is, from 0 to 1.
+    pub fn alignment(&self) -> (f32, f32) {
+        alignment(&self.align)
+    }
+}
+
+/// How far across and down something placed at `align` (e.g. "top-left") sits, from 0 to 1.
+pub fn alignment(align: &str) -> (f32, f32) {
+    let across = match align {
+        a if a.ends_with("left") => 0.0,
+        a if a.ends_with("right") => 1.0,
+        _ => 0.5,
+    };
+    let down = match align {
+        a if a.starts_with("top") => 0.0,
+        a if a.starts_with("bottom") => 1.0,
+        _ => 0.5,
+    };
+    (across, down)
+}
+
+impl Default for VideoFit {
+    fn default() -> Self {
+        VideoFit {
+            mode: "stretch".to_string(),
+            align: "center".to_string(),
+            pad_color: [0, 0, 0],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct YearReview {
+    /// How much of each day's movie to use.
+    #[serde(default = "default_year_review_seconds")]
+    pub seconds_per_day: f64,
+    /// "start" for the beginning of each day, or "busiest" for where the most was going on.
+    #[serde(default = "default_year_review_pick")]
+    pub pick: String,
+    /// What to call the review, like video_name_template but with just {year} for a date.
+    #[serde(default = "default_year_review_name_template")]
+    pub name_template: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonthMontage {
+    /// How long the montage is. Days' movies that are shorter hold on their last frame.
+    #[serde(default = "default_month_montage_seconds")]
+    pub seconds: f64,
+    /// "monday" or "sunday", for the first column of the calendar.
+    #[serde(default = "default_month_montage_week_starts")]
+    pub week_starts: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShareCopy {
+    /// Any ffmpeg encoder that goes in an mp4, e.g. "libx265" for smaller files that don't play
+    /// everywhere.
+    #[serde(default = "default_share_codec")]
+    pub codec: String,
+    /// Constant rate factor, lower is better looking and bigger (0-51).
+    #[serde(default = "default_share_crf")]
+    pub crf: u8,
+    /// Shrink it to this height, keeping its shape. Defaults to the same size as the master.
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Only keep share copies this many days, masters are kept regardless. Forever if not set.
+    #[serde(default)]
+    pub keep_days: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Watermark {
+    /// The image to put over the movie, ideally a PNG with transparency.
+    pub image: String,
+    /// Same as video_fit's align, e.g. "bottom-right".
+    #[serde(default = "default_watermark_position")]
+    pub position: String,
+    /// 0 (invisible) to 1 (as is).
+    #[serde(default = "default_watermark_opacity")]
+    pub opacity: f32,
+    /// How wide it is, as a fraction of the movie's width.
+    #[serde(default = "default_watermark_scale")]
+    pub scale: f32,
+    /// Pixels to keep between it and the edges.
+    #[serde(default = "default_watermark_margin")]
+    pub margin: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TitleCard {
+    /// How long it's up for.
+    #[serde(default = "default_title_card_seconds")]
+    pub seconds: f64,
+    /// chrono strftime format for the date.
+    #[serde(default = "default_title_card_format")]
+    pub format: String,
+    /// Anything else to put under the date.
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Compression {
+    /// One of "zstd", "lz4", "xz", or "none".
+    pub codec: Codec,
+    /// zstd level, 1 (fast) to 22 (small). Also used for archives.
+    pub level: i32,
+    /// How many files to compress at once, 0 for one per CPU.
+    pub workers: usize,
+    /// Let zstd look further back for matches. Uses more memory, helps on big archives.
+    pub long_distance_matching: bool,
+}
+
+impl Compression {
+    pub fn worker_count(&self) -> usize {
+        match self.workers {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            codec: Codec::default(),
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+            workers: 0,
+            long_distance_matching: false,
+        }
+    }
+}
+
+/// What to do when capture keeps failing (not skipped on purpose, like when the screen is locked).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureRetry {
+    /// Back off from the usual interval, doubling each failure, up to this.
+    #[serde(deserialize_with = "secs::deserialize")]
+    pub max_backoff_secs: u64,
+    /// Complain loudly after this many failures in a row.
+    pub alert_after: u32,
+    /// Also pop up a desktop notification when complaining.
+    pub notify: bool,
+}
+
+impl Default for CaptureRetry {
+    fn default() -> Self {
+        CaptureRetry {
+            max_backoff_secs: 300,
+            alert_after: 10,
+            notify: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OnBattery {
+    /// Stop capturing entirely.
+    #[serde(default)]
+    pub pause: bool,
+    /// Capture at most this often.
+    #[serde(default, deserialize_with = "secs::deserialize_opt")]
+    pub interval_secs: Option<u64>,
+    /// Use this instead of shot_quality.
+    #[serde(default)]
+    pub shot_quality: Option<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Schedule {
+    /// e.g. ["mon", "tue"], or the whole names.
+    #[serde(default = "default_schedule_days")]
+    pub days: Vec<Weekday>,
+    /// Local time, e.g. "08:00".
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl Schedule {
+    /// Whether we should be capturing at `when`.
+    pub fn covers(&self, when: NaiveDateTime) -> bool {
+        self.days.contains(&when.weekday()) && (self.start..self.end).contains(&when.time())
+    }
+}
+
+fn default_schedule_days() -> Vec<Weekday> {
+    vec![
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AdaptiveInterval {
+    #[serde(deserialize_with = "secs::deserialize")]
+    pub min_secs: u64,
+    #[serde(deserialize_with = "secs::deserialize")]
+    pub max_secs: u64,
+}
+
+/// Picture-in-picture from a webcam in a corner of every frame.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WebcamOverlay {
+    /// Whatever ffmpeg calls the camera: /dev/video0 (v4l2), 0 (avfoundation), video=Name (dshow)
+    #[serde(default = "webcam::default_device")]
+    pub device: String,
+    /// "top-left", "top-right", "bottom-left", or "bottom-right"
+    #[serde(default = "default_webcam_corner")]
+    pub corner: String,
+    /// Width of the webcam frame, as a fraction of the whole frame's width.
+    #[serde(default = "default_webcam_size")]
+    pub size: f32,
+}
+
+/// How the frames we write in place of missing time look.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FillerFrame {
+    /// Shown after a gap in capture, "{duration}" gets swapped for how long it was.
+    pub gap_text: String,
+    /// Same, but for when we paused because nobody was using the machine.
+    pub idle_text: String,
+    /// Shown once when we stop capturing because the disk is nearly full.
+    pub disk_full_text: String,
+    /// Path to a TTF/OTF font, defaults to the bundled Ubuntu Regular.
+    pub font: Option<String>,
+    /// Defaults to something proportional to the frame height.
+    pub font_size: Option<f32>,
+    /// RGB
+    pub text_color: [u8; 3],
+    /// RGB
+    pub background_color: [u8; 3],
+}
+
+impl Default for FillerFrame {
+    fn default() -> Self {
+        FillerFrame {
+            gap_text: "{duration} go by".to_string(),
+            idle_text: "{duration} idle".to_string(),
+            disk_full_text: "Disk full".to_string(),
+            font: None,
+            font_size: None,
+            text_color: [255, 255, 255],
+            background_color: [0, 0, 0],
+        }
+    }
+}
+
+/// Burns the capture time into a corner of every frame.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimestampOverlay {
+    /// chrono strftime format
+    #[serde(default = "default_timestamp_format")]
+    pub format: String,
+    /// "top-left", "top-right", "bottom-left", or "bottom-right"
+    #[serde(default = "default_timestamp_corner")]
+    pub corner: String,
+    /// Defaults to something proportional to the frame height.
+    #[serde(default)]
+    pub font_size: Option<f32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VideoSubtitles {
+    /// chrono strftime format for when each frame was captured.
+    #[serde(default = "default_subtitle_format")]
+    pub format: String,
+    /// Follow the time with the app in the foreground, and its window title.
+    #[serde(default = "default_subtitle_app")]
+    pub app: bool,
+}
+
+/// A rectangle, in captured pixels, to hide before a frame gets written.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RedactRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// "black" or "blur"
+    #[serde(default = "default_redact_style")]
+    pub style: String,
+}
+
+fn default_capture_backend() -> String {
+    backend::AUTO_BACKEND.to_string()
+}
+
+fn default_shot_quality() -> u8 {
+    90
+}
+
+fn default_shot_speed() -> u8 {
+    8
+}
+
+fn default_encryption_key_file() -> String {
+    let legacy = home_dir()
+        .expect("Couldn't figure out our home directory?!")
+        .join(".ompd-key");
+    // Shots made with a key that's already there can't be read without it.
+    let key_file = match ompd_config_dir() {
+        Some(dir) if !legacy.exists() => dir.join("key"),
+        _ => legacy,
+    };
+    key_file.into_os_string().into_string().unwrap()
+}
+
+fn default_app_blacklist_action() -> String {
+    "skip".to_string()
+}
+
+fn default_capture_monitors() -> String {
+    "primary".to_string()
+}
+
+/// Expand a leading ~ and any $VAR, ${VAR}, or %VAR% in a path. Variables that aren't set are left
+/// as they are.
+fn expand_path(raw: &str, home: &Path) -> String {
+    let mut expanded = String::new();
+    let mut rest = raw;
+
+    if let Some(after) = rest.strip_prefix('~') {
+        if after.is_empty() || after.starts_with(['/', '\\']) {
+            expanded.push_str(&home.to_string_lossy());
+            rest = after;
+        }
+    }
+
+    while let Some(start) = rest.find(['$', '%']) {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let (name, consumed) = if rest[start..].starts_with('%') {
+            match after.find('%') {
+                Some(end) => (&after[..end], end + 1),
+                None => ("", 0),
+            }
+        } else if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end)
+        };
+
+        match std::env::var(name) {
+            Ok(value) if !name.is_empty() => expanded.push_str(&value),
+            _ => expanded.push_str(&rest[start..start + 1 + consumed]),
+        }
+        rest = &after[consumed..];
+    }
+
+    expanded.push_str(rest);
+    expanded
+}
+
+fn default_video_name_template() -> String {
+    video_name::DEFAULT_TEMPLATE.to_string()
+}
+
+fn default_video_backend() -> String {
+    "ffmpeg".to_string()
+}
+
+fn default_video_encoder() -> String {
+    "auto".to_string()
+}
+
+fn default_video_encoder_fallbacks() -> Vec<String> {
+    vec!["libx264".to_string(), "mpeg4".to_string()]
+}
+
+fn default_shot_retention_action() -> String {
+    "delete".to_string()
+}
+
+fn default_min_frames_for_video() -> u64 {
+    10
+}
+
+fn default_backfill_jobs() -> u32 {
+    1
+}
+
+fn default_backfill_low_priority() -> bool {
+    true
+}
+
+fn default_year_review_seconds() -> f64 {
+    1.0
+}
+
+fn default_year_review_pick() -> String {
+    "start".to_string()
+}
+
+fn default_year_review_name_template() -> String {
+    "ompd-{year}-review.{ext}".to_string()
+}
+
+fn default_month_montage_seconds() -> f64 {
+    30.0
+}
+
+fn default_month_montage_week_starts() -> String {
+    "monday".to_string()
+}
+
+fn default_title_card_seconds() -> f64 {
+    1.5
+}
+
+fn default_title_card_format() -> String {
+    "%A, %B %-d, %Y".to_string()
+}
+
+fn default_video_timing() -> String {
+    "even".to_string()
+}
+
+fn default_drop_static_threshold() -> f32 {
+    1.0
+}
+
+fn default_drop_static_min_per_minute() -> u32 {
+    1
+}
+
+fn default_share_codec() -> String {
+    "libx264".to_string()
+}
+
+fn default_share_crf() -> u8 {
+    28
+}
+
+fn default_watermark_position() -> String {
+    "bottom-right".to_string()
+}
+
+fn default_watermark_opacity() -> f32 {
+    0.8
+}
+
+fn default_watermark_scale() -> f32 {
+    0.15
+}
+
+fn default_watermark_margin() -> u32 {
+    16
+}
+
+fn default_video_poster() -> bool {
+    true
+}
+
+fn default_pause_during_meetings() -> bool {
+    true
+}
+
+fn default_meeting_window_patterns() -> Vec<String> {
+    [
+        // Zoom's meeting window, and the toolbar it shows while sharing
+        "zoom meeting",
+        "zoom share",
+        // A Google Meet tab, e.g. "Meet - abc-defg-hij"
+        "meet - ",
+        "meeting | microsoft teams",
+        "is sharing your screen",
+        "webex meeting",
+    ]
+    .iter()
+    .map(|p| p.to_string())
+    .collect()
+}
+
+fn default_min_free_disk_mb() -> u64 {
+    1024
+}
+
+fn default_upload_attempts() -> u32 {
+    3
+}
+
+fn default_sftp() -> String {
+    "sftp".to_string()
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_redact_style() -> String {
+    "black".to_string()
+}
+
+fn default_capture_scale() -> f32 {
+    1.0
+}
+
+fn default_subtitle_format() -> String {
+    "%H:%M:%S".to_string()
+}
+
+fn default_subtitle_app() -> bool {
+    true
+}
+
+fn default_timestamp_format() -> String {
+    "%Y-%m-%d %H:%M".to_string()
+}
+
+fn default_timestamp_corner() -> String {
+    "bottom-right".to_string()
+}
+
+fn default_webcam_corner() -> String {
+    "bottom-right".to_string()
+}
+
+fn default_webcam_size() -> f32 {
+    0.2
 }
 
 impl Config {
-    pub fn get_config() -> Config {
-        let home = home_dir().expect("Couldn't figure out our home directory?!");
+    /// Hand edited configs tend to say "~/ompd/shots" or "$HOME/ompd", so make those mean what
+    /// they look like.
+    fn expand_paths(&mut self, home: &Path) {
+        for path in [
+            &mut self.shot_output_dir,
+            &mut self.vid_output_dir,
+            &mut self.ffmpeg,
+            &mut self.encryption_key_file,
+        ] {
+            *path = expand_path(path, home);
+        }
 
-        let config_path = home.join(".ompd-config.json");
-        let mut write_config = true;
+        for path in [
+            &mut self.shot_spool_dir,
+            &mut self.background_audio,
+            &mut self.days_off_ics,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = expand_path(path, home);
+        }
+        if let Some(watermark) = &mut self.watermark {
+            watermark.image = expand_path(&watermark.image, home);
+        }
+    }
 
-        if config_path.exists() {
-            if config_path.is_file() {
-                let config_file = File::open(config_path).expect("Failed to open config.json");
-                let config: Config =
-                    serde_json::from_reader(config_file).expect("Failed to read config file");
-                debug!("Read config of: {config:?}");
+    /// The same config, pointed at one machine's directories under the shared ones.
+    pub fn for_host(&self, host: &str) -> Config {
+        let mut config = self.clone();
+        for dir in [&mut config.shot_output_dir, &mut config.vid_output_dir] {
+            *dir = Path::new(dir).join(host).to_string_lossy().to_string();
+        }
+        config
+    }
 
-                let valid_shot_types = HashSet::from([
-                    "bmp", "gif", "jpeg", "jpg", "png", "pnm", "tga", "tiff", "webp",
-                ]);
+    /// Who made the shots (and movies) this config is for.
+    pub fn hostname(&self) -> String {
+        match Path::new(&self.vid_output_dir).file_name() {
+            Some(host) if self.per_host_dirs => host.to_string_lossy().to_string(),
+            _ => gethostname::gethostname().to_string_lossy().to_string(),
+        }
+    }
 
-                assert!(
-                    config.max_sleep_secs > 0,
-                    "max_sleep_secs must be greater than zero. No sleeping backwards!"
-                );
+    pub fn video_name(&self) -> VideoName {
+        VideoName::new(
+            &self.video_name_template,
+            &self.hostname(),
+            &self.video_type,
+        )
+    }
 
-                if !valid_shot_types.contains(config.shot_type.as_str()) {
-                    panic!(
-                        "Invalid shot type {}, pick from: {:?}",
-                        config.shot_type, valid_shot_types
-                    );
+    /// What share copies are called, if they get made.
+    pub fn share_video_name(&self) -> Option<VideoName> {
+        self.share_copy.as_ref()?;
+        let template = self.video_name_template.strip_suffix(".{ext}")?;
+        Some(VideoName::new(
+            &format!("{template}.share.{{ext}}"),
+            &self.hostname(),
+            SHARE_VIDEO_TYPE,
+        ))
+    }
+
+    /// What the movies that get uploaded are called: the share copies if there are any, otherwise
+    /// the movies themselves.
+    pub fn uploaded_video_name(&self) -> VideoName {
+        self.share_video_name().unwrap_or_else(|| self.video_name())
+    }
+
+    /// With per_host_dirs, the shared directories every machine's own shot and video directories
+    /// live under.
+    pub fn shared_dirs(&self) -> Option<(PathBuf, PathBuf)> {
+        if !self.per_host_dirs {
+            return None;
+        }
+        Some((
+            Path::new(&self.shot_output_dir).parent()?.to_path_buf(),
+            Path::new(&self.vid_output_dir).parent()?.to_path_buf(),
+        ))
+    }
+
+    /// Has every get_config from here on use `overrides`. Only the first call counts.
+    pub fn set_overrides(overrides: Overrides) {
+        if OVERRIDES.set(overrides).is_err() {
+            warn!("Command line settings were already set, ignoring the new ones");
+        }
+    }
+
+    fn apply_overrides(&mut self) {
+        let Some(overrides) = OVERRIDES.get() else {
+            return;
+        };
+        if let Some(interval) = overrides.interval {
+            self.interval = interval;
+        }
+        if let Some(dir) = &overrides.shot_output_dir {
+            self.shot_output_dir = dir.clone();
+        }
+        if let Some(dir) = &overrides.vid_output_dir {
+            self.vid_output_dir = dir.clone();
+        }
+        if overrides.no_backfill {
+            self.handle_old_dirs_on_startup = false;
+        }
+    }
+
+    /// Everything wrong with the config, as much as can be told without trying it out.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        // Like assert!, but keeps going so every problem gets reported at once.
+        macro_rules! check {
+            ($ok:expr, $($problem:tt)+) => {
+                let ok: bool = $ok;
+                if !ok {
+                    problems.push(format!($($problem)+));
                 }
+            };
+        }
+
+        let valid_shot_types = HashSet::from([
+            "avif", "bmp", "gif", "jpeg", "jpg", "png", "pnm", "tga", "tiff", "webp",
+        ]);
+
+        check!(
+            self.config_version <= CONFIG_VERSION,
+            "config_version {} is from a newer ompd than this one, which only knows up to {}",
+            self.config_version,
+            CONFIG_VERSION
+        );
+
+        check!(
+            self.max_sleep_secs > 0,
+            "max_sleep_secs must be greater than zero. No sleeping backwards!"
+        );
+        check!(self.interval > 0, "interval must be at least 1 second");
+        check!(
+            (self.interval as i64) < self.max_sleep_secs,
+            "interval ({}) must be less than max_sleep_secs ({}), or every frame looks like a gap",
+            self.interval,
+            self.max_sleep_secs
+        );
+
+        check!(
+            self.vid_width > 0 && self.vid_width.is_multiple_of(2),
+            "vid_width must be even and greater than zero, try {}",
+            (self.vid_width.max(2) + 1) & !1
+        );
+        check!(
+            self.vid_height > 0 && self.vid_height.is_multiple_of(2),
+            "vid_height must be even and greater than zero, try {}",
+            (self.vid_height.max(2) + 1) & !1
+        );
+        if let Some(factor) = self.vid_scale_factor {
+            check!(
+                factor > 0.0 && factor <= 1.0,
+                "vid_scale_factor must be greater than 0 and at most 1"
+            );
+        }
+
+        check!(
+            self.day_starts_at_hour < 24,
+            "day_starts_at_hour must be between 0 and 23"
+        );
+
+        check!(
+            self.video_fps != Some(0) && self.target_video_seconds != Some(0),
+            "video_fps and target_video_seconds must be greater than zero"
+        );
+
+        if let Some(preset) = &self.quality_preset {
+            if !QUALITY_PRESETS.contains(&preset.as_str()) {
+                problems.push(format!(
+                    "Invalid quality_preset {preset}, pick from: {QUALITY_PRESETS:?}"
+                ));
+            }
+        }
+
+        if let Some(crf) = self.video_crf {
+            check!(crf <= 51, "video_crf must be between 0 and 51");
+        }
+
+        for needed in ["{year}", "{month}", "{day}"] {
+            check!(
+                self.video_name_template.contains(needed),
+                "video_name_template needs {needed} in it"
+            );
+        }
+        check!(
+            self.video_name_template.ends_with(".{ext}"),
+            "video_name_template has to end in .{{ext}}"
+        );
+        check!(
+            !self.video_name_template.contains(['/', '\\']),
+            "video_name_template is just a file name, it can't have directories in it"
+        );
+
+        check!(
+            self.gap_speedup != Some(0),
+            "gap_speedup must be greater than zero"
+        );
+
+        if let Some(drop_static) = &self.drop_static {
+            check!(
+                (0.0..=255.0).contains(&drop_static.threshold),
+                "drop_static threshold must be between 0 and 255"
+            );
+        }
+
+        if let Some(target_mb) = self.video_target_mb {
+            check!(target_mb > 0.0, "video_target_mb must be greater than zero");
+        }
+
+        check!(self.backfill_jobs > 0, "backfill_jobs must be at least 1");
+        if let (Some(since), Some(until)) = (self.backfill_since, self.backfill_until) {
+            check!(
+                since <= until,
+                "backfill_since ({since}) has to be on or before backfill_until ({until})"
+            );
+        }
+        if let Some(threads) = self.backfill_ffmpeg_threads {
+            check!(threads > 0, "backfill_ffmpeg_threads must be at least 1");
+        }
+
+        if let Some(segments) = self.video_segments {
+            check!(segments > 0, "video_segments must be at least 1");
+        }
+
+        check!(
+            (1..=100).contains(&self.shot_quality),
+            "shot_quality must be between 1 and 100"
+        );
+
+        check!(
+            (1..=10).contains(&self.shot_speed),
+            "shot_speed must be between 1 and 10"
+        );
+
+        check!(
+            self.capture_scale > 0.0 && self.capture_scale <= 1.0,
+            "capture_scale must be greater than 0 and at most 1"
+        );
+
+        let valid_capture_monitors = HashSet::from(["primary", "all"]);
+        if !valid_capture_monitors.contains(self.capture_monitors.as_str()) {
+            problems.push(format!(
+                "Invalid capture_monitors {}, pick from: {:?}",
+                self.capture_monitors, valid_capture_monitors
+            ));
+        }
+        if let Some(layout) = &self.monitor_layout {
+            if !MONITOR_LAYOUTS.contains(&layout.as_str()) {
+                problems.push(format!(
+                    "Invalid monitor_layout {}, pick from: {:?}",
+                    layout, MONITOR_LAYOUTS
+                ));
+            }
+        }
+
+        let valid_blacklist_actions = HashSet::from(["skip", "blackout"]);
+        if !valid_blacklist_actions.contains(self.app_blacklist_action.as_str()) {
+            problems.push(format!(
+                "Invalid app_blacklist_action {}, pick from: {:?}",
+                self.app_blacklist_action, valid_blacklist_actions
+            ));
+        }
+
+        if let Some(retention) = &self.shot_retention {
+            let valid_retention_actions = HashSet::from(["delete", "archive"]);
+            if !valid_retention_actions.contains(retention.action.as_str()) {
+                problems.push(format!(
+                    "Invalid shot_retention action {}, pick from: {:?}",
+                    retention.action, valid_retention_actions
+                ));
+            }
+        }
+
+        let valid_fit_modes = HashSet::from(["stretch", "contain", "cover"]);
+        if !valid_fit_modes.contains(self.video_fit.mode.as_str()) {
+            problems.push(format!(
+                "Invalid video_fit mode {}, pick from: {:?}",
+                self.video_fit.mode, valid_fit_modes
+            ));
+        }
+        let valid_fit_aligns = HashSet::from([
+            "center",
+            "top",
+            "bottom",
+            "left",
+            "right",
+            "top-left",
+            "top-right",
+            "bottom-left",
+            "bottom-right",
+        ]);
+        if !valid_fit_aligns.contains(self.video_fit.align.as_str()) {
+            problems.push(format!(
+                "Invalid video_fit align {}, pick from: {:?}",
+                self.video_fit.align, valid_fit_aligns
+            ));
+        }
+
+        if let Some(watermark) = &self.watermark {
+            check!(
+                std::path::Path::new(&watermark.image).is_file(),
+                "watermark image {} isn't a file!",
+                watermark.image
+            );
+            if !valid_fit_aligns.contains(watermark.position.as_str()) {
+                problems.push(format!(
+                    "Invalid watermark position {}, pick from: {:?}",
+                    watermark.position, valid_fit_aligns
+                ));
+            }
+            check!(
+                watermark.opacity > 0.0 && watermark.opacity <= 1.0,
+                "watermark opacity must be more than 0, and at most 1"
+            );
+            check!(
+                watermark.scale > 0.0 && watermark.scale <= 1.0,
+                "watermark scale must be more than 0, and at most 1"
+            );
+        }
+
+        let valid_timings = HashSet::from(["even", "captured"]);
+        if !valid_timings.contains(self.video_timing.as_str()) {
+            problems.push(format!(
+                "Invalid video_timing {}, pick from: {:?}",
+                self.video_timing, valid_timings
+            ));
+        }
+
+        if !VALID_ROTATIONS.contains(&self.video_rotation) {
+            problems.push(format!(
+                "Invalid video_rotation {}, pick from: {:?}",
+                self.video_rotation, VALID_ROTATIONS
+            ));
+        }
+
+        if let Some(review) = &self.year_review {
+            check!(
+                review.seconds_per_day > 0.0,
+                "year_review seconds_per_day must be greater than zero"
+            );
+            let valid_review_picks = HashSet::from(["start", "busiest"]);
+            if !valid_review_picks.contains(review.pick.as_str()) {
+                problems.push(format!(
+                    "Invalid year_review pick {}, pick from: {:?}",
+                    review.pick, valid_review_picks
+                ));
+            }
+            check!(
+                review.name_template.contains("{year}"),
+                "year_review name_template needs {{year}} in it"
+            );
+            check!(
+                !review.name_template.contains("{month}")
+                    && !review.name_template.contains("{day}"),
+                "year_review name_template is for a whole year, it can't have {{month}} or {{day}}"
+            );
+            check!(
+                review.name_template.ends_with(".{ext}"),
+                "year_review name_template has to end in .{{ext}}"
+            );
+            check!(
+                !review.name_template.contains(['/', '\\']),
+                "year_review name_template is just a file name, it can't have directories in it"
+            );
+        }
+
+        if let Some(montage) = &self.month_montage {
+            check!(
+                montage.seconds > 0.0,
+                "month_montage seconds must be greater than zero"
+            );
+            let valid_week_starts = HashSet::from(["monday", "sunday"]);
+            if !valid_week_starts.contains(montage.week_starts.as_str()) {
+                problems.push(format!(
+                    "Invalid month_montage week_starts {}, pick from: {:?}",
+                    montage.week_starts, valid_week_starts
+                ));
+            }
+        }
+
+        if let Some(share) = &self.share_copy {
+            check!(share.crf <= 51, "share_copy crf must be between 0 and 51");
+            if let Some(height) = share.height {
+                check!(
+                    height > 0 && height % 2 == 0,
+                    "share_copy height must be even and greater than zero"
+                );
+            }
+        }
 
-                if !std::path::Path::new(&config.ffmpeg).is_file() {
-                    let e = format!("Our 'ffmpeg' ({}) isn't a file!", &config.ffmpeg);
-                    error!("{}", e);
-                    panic!("{}", e);
+        if let Some(card) = &self.title_card {
+            check!(
+                card.seconds > 0.0,
+                "title_card seconds must be greater than zero"
+            );
+        }
+
+        let valid_redact_styles = HashSet::from(["black", "blur"]);
+        for region in &self.redact_regions {
+            if !valid_redact_styles.contains(region.style.as_str()) {
+                problems.push(format!(
+                    "Invalid redact region style {}, pick from: {:?}",
+                    region.style, valid_redact_styles
+                ));
+            }
+        }
+
+        let valid_corners = HashSet::from(["top-left", "top-right", "bottom-left", "bottom-right"]);
+        if let Some(overlay) = &self.timestamp_overlay {
+            if !valid_corners.contains(overlay.corner.as_str()) {
+                problems.push(format!(
+                    "Invalid timestamp corner {}, pick from: {:?}",
+                    overlay.corner, valid_corners
+                ));
+            }
+        }
+
+        if let Some(clock) = &self.video_clock {
+            if !valid_corners.contains(clock.corner.as_str()) {
+                problems.push(format!(
+                    "Invalid video_clock corner {}, pick from: {:?}",
+                    clock.corner, valid_corners
+                ));
+            }
+        }
+
+        check!(
+            self.upload_attempts > 0,
+            "upload_attempts must be at least 1"
+        );
+
+        check!(
+            zstd::compression_level_range().contains(&self.compression.level),
+            "compression level must be in {:?}",
+            zstd::compression_level_range()
+        );
+
+        check!(
+            self.capture_retry.alert_after > 0,
+            "capture_retry alert_after must be at least 1"
+        );
+
+        if let Some(on_battery) = &self.on_battery {
+            if let Some(interval_secs) = on_battery.interval_secs {
+                check!(
+                    (interval_secs as i64) < self.max_sleep_secs,
+                    "on_battery interval_secs must be less than max_sleep_secs, or every frame looks like a gap"
+                );
+            }
+            if let Some(quality) = on_battery.shot_quality {
+                check!(
+                    (1..=100).contains(&quality),
+                    "on_battery shot_quality must be between 1 and 100"
+                );
+            }
+        }
+
+        if let Some(schedule) = &self.schedule {
+            check!(
+                schedule.start < schedule.end,
+                "schedule start ({}) has to be before its end ({}), it can't go past midnight",
+                schedule.start,
+                schedule.end
+            );
+            check!(
+                !schedule.days.is_empty(),
+                "schedule days can't be empty, or nothing ever gets captured"
+            );
+        }
+
+        if let Some(adaptive) = &self.adaptive_interval {
+            check!(
+                adaptive.min_secs > 0 && adaptive.min_secs <= adaptive.max_secs,
+                "adaptive_interval needs 0 < min_secs <= max_secs"
+            );
+            check!(
+                (adaptive.max_secs as i64) < self.max_sleep_secs,
+                "adaptive_interval max_secs must be less than max_sleep_secs, or every frame looks like a gap"
+            );
+        }
+
+        if let Some(webcam) = &self.webcam_overlay {
+            if !valid_corners.contains(webcam.corner.as_str()) {
+                problems.push(format!(
+                    "Invalid webcam corner {}, pick from: {:?}",
+                    webcam.corner, valid_corners
+                ));
+            }
+
+            check!(
+                webcam.size > 0.0 && webcam.size <= 1.0,
+                "Webcam size must be greater than 0 and at most 1"
+            );
+        }
+
+        let subtitle_video_types: HashSet<&str> = SUBTITLE_CODECS
+            .iter()
+            .map(|(video_type, _)| *video_type)
+            .collect();
+        if self.video_subtitles.is_some()
+            && !subtitle_video_types.contains(self.video_type.as_str())
+        {
+            problems.push(format!(
+                "video_subtitles can't go in {} movies, pick from: {:?}",
+                self.video_type, subtitle_video_types
+            ));
+        }
+
+        if let Some(ics) = &self.days_off_ics {
+            check!(
+                std::path::Path::new(ics).is_file(),
+                "days_off_ics {ics} isn't a file!"
+            );
+        }
+
+        if let Some(audio) = &self.background_audio {
+            check!(
+                std::path::Path::new(audio).exists(),
+                "background_audio {audio} doesn't exist!"
+            );
+        }
+
+        if let Some(font) = &self.filler_frame.font {
+            check!(
+                std::path::Path::new(font).is_file(),
+                "Filler frame font {font} isn't a file!"
+            );
+        }
+
+        if let Some(font_size) = self.filler_frame.font_size {
+            check!(font_size > 0.0, "Filler frame font_size must be positive");
+        }
+
+        if !valid_shot_types.contains(self.shot_type.as_str()) {
+            problems.push(format!(
+                "Invalid shot type {}, pick from: {:?}",
+                self.shot_type, valid_shot_types
+            ));
+        }
+
+        if !backend::known_backends().contains(&self.capture_backend.as_str()) {
+            problems.push(format!(
+                "Invalid capture backend {}, pick from: {:?}",
+                self.capture_backend,
+                backend::known_backends()
+            ));
+        }
+
+        let valid_video_backends = HashSet::from(["ffmpeg", "builtin"]);
+        if !valid_video_backends.contains(self.video_backend.as_str()) {
+            problems.push(format!(
+                "Invalid video_backend {}, pick from: {:?}",
+                self.video_backend, valid_video_backends
+            ));
+        }
+
+        if self.video_backend == "builtin" {
+            check!(
+                self.video_type == BUILTIN_VIDEO_TYPE,
+                "The builtin video_backend only makes '{BUILTIN_VIDEO_TYPE}' movies"
+            );
+            check!(
+                self.shot_type != "avif",
+                "The builtin video_backend can't read avif shots"
+            );
+            check!(
+                self.year_review.is_none(),
+                "year_review needs the ffmpeg video_backend"
+            );
+            check!(
+                self.monitor_layout.is_none(),
+                "monitor_layout needs the ffmpeg video_backend"
+            );
+            check!(
+                self.month_montage.is_none(),
+                "month_montage needs the ffmpeg video_backend"
+            );
+            check!(
+                self.video_clock.is_none(),
+                "video_clock needs the ffmpeg video_backend"
+            );
+            check!(
+                self.background_audio.is_none(),
+                "background_audio needs the ffmpeg video_backend"
+            );
+            check!(
+                self.video_target_mb.is_none(),
+                "video_target_mb needs the ffmpeg video_backend"
+            );
+            check!(
+                self.video_segments.is_none(),
+                "video_segments needs the ffmpeg video_backend"
+            );
+            check!(
+                self.video_subtitles.is_none(),
+                "video_subtitles needs the ffmpeg video_backend"
+            );
+            check!(
+                self.share_copy.is_none(),
+                "share_copy needs the ffmpeg video_backend"
+            );
+            check!(
+                self.video_timing == "even",
+                "video_timing {} needs the ffmpeg video_backend",
+                self.video_timing
+            );
+            return problems;
+        }
+
+        if !std::path::Path::new(&self.ffmpeg).is_file() {
+            problems.push(format!(
+                "Our 'ffmpeg' ({}) isn't a file! Point ffmpeg at one, or use the builtin \
+                 video_backend",
+                self.ffmpeg
+            ));
+        } else if let Err(e) = MovieMaker::has_muxer(&self.ffmpeg, &self.video_type) {
+            problems.push(e.to_string());
+        }
+
+        if matches!(self.video_type.as_str(), "gif" | "webp")
+            && (self.video_crf.is_some()
+                || self.video_preset.is_some()
+                || self.video_bitrate.is_some())
+        {
+            warn!(
+                "video_crf, video_preset, and video_bitrate don't apply to {} movies",
+                self.video_type
+            );
+        }
+
+        problems
+    }
+
+    /// The same config, with whatever OMPD_ environment variables there are.
+    fn with_env_overrides(self) -> Result<Config, Error> {
+        let mut fields = match serde_json::to_value(&self)? {
+            Value::Object(fields) => fields,
+            _ => return Ok(self),
+        };
+
+        let defaults = fields.clone();
+        let mut overridden = false;
+        for (name, raw) in std::env::vars_os() {
+            let (Ok(name), Ok(raw)) = (name.into_string(), raw.into_string()) else {
+                continue;
+            };
+            let Some(field) = name.strip_prefix(ENV_PREFIX).map(str::to_lowercase) else {
+                continue;
+            };
+            let value = match defaults.get(&field) {
+                None => {
+                    warn!("{name} isn't for anything in the config, ignoring it");
+                    continue;
                 }
+                // So a directory named 2024 doesn't turn into a number.
+                Some(Value::String(_)) => Value::String(raw),
+                Some(Value::Null) if takes_text(&defaults, &field, &raw) => Value::String(raw),
+                Some(_) => serde_json::from_str(&raw).unwrap_or(Value::String(raw)),
+            };
+            debug!("{name} overrides {field}");
+            fields.insert(field, value);
+            overridden = true;
+        }
+
+        if !overridden {
+            return Ok(self);
+        }
+        serde_json::from_value(Value::Object(fields))
+            .map_err(|e| anyhow!("the OMPD_ environment variables don't work out: {e}"))
+    }
 
-                let mux_check = MovieMaker::has_muxer(&config.ffmpeg, &config.video_type);
-                if let Err(e) = mux_check {
-                    error!("{}", e);
-                    panic!("{}", e);
+    /// The config at `path`, with the environment's and command line's settings and everything made ready to use,
+    /// but not checked over.
+    fn read_from(path: &Path, home: &Path) -> Result<Config, Error> {
+        Config::read_upgraded(path)?.0.ready(home)
+    }
+
+    /// The config file at `path`, brought up to CONFIG_VERSION if it's from an older ompd. Says
+    /// which version it was if so.
+    fn read_upgraded(path: &Path) -> Result<(Config, Option<u32>), Error> {
+        let Value::Object(mut fields) = serde_json::from_reader(File::open(path)?)? else {
+            return Err(anyhow!("it should be a JSON object, {{ ... }}"));
+        };
+        let defaults = serde_defaults(&fields)?;
+        let upgraded_from = upgrade(&mut fields, &defaults);
+        Ok((
+            serde_json::from_value(Value::Object(fields))?,
+            upgraded_from,
+        ))
+    }
+
+    /// Writes an upgraded config from `version` back to `path`, keeping the old one next to it.
+    fn save_upgrade(&self, path: &Path, version: u32) {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".v{version}.bak"));
+        match version < CONFIG_VERSION {
+            true => info!("Upgrading {path:?} from config_version {version} to {CONFIG_VERSION}"),
+            false => info!("Filling in the new settings in {path:?}"),
+        }
+        // Atomically, so dying halfway through can't leave half a config behind.
+        let saved = std::fs::copy(path, &backup).and_then(|_| {
+            let upgraded = serde_json::to_string_pretty(self).unwrap();
+            DirManager::write_atomically(path, upgraded.as_bytes())
+        });
+        if let Err(e) = saved {
+            warn!("Couldn't save the upgraded config, will try again next time: {e}");
+        }
+    }
+
+    /// Everything the config needs before it can be used: environment variables, the command
+    /// line's settings, paths filled in, and this machine's directories.
+    fn ready(self, home: &Path) -> Result<Config, Error> {
+        let mut config = self.with_env_overrides()?;
+        config.apply_overrides();
+        config.expand_paths(home);
+        if let Some(factor) = config.vid_scale_factor {
+            match capturer::primary_screen_size() {
+                Some((width, height)) => {
+                    // Most encoders only take even sizes.
+                    config.vid_width = ((width as f32 * factor) as u32).max(2) & !1;
+                    config.vid_height = ((height as f32 * factor) as u32).max(2) & !1;
                 }
+                None => warn!(
+                    "Couldn't find the screen for vid_scale_factor, going by vid_width and \
+                     vid_height"
+                ),
+            }
+        }
+        if config.per_host_dirs {
+            let host = gethostname::gethostname().to_string_lossy().to_string();
+            config = config.for_host(&host);
+        }
+        Ok(config)
+    }
 
-                return config;
-            } else {
-                warn!("{config_path:?} isn't a file. Going to use default config and NOT save it.");
-                write_config = false;
+    /// Reads the config and goes over all of it, including whether the directories it points at
+    /// can be written to. Says where the config is, and everything wrong with it.
+    pub fn check_config() -> (PathBuf, Vec<String>) {
+        let config_path = Config::config_path();
+        if !config_path.exists() {
+            let problem = "There's no config here yet, running ompd once makes one".to_string();
+            return (config_path, vec![problem]);
+        }
+        let home = home_dir().expect("Couldn't figure out our home directory?!");
+        let config = match Config::read_from(&config_path, &home) {
+            Ok(config) => config,
+            Err(e) => return (config_path, vec![format!("Couldn't read it: {e}")]),
+        };
+
+        let mut problems = config.problems();
+        let dirs = [Some(&config.shot_output_dir), Some(&config.vid_output_dir)];
+        for dir in dirs
+            .into_iter()
+            .chain([config.shot_spool_dir.as_ref()])
+            .flatten()
+        {
+            if let Some(why) = check_writable(Path::new(dir)) {
+                problems.push(format!(
+                    "Can't write to {dir}, {why}. Make sure it exists and belongs to you, or point \
+                     the config somewhere else"
+                ));
             }
         }
+        (config_path, problems)
+    }
 
-        debug!("Making new base config");
+    /// Where the config gets read from.
+    pub fn config_path() -> PathBuf {
+        OVERRIDES
+            .get()
+            .and_then(|overrides| overrides.config_path.clone())
+            .unwrap_or_else(|| {
+                default_config_path(&home_dir().expect("Couldn't figure out our home directory?!"))
+            })
+    }
+
+    /// What a brand new config looks like, for this machine.
+    fn new_config(home: &Path, (vid_width, vid_height): (u32, u32)) -> Config {
         #[cfg(target_os = "windows")]
         let ffmpeg_path_maybe = which("ffmpeg.exe");
 
         #[cfg(not(target_os = "windows"))]
         let ffmpeg_path_maybe = which("ffmpeg");
 
-        let ffmpeg_path = match ffmpeg_path_maybe {
-            Err(_) => {
-                warn!("Couldn't find a path to ffmpeg, making one up! You should update {config_path:?}");
-                "FIND SOMETHING TO PUT HERE".to_string()
-            }
-            Ok(p) => p.to_str().unwrap().to_string(),
+        let (ffmpeg_path, video_backend, video_type) = match ffmpeg_path_maybe {
+            Err(_) => (
+                "FIND SOMETHING TO PUT HERE".to_string(),
+                "builtin".to_string(),
+                BUILTIN_VIDEO_TYPE.to_string(),
+            ),
+            Ok(p) => (
+                p.to_str().unwrap().to_string(),
+                default_video_backend(),
+                "mp4".to_string(),
+            ),
         };
 
-        let new_config = Config {
+        Config {
+            config_version: CONFIG_VERSION,
             interval: 20,
             max_sleep_secs: 180,
-            shot_output_dir: home
-                .join("ompd")
+            day_starts_at_hour: 0,
+            shot_output_dir: dirs::data_local_dir()
+                .map(|dir| dir.join("ompd"))
+                .unwrap_or_else(|| home.join("ompd"))
                 .join("shots")
                 .into_os_string()
                 .into_string()
                 .unwrap(),
-            vid_output_dir: home
-                .join("ompd")
-                .join("videos")
+            shot_spool_dir: None,
+            per_host_dirs: false,
+            backfill_all_hosts: false,
+            backfill_dry_run: false,
+            backfill_reencode: false,
+            backfill_since: None,
+            backfill_until: None,
+            backfill_jobs: default_backfill_jobs(),
+            backfill_low_priority: default_backfill_low_priority(),
+            backfill_ffmpeg_threads: None,
+            backfill_when_idle_secs: None,
+            vid_output_dir: dirs::video_dir()
+                .map(|dir| dir.join("ompd"))
+                .unwrap_or_else(|| home.join("ompd").join("videos"))
                 .into_os_string()
                 .into_string()
                 .unwrap(),
             ffmpeg: ffmpeg_path,
             handle_old_dirs_on_startup: true,
-            vid_width: 860,
-            vid_height: 360,
+            vid_width,
+            vid_height,
+            vid_scale_factor: None,
             shot_type: "jpeg".to_string(),
             compress_shots: true,
-            video_type: "mp4".to_string(),
-        };
+            archive_shots: false,
+            shot_retention: None,
+            compression: Compression::default(),
+            video_type,
+            video_name_template: default_video_name_template(),
+            video_fit: VideoFit::default(),
+            monitor_layout: None,
+            video_rotation: 0,
+            video_timing: default_video_timing(),
+            gap_speedup: None,
+            drop_static: None,
+            skip_gaps: false,
+            keep_gap_cards: false,
+            video_encoder: default_video_encoder(),
+            video_backend,
+            video_codec: None,
+            video_encoder_fallbacks: default_video_encoder_fallbacks(),
+            quality_preset: None,
+            video_fps: None,
+            target_video_seconds: None,
+            video_crf: None,
+            video_preset: None,
+            video_bitrate: None,
+            video_target_mb: None,
+            video_segments: None,
+            min_frames_for_video: default_min_frames_for_video(),
+            ffmpeg_input_args: Vec::new(),
+            ffmpeg_output_args: Vec::new(),
+            background_audio: None,
+            year_review: None,
+            month_montage: None,
+            capture_backend: default_capture_backend(),
+            shot_quality: default_shot_quality(),
+            shot_lossless: false,
+            shot_speed: default_shot_speed(),
+            encrypt_shots: false,
+            encryption_key_file: default_encryption_key_file(),
+            pause_when_idle_secs: None,
+            schedule: None,
+            days_off: Vec::new(),
+            days_off_ics: None,
+            app_blacklist: Vec::new(),
+            app_blacklist_action: default_app_blacklist_action(),
+            pause_during_meetings: default_pause_during_meetings(),
+            meeting_window_patterns: default_meeting_window_patterns(),
+            redact_regions: Vec::new(),
+            capture_scale: default_capture_scale(),
+            hdr_tone_map: false,
+            capture_monitors: default_capture_monitors(),
+            timestamp_overlay: None,
+            video_clock: None,
+            video_subtitles: None,
+            title_card: None,
+            share_copy: None,
+            watermark: None,
+            video_poster: default_video_poster(),
+            filler_frame: FillerFrame::default(),
+            webcam_overlay: None,
+            min_free_disk_mb: default_min_free_disk_mb(),
+            s3_upload: None,
+            sftp_upload: None,
+            webdav_upload: None,
+            upload_attempts: default_upload_attempts(),
+            capture_retry: CaptureRetry::default(),
+            on_battery: None,
+            adaptive_interval: None,
+        }
+    }
 
-        if write_config {
-            let wrote_config = std::fs::write(
-                config_path,
-                serde_json::to_string_pretty(&new_config).unwrap(),
+    pub fn get_config() -> Config {
+        let home = home_dir().expect("Couldn't figure out our home directory?!");
+
+        let config_path = Config::config_path();
+        let mut write_config = true;
+
+        if config_path.exists() {
+            if config_path.is_file() {
+                let (config, upgraded_from) = Config::read_upgraded(&config_path)
+                    .unwrap_or_else(|e| panic!("Couldn't read {config_path:?}: {e}"));
+                if let Some(version) = upgraded_from {
+                    config.save_upgrade(&config_path, version);
+                }
+                let config = config
+                    .ready(&home)
+                    .unwrap_or_else(|e| panic!("Couldn't read {config_path:?}: {e}"));
+                debug!("Read config of: {config:?}");
+
+                let problems = config.problems();
+                if !problems.is_empty() {
+                    for problem in &problems {
+                        error!("{problem}");
+                    }
+                    panic!(
+                        "{} problems with {config_path:?}, see above or run with --check-config",
+                        problems.len()
+                    );
+                }
+
+                return config;
+            } else {
+                warn!("{config_path:?} isn't a file. Going to use default config and NOT save it.");
+                write_config = false;
+            }
+        }
+
+        debug!("Making new base config");
+        let new_config = Config::new_config(&home, default_vid_size());
+        if new_config.video_backend == "builtin" {
+            warn!(
+                "Couldn't find a path to ffmpeg, making movies without it! You can point \
+                 {config_path:?} at one later"
             );
+        }
+
+        if write_config {
+            let wrote_config = config_path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| {
+                    std::fs::write(
+                        &config_path,
+                        serde_json::to_string_pretty(&new_config).unwrap(),
+                    )
+                });
             if let Err(e) = wrote_config {
                 error!("Couldn't write config file! Will have to try again next time: {e:?}");
             }
         }
 
-        new_config
+        // Only after writing it, so the environment's and command line's settings don't stick
+        // around.
+        new_config.ready(&home).unwrap_or_else(|e| panic!("{e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand_path, serde_defaults, upgrade, Config, Schedule, CONFIG_VERSION};
+    use chrono::NaiveDate;
+    use serde_json::{json, Map, Value};
+    use std::path::Path;
+
+    fn as_map(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("not an object"),
+        }
+    }
+
+    #[test]
+    fn upgrade_from_before_versions() {
+        let mut fields = as_map(json!({"interval": 30, "video_type": "mkv"}));
+        let defaults = as_map(json!({
+            "config_version": CONFIG_VERSION,
+            "interval": 10,
+            "video_type": "mp4",
+            "encrypt_shots": false,
+        }));
+
+        assert_eq!(upgrade(&mut fields, &defaults), Some(0));
+        assert_eq!(fields["config_version"], json!(1));
+        // What was set stays set, and what wasn't gets the default.
+        assert_eq!(fields["interval"], json!(30));
+        assert_eq!(fields["video_type"], json!("mkv"));
+        assert_eq!(fields["encrypt_shots"], json!(false));
+
+        // Once upgraded, there's nothing more to do.
+        assert_eq!(upgrade(&mut fields, &defaults), None);
+    }
+
+    #[test]
+    fn upgrade_without_ffmpeg_on_the_path() {
+        // Plenty of configs point at ffmpeg directly, so it not being on the PATH can't matter.
+        std::env::set_var("PATH", "");
+        let mut fields = as_map(json!({
+            "interval": 20,
+            "max_sleep_secs": 180,
+            "shot_output_dir": "/shots",
+            "vid_output_dir": "/movies",
+            "ffmpeg": "/opt/ffmpeg/bin/ffmpeg",
+            "handle_old_dirs_on_startup": true,
+            "vid_width": 860,
+            "vid_height": 360,
+            "shot_type": "png",
+            "compress_shots": true,
+            "video_type": "mp4",
+        }));
+
+        let defaults = serde_defaults(&fields).unwrap();
+        assert_eq!(upgrade(&mut fields, &defaults), Some(0));
+        let config: Config = serde_json::from_value(Value::Object(fields)).unwrap();
+        assert_eq!(config.video_backend, "ffmpeg");
+        assert_eq!(config.video_type, "mp4");
+        let problems = config.problems();
+        assert!(
+            !problems.iter().any(|p| p.contains("only makes")),
+            "{problems:?}"
+        );
+    }
+
+    #[test]
+    fn expand_home() {
+        let home = Path::new("/home/me");
+        assert_eq!(expand_path("~", home), "/home/me");
+        assert_eq!(expand_path("~/shots", home), "/home/me/shots");
+        assert_eq!(expand_path("~\\shots", home), "/home/me\\shots");
+        // Somebody else's home, or a ~ that's just part of a name, stay as they are.
+        assert_eq!(expand_path("~them/shots", home), "~them/shots");
+        assert_eq!(expand_path("/shots/~", home), "/shots/~");
+    }
+
+    #[test]
+    fn expand_vars() {
+        let home = Path::new("/home/me");
+        std::env::set_var("EXPAND_PATH_TEST_DIR", "/mnt/big");
+        assert_eq!(
+            expand_path("$EXPAND_PATH_TEST_DIR/shots", home),
+            "/mnt/big/shots"
+        );
+        assert_eq!(
+            expand_path("${EXPAND_PATH_TEST_DIR}shots", home),
+            "/mnt/bigshots"
+        );
+        assert_eq!(
+            expand_path("%EXPAND_PATH_TEST_DIR%\\shots", home),
+            "/mnt/big\\shots"
+        );
+        assert_eq!(
+            expand_path("~/$EXPAND_PATH_TEST_DIR/$EXPAND_PATH_TEST_DIR", home),
+            "/home/me//mnt/big//mnt/big"
+        );
+    }
+
+    #[test]
+    fn expand_missing_vars() {
+        let home = Path::new("/home/me");
+        std::env::remove_var("EXPAND_PATH_TEST_MISSING");
+        // Left alone, so whatever uses the path complains about what it actually says.
+        assert_eq!(
+            expand_path("$EXPAND_PATH_TEST_MISSING/x", home),
+            "$EXPAND_PATH_TEST_MISSING/x"
+        );
+        assert_eq!(
+            expand_path("${EXPAND_PATH_TEST_MISSING}", home),
+            "${EXPAND_PATH_TEST_MISSING}"
+        );
+        assert_eq!(
+            expand_path("%EXPAND_PATH_TEST_MISSING%", home),
+            "%EXPAND_PATH_TEST_MISSING%"
+        );
+        assert_eq!(expand_path("costs $5", home), "costs $5");
+        assert_eq!(expand_path("50% off", home), "50% off");
+        assert_eq!(expand_path("${unclosed", home), "${unclosed");
+    }
+
+    #[test]
+    fn schedule_covers() {
+        let schedule: Schedule =
+            serde_json::from_value(json!({"start": "08:00", "end": "18:00"})).unwrap();
+        // 2024-06-03 was a Monday.
+        let at = |day: u32, hour: u32, min: u32| {
+            NaiveDate::from_ymd_opt(2024, 6, day)
+                .unwrap()
+                .and_hms_opt(hour, min, 0)
+                .unwrap()
+        };
+        assert!(schedule.covers(at(3, 8, 0)));
+        assert!(schedule.covers(at(3, 12, 30)));
+        assert!(schedule.covers(at(7, 17, 59)));
+        assert!(!schedule.covers(at(3, 7, 59)));
+        assert!(!schedule.covers(at(3, 18, 0)));
+        // Weekends are off unless they're asked for.
+        assert!(!schedule.covers(at(8, 12, 0)));
+        assert!(!schedule.covers(at(9, 12, 0)));
+
+        let weekends: Schedule = serde_json::from_value(
+            json!({"days": ["sat", "Sunday"], "start": "10:00", "end": "12:00"}),
+        )
+        .unwrap();
+        assert!(weekends.covers(at(8, 11, 0)));
+        assert!(weekends.covers(at(9, 10, 0)));
+        assert!(!weekends.covers(at(3, 11, 0)));
     }
 }
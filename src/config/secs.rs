@@ -0,0 +1,139 @@
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use std::fmt;
+
+/*
+Seconds in the config can be a plain number like before, or a duration like "20s", "3m", or
+"1h 30m" for the ones where counting seconds gets old. They're always saved back as numbers.
+*/
+
+struct Secs(i64);
+
+impl<'de> Deserialize<'de> for Secs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SecsVisitor)
+    }
+}
+
+struct SecsVisitor;
+
+impl Visitor<'_> for SecsVisitor {
+    type Value = Secs;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a number of seconds, or a duration like \"20s\" or \"3m\"")
+    }
+
+    fn visit_i64<E: de::Error>(self, secs: i64) -> Result<Secs, E> {
+        Ok(Secs(secs))
+    }
+
+    fn visit_u64<E: de::Error>(self, secs: u64) -> Result<Secs, E> {
+        i64::try_from(secs)
+            .map(Secs)
+            .map_err(|_| E::custom(format!("{secs} seconds is far too long")))
+    }
+
+    fn visit_str<E: de::Error>(self, text: &str) -> Result<Secs, E> {
+        let duration = humantime::parse_duration(text.trim()).map_err(|e| {
+            E::custom(format!(
+                "couldn't make sense of \"{text}\" as a duration ({e}), \
+                 try something like \"20s\", \"3m\", or \"1h 30m\""
+            ))
+        })?;
+        if duration.subsec_nanos() != 0 {
+            return Err(E::custom(format!(
+                "\"{text}\" isn't a whole number of seconds"
+            )));
+        }
+        self.visit_u64(duration.as_secs())
+    }
+}
+
+impl Secs {
+    fn convert<T: TryFrom<i64>, E: de::Error>(self) -> Result<T, E> {
+        T::try_from(self.0).map_err(|_| E::custom(format!("{} seconds won't do here", self.0)))
+    }
+}
+
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<i64>,
+{
+    Secs::deserialize(deserializer)?.convert()
+}
+
+/// For the seconds that are optional, which also need `#[serde(default)]`.
+pub fn deserialize_opt<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<i64>,
+{
+    Option::<Secs>::deserialize(deserializer)?
+        .map(Secs::convert)
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct Settings {
+        #[serde(deserialize_with = "super::deserialize")]
+        interval: u64,
+        #[serde(default, deserialize_with = "super::deserialize_opt")]
+        idle: Option<u32>,
+        #[serde(default, deserialize_with = "super::deserialize_opt")]
+        offset: Option<i64>,
+    }
+
+    fn interval(value: serde_json::Value) -> Result<u64, serde_json::Error> {
+        serde_json::from_value::<Settings>(json!({ "interval": value })).map(|s| s.interval)
+    }
+
+    #[test]
+    fn bare_numbers() {
+        assert_eq!(interval(json!(0)).unwrap(), 0);
+        assert_eq!(interval(json!(20)).unwrap(), 20);
+        assert!(interval(json!(-5)).is_err());
+        assert!(interval(json!(2.5)).is_err());
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!(interval(json!("20s")).unwrap(), 20);
+        assert_eq!(interval(json!("3m")).unwrap(), 180);
+        assert_eq!(interval(json!("1h 30m")).unwrap(), 5400);
+        assert_eq!(interval(json!(" 2h ")).unwrap(), 7200);
+        assert_eq!(interval(json!("1d")).unwrap(), 86400);
+    }
+
+    #[test]
+    fn nonsense() {
+        assert!(interval(json!("")).is_err());
+        assert!(interval(json!("soon")).is_err());
+        assert!(interval(json!("20")).is_err());
+        assert!(interval(json!("1500ms")).is_err());
+        assert!(interval(json!(true)).is_err());
+    }
+
+    #[test]
+    fn optional() {
+        let settings: Settings = serde_json::from_value(json!({ "interval": 1 })).unwrap();
+        assert_eq!((settings.idle, settings.offset), (None, None));
+
+        let settings: Settings =
+            serde_json::from_value(json!({ "interval": 1, "idle": null, "offset": -30 })).unwrap();
+        assert_eq!((settings.idle, settings.offset), (None, Some(-30)));
+
+        let settings: Settings =
+            serde_json::from_value(json!({ "interval": 1, "idle": "5m" })).unwrap();
+        assert_eq!(settings.idle, Some(300));
+
+        let too_long = json!({ "interval": 1, "idle": 5_000_000_000u64 });
+        assert!(serde_json::from_value::<Settings>(too_long).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use crate::config::Config;
+use log::{debug, info, warn};
+use serde_json::{Map, Value};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/*
+Notices when the config file changes while we're running, and picks up what it can without a
+restart, which would lose the capture cadence and set off another backfill. Most settings apply from
+the next frame, and movie settings from the next movie, which goes by what's been picked up here.
+Where things get stored, and anything only looked at on startup, still has to wait for a restart.
+*/
+
+/// Only looked at on startup, or changing them partway through a day would leave it split between
+/// two places or two kinds of frames.
+const NEEDS_RESTART: &[&str] = &[
+    "shot_output_dir",
+    "shot_spool_dir",
+    "vid_output_dir",
+    "per_host_dirs",
+    "day_starts_at_hour",
+    "shot_type",
+    "capture_backend",
+    "capture_scale",
+    "encrypt_shots",
+    "encryption_key_file",
+    "handle_old_dirs_on_startup",
+    "backfill_all_hosts",
+    "backfill_dry_run",
+    "backfill_reencode",
+    "backfill_since",
+    "backfill_until",
+    "backfill_jobs",
+    "backfill_low_priority",
+    "backfill_ffmpeg_threads",
+    "backfill_when_idle_secs",
+];
+
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    config: Config,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|m| m.modified()).ok()
+}
+
+fn fields(config: &Config) -> Map<String, Value> {
+    match serde_json::to_value(config) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+impl ConfigWatcher {
+    pub fn new(config: &Config) -> ConfigWatcher {
+        let path = Config::config_path();
+        ConfigWatcher {
+            modified: modified(&path),
+            path,
+            config: config.clone(),
+        }
+    }
+
+    /// The config to go with from here, if the file changed in a way we can use since last time.
+    pub fn check(&mut self) -> Option<Config> {
+        let modified = modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        // A config that doesn't make sense panics, which shouldn't take capture down with it.
+        let Ok(fresh) = panic::catch_unwind(Config::get_config) else {
+            warn!("{:?} has a problem, sticking with what we had", self.path);
+            return None;
+        };
+
+        let old = fields(&self.config);
+        let mut new = fields(&fresh);
+        let (later, now): (Vec<&str>, Vec<&str>) = new
+            .keys()
+            .filter(|field| old.get(*field) != new.get(*field))
+            .map(String::as_str)
+            .partition(|field| NEEDS_RESTART.contains(field));
+        if !later.is_empty() {
+            warn!(
+                "Changing {} won't do anything until a restart",
+                later.join(", ")
+            );
+        }
+        if now.is_empty() {
+            debug!("Nothing we can use changed in {:?}", self.path);
+            return None;
+        }
+        info!("Picked up new {} from {:?}", now.join(", "), self.path);
+
+        for field in NEEDS_RESTART {
+            if let Some(value) = old.get(*field) {
+                new.insert(field.to_string(), value.clone());
+            }
+        }
+        match serde_json::from_value::<Config>(Value::Object(new)) {
+            Ok(config) => {
+                self.config = config.clone();
+                Some(config)
+            }
+            Err(e) => {
+                warn!("Couldn't put the new config together, sticking with what we had: {e}");
+                None
+            }
+        }
+    }
+}
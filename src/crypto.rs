@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use log::info;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+use crate::day_index::day_key;
+
+/*
+Frames are sealed with XChaCha20-Poly1305 under a raw 32 byte key that lives outside the shot
+directory. Each file is the random 24 byte nonce followed by the ciphertext, with ".enc" tacked on
+the end of the usual name so nothing mistakes it for a real image. Window titles in frames.jsonl
+get the same treatment, base64'd so they still fit in a line of JSON.
+*/
+
+pub const ENCRYPTED_FILE_EXTENSION: &str = "enc";
+
+const NONCE_LEN: usize = 24;
+
+#[derive(Clone)]
+pub struct FrameCipher {
+    cipher: XChaCha20Poly1305,
+}
+
+impl FrameCipher {
+    pub fn from_key_file(path: &Path) -> Result<FrameCipher, Error> {
+        if !path.exists() {
+            info!("No key at {path:?}, making a new one. Back it up or lose your frames!");
+            let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+            Self::write_private(path, key.as_slice())?;
+        }
+
+        let raw = fs::read(path)?;
+        if raw.len() != 32 {
+            return Err(anyhow!(
+                "{path:?} should hold a 32 byte key, found {} bytes",
+                raw.len()
+            ));
+        }
+
+        Ok(FrameCipher {
+            cipher: XChaCha20Poly1305::new(Key::from_slice(&raw)),
+        })
+    }
+
+    pub fn encrypt(&self, plain: &[u8]) -> Result<Vec<u8>, Error> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .cipher
+            .encrypt(&nonce, plain)
+            .map_err(|e| anyhow!("Couldn't encrypt frame: {e}"))?;
+
+        let mut out = nonce.to_vec();
+        out.extend(sealed);
+        Ok(out)
+    }
+
+    pub fn decrypt(&self, sealed: &[u8]) -> Result<Vec<u8>, Error> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow!("Too short to be an encrypted frame"));
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|e| anyhow!("Couldn't decrypt frame (wrong key?): {e}"))
+    }
+
+    pub fn encrypt_text(&self, plain: &str) -> Result<String, Error> {
+        Ok(STANDARD.encode(self.encrypt(plain.as_bytes())?))
+    }
+
+    pub fn decrypt_text(&self, sealed: &str) -> Result<String, Error> {
+        let plain = self.decrypt(&STANDARD.decode(sealed)?)?;
+        Ok(String::from_utf8(plain)?)
+    }
+
+    /// Decrypt every frame in `in_dir` into a fresh directory under the system temp dir. Only we
+    /// can get into it (0700 on unix), and it goes away along with the plaintext when it's
+    /// dropped, however making the movie went.
+    pub fn decrypt_dir_to_temp(&self, in_dir: &Path) -> Result<TempDir, Error> {
+        let out_dir = private_temp_dir(&format!("ompd-{}-", day_key(in_dir)))?;
+
+        for entry in fs::read_dir(in_dir)? {
+            let path = entry?.path();
+            if path.extension() != Some(ENCRYPTED_FILE_EXTENSION.as_ref()) {
+                continue;
+            }
+
+            // Reading through symlinked filler frames is fine, they just turn into real files.
+            let plain = self.decrypt(&fs::read(&path)?)?;
+            let plain_name = path.file_stem().unwrap();
+            Self::write_private(&out_dir.path().join(plain_name), &plain)?;
+        }
+
+        Ok(out_dir)
+    }
+
+    #[cfg(unix)]
+    pub(crate) fn write_private(path: &Path, contents: &[u8]) -> Result<(), Error> {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(contents)?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn write_private(path: &Path, contents: &[u8]) -> Result<(), Error> {
+        Ok(fs::write(path, contents)?)
+    }
+}
+
+/// A fresh directory under the system temp dir that only we can get into (0700 on unix), gone
+/// along with whatever's in it once it's dropped.
+pub fn private_temp_dir(prefix: &str) -> Result<TempDir, Error> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(prefix);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(fs::Permissions::from_mode(0o700));
+    }
+    Ok(builder.tempdir()?)
+}
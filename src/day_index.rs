@@ -0,0 +1,160 @@
+use anyhow::Error;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::time::Duration;
+
+/*
+A little SQLite database at the top of the shot directory that keeps track of every day we've
+captured: how many frames, how big they are, what size they were, and whether there's a movie yet.
+Anything that'd otherwise have to walk every day's directory can ask this instead.
+*/
+
+pub const INDEX_FILE: &str = "ompd-index.sqlite3";
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct DayIndex {
+    conn: Connection,
+}
+
+/// "2023-04-05" out of ".../2023/04/05"
+pub fn day_key(day_dir: &Path) -> String {
+    let parts: Vec<_> = day_dir
+        .iter()
+        .rev()
+        .take(3)
+        .map(|p| p.to_string_lossy())
+        .collect();
+    parts.into_iter().rev().collect::<Vec<_>>().join("-")
+}
+
+impl DayIndex {
+    pub fn open(shot_root: &Path) -> Result<DayIndex, Error> {
+        let conn = Connection::open(shot_root.join(INDEX_FILE))?;
+
+        // The frame writer, movie maker, and backfiller all poke at this from their own threads.
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS days (
+                day TEXT PRIMARY KEY,
+                frames INTEGER NOT NULL DEFAULT 0,
+                bytes INTEGER NOT NULL DEFAULT 0,
+                width INTEGER,
+                height INTEGER,
+                video TEXT,
+                video_settings TEXT
+            );",
+        )?;
+
+        // Indexes from before video settings were kept don't have anywhere to put them yet.
+        let has_settings = conn
+            .prepare("SELECT 1 FROM pragma_table_info('days') WHERE name = 'video_settings'")?
+            .exists([])?;
+        if !has_settings {
+            conn.execute_batch("ALTER TABLE days ADD COLUMN video_settings TEXT")?;
+        }
+
+        Ok(DayIndex { conn })
+    }
+
+    /// Whether we've ever filled this in from what was already on disk. Frames get recorded from
+    /// the moment we start capturing, so having some days in here doesn't say much.
+    pub fn is_seeded(&self) -> Result<bool, Error> {
+        let version: i64 = self
+            .conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        Ok(version >= 1)
+    }
+
+    pub fn mark_seeded(&self) -> Result<(), Error> {
+        self.conn.execute_batch("PRAGMA user_version = 1")?;
+        Ok(())
+    }
+
+    pub fn record_frame(
+        &self,
+        day: &str,
+        bytes: u64,
+        width: u32,
+        height: u32,
+    ) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT INTO days (day, frames, bytes, width, height) VALUES (?1, 1, ?2, ?3, ?4)
+             ON CONFLICT(day) DO UPDATE SET
+                frames = frames + 1,
+                bytes = bytes + excluded.bytes,
+                width = excluded.width,
+                height = excluded.height",
+            params![day, bytes as i64, width, height],
+        )?;
+        Ok(())
+    }
+
+    /// Overwrite the frame count and size for a day, e.g. after counting them up on disk.
+    pub fn set_day(&self, day: &str, frames: u64, bytes: u64) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT INTO days (day, frames, bytes) VALUES (?1, ?2, ?3)
+             ON CONFLICT(day) DO UPDATE SET frames = excluded.frames, bytes = excluded.bytes",
+            params![day, frames as i64, bytes as i64],
+        )?;
+        Ok(())
+    }
+
+    pub fn mark_video(&self, day: &str, video: &Path) -> Result<(), Error> {
+        self.conn.execute(
+            "INSERT INTO days (day, video) VALUES (?1, ?2)
+             ON CONFLICT(day) DO UPDATE SET video = excluded.video",
+            params![day, video.to_string_lossy()],
+        )?;
+        Ok(())
+    }
+
+    /// What the day's movie was made with, as JSON.
+    pub fn set_video_settings(&self, day: &str, settings: &str) -> Result<(), Error> {
+        self.conn.execute(
+            "UPDATE days SET video_settings = ?2 WHERE day = ?1",
+            params![day, settings],
+        )?;
+        Ok(())
+    }
+
+    /// For when the movie we thought we had turns out to be no good.
+    pub fn clear_video(&self, day: &str) -> Result<(), Error> {
+        self.conn.execute(
+            "UPDATE days SET video = NULL, video_settings = NULL WHERE day = ?1",
+            params![day],
+        )?;
+        Ok(())
+    }
+
+    /// Every day we know about, movie or not.
+    pub fn days(&self) -> Result<Vec<String>, Error> {
+        let mut stmt = self.conn.prepare("SELECT day FROM days ORDER BY day")?;
+        let days = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(days)
+    }
+
+    /// Every day with a movie, and what it was made with if we know.
+    pub fn days_with_video(&self) -> Result<Vec<(String, Option<String>)>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT day, video_settings FROM days WHERE video IS NOT NULL ORDER BY day")?;
+        let days = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(days)
+    }
+
+    /// Days with at least `min_frames` frames and no movie.
+    pub fn days_without_video(&self, min_frames: u64) -> Result<Vec<String>, Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT day FROM days WHERE video IS NULL AND frames >= MAX(?1, 1) ORDER BY day",
+        )?;
+        let days = stmt
+            .query_map(params![min_frames as i64], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(days)
+    }
+}
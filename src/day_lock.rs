@@ -0,0 +1,88 @@
+use crate::day_index::day_key;
+use fs2::FileExt;
+use log::info;
+use std::fs::{create_dir_all, File};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/*
+Capture writing today's frames, the movie maker chewing on yesterday's, and retention clearing out
+last month's can all be going at once, in this process or a second one someone launched by accident.
+Whoever's working on a day holds its lock, so nobody compresses or deletes files out from under
+anyone else. Locks live in one spot under the shot root rather than in the day's directory, since
+archiving and retention get rid of that.
+*/
+
+const LOCK_DIR: &str = ".locks";
+
+/// How often to try again for a lock someone else has.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often to say we're still waiting.
+const WAITING_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+pub struct DayLock {
+    // Unlocked when this is dropped and the file gets closed.
+    _file: File,
+}
+
+impl DayLock {
+    /// Wait up to `wait` for, and take, the lock on a day's shots.
+    pub fn acquire(day_dir: &Path, wait: Duration) -> Result<DayLock, anyhow::Error> {
+        let file = Self::lock_file(day_dir)?;
+        if Self::try_lock(&file)? {
+            return Ok(DayLock { _file: file });
+        }
+
+        let day = day_key(day_dir);
+        info!("Something else is working on {day}, waiting up to {wait:?} for it to finish");
+        let started = Instant::now();
+        let mut last_log = started;
+        loop {
+            thread::sleep(RETRY_INTERVAL);
+            if Self::try_lock(&file)? {
+                return Ok(DayLock { _file: file });
+            }
+            if started.elapsed() >= wait {
+                return Err(anyhow::anyhow!(
+                    "Gave up waiting for {day} after {wait:?}, is another ompd working on it?"
+                ));
+            }
+            if last_log.elapsed() >= WAITING_LOG_INTERVAL {
+                info!(
+                    "Still waiting on {day}, {}s so far",
+                    started.elapsed().as_secs()
+                );
+                last_log = Instant::now();
+            }
+        }
+    }
+
+    /// Take the lock on a day's shots if nobody else has it.
+    pub fn try_acquire(day_dir: &Path) -> Result<Option<DayLock>, anyhow::Error> {
+        let file = Self::lock_file(day_dir)?;
+        Ok(Self::try_lock(&file)?.then_some(DayLock { _file: file }))
+    }
+
+    fn lock_file(day_dir: &Path) -> Result<File, anyhow::Error> {
+        let shot_root = day_dir
+            .ancestors()
+            .nth(3)
+            .ok_or_else(|| anyhow::anyhow!("{day_dir:?} doesn't look like a day directory"))?;
+        let lock_dir = shot_root.join(LOCK_DIR);
+        create_dir_all(&lock_dir)?;
+
+        Ok(File::create(
+            lock_dir.join(format!("{}.lock", day_key(day_dir))),
+        )?)
+    }
+
+    fn try_lock(file: &File) -> Result<bool, anyhow::Error> {
+        match file.try_lock_exclusive() {
+            Ok(()) => Ok(true),
+            Err(e) if e.raw_os_error() == fs2::lock_contended_error().raw_os_error() => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
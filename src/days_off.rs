@@ -0,0 +1,86 @@
+use crate::config::Config;
+use chrono::{Days, NaiveDate};
+use log::{debug, warn};
+use std::collections::BTreeSet;
+
+/*
+Days to leave alone entirely, like holidays and time off. Nothing gets captured on them, and the
+backfiller doesn't go looking for their movies. They come from days_off in the config, and from the
+all-day events in days_off_ics, say a calendar exported with just your time off on it. Repeating
+events only count the first time.
+*/
+
+/// Any longer and it's probably not a day off, but a calendar with something odd in it.
+const MAX_EVENT_DAYS: u64 = 366;
+
+#[derive(Debug, Default)]
+pub struct DaysOff(BTreeSet<NaiveDate>);
+
+impl DaysOff {
+    pub fn from_config(config: &Config) -> DaysOff {
+        let mut days: BTreeSet<NaiveDate> = config.days_off.iter().copied().collect();
+        if let Some(ics) = &config.days_off_ics {
+            match std::fs::read_to_string(ics) {
+                Ok(contents) => {
+                    let from_ics = ics_days(&contents);
+                    debug!("{} days off in {ics}", from_ics.len());
+                    days.extend(from_ics);
+                }
+                Err(e) => warn!("Couldn't read days off from {ics}: {e}"),
+            }
+        }
+        DaysOff(days)
+    }
+
+    pub fn contains(&self, day: NaiveDate) -> bool {
+        self.0.contains(&day)
+    }
+}
+
+/// The date in an ICS value, if it's an all-day one like "20240704".
+fn ics_date(value: &str) -> Option<NaiveDate> {
+    if value.len() != 8 {
+        return None;
+    }
+    NaiveDate::parse_from_str(value, "%Y%m%d").ok()
+}
+
+/// Every day covered by an all-day event in `contents`.
+fn ics_days(contents: &str) -> Vec<NaiveDate> {
+    // Long lines get folded onto the next with a leading space or tab.
+    let unfolded = contents
+        .replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+
+    let mut days = Vec::new();
+    let (mut start, mut end) = (None, None);
+    for line in unfolded.lines() {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Parameters like ";VALUE=DATE" come after the name.
+        match name.split(';').next().unwrap_or_default() {
+            "BEGIN" if value == "VEVENT" => (start, end) = (None, None),
+            "DTSTART" => start = ics_date(value.trim()),
+            "DTEND" => end = ics_date(value.trim()),
+            "END" if value == "VEVENT" => {
+                let Some(first) = start else {
+                    continue;
+                };
+                // The end is the day after the last one, and a one day event can leave it out.
+                let last = end
+                    .and_then(|end| end.pred_opt())
+                    .unwrap_or(first)
+                    .max(first);
+                if last > first + Days::new(MAX_EVENT_DAYS) {
+                    warn!("Skipping an event from {first} to {last}, that's a long time off");
+                    continue;
+                }
+                days.extend(first.iter_days().take_while(|day| *day <= last));
+            }
+            _ => {}
+        }
+    }
+    days
+}
@@ -1,35 +1,169 @@
-use chrono::{Datelike, Local};
-use log::{debug, warn};
-use std::fs::{create_dir_all, read_dir, remove_file};
-use std::io::{BufReader, BufWriter};
+use crate::config::{Compression, Config};
+use crate::day_index::day_key;
+use crate::day_lock::DayLock;
+use crate::frame_metadata::{self, FrameMetadata};
+use crate::manifest;
+use crate::video_name::VideoName;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
+use glob::{glob, Pattern};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::fs::{create_dir_all, read_dir, remove_file, rename, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
-use zstd::DEFAULT_COMPRESSION_LEVEL;
+use std::sync::Mutex;
+use std::thread;
 
-const COMPRESSED_FILE_EXTENSION: &str = "zst";
+/// What the other monitors' directories in a day start with, see capture_monitors.
+const MONITOR_DIR_PREFIX: &str = "monitor-";
+
+/// How individual shots get compressed once their day is done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    #[default]
+    Zstd,
+    Lz4,
+    Xz,
+    None,
+}
+
+impl Codec {
+    /// Everything that actually compresses, so we can undo whichever one was used back then.
+    const COMPRESSING: [Codec; 3] = [Codec::Zstd, Codec::Lz4, Codec::Xz];
+
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Codec::Zstd => Some("zst"),
+            Codec::Lz4 => Some("lz4"),
+            Codec::Xz => Some("xz"),
+            Codec::None => None,
+        }
+    }
+
+    fn from_extension(extension: &OsStr) -> Option<Codec> {
+        Self::COMPRESSING
+            .into_iter()
+            .find(|codec| codec.extension().map(OsStr::new) == Some(extension))
+    }
+
+    /// "00001.jpeg.lz4" -> "00001.jpeg", and anything not compressed comes back as is.
+    pub fn strip_extension(name: &str) -> &str {
+        Self::COMPRESSING
+            .iter()
+            .filter_map(|codec| name.strip_suffix(&format!(".{}", codec.extension()?)))
+            .next()
+            .unwrap_or(name)
+    }
+
+    fn encode(
+        &self,
+        mut reader: impl BufRead,
+        writer: impl Write,
+        compression: &Compression,
+    ) -> Result<(), anyhow::Error> {
+        match self {
+            Codec::Zstd => {
+                let mut encoder = DirManager::encoder(writer, compression)?;
+                std::io::copy(&mut reader, &mut encoder)?;
+                encoder.finish()?;
+            }
+            Codec::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(writer);
+                std::io::copy(&mut reader, &mut encoder)?;
+                encoder.finish()?;
+            }
+            Codec::Xz => {
+                let mut writer = writer;
+                lzma_rs::xz_compress(&mut reader, &mut writer)?;
+            }
+            Codec::None => {
+                let mut writer = writer;
+                std::io::copy(&mut reader, &mut writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(
+        &self,
+        mut reader: impl BufRead,
+        mut writer: impl Write,
+    ) -> Result<(), anyhow::Error> {
+        match self {
+            Codec::Zstd => zstd::stream::copy_decode(reader, writer)?,
+            Codec::Lz4 => {
+                std::io::copy(&mut lz4_flex::frame::FrameDecoder::new(reader), &mut writer)?;
+            }
+            Codec::Xz => lzma_rs::xz_decompress(&mut reader, &mut writer)?,
+            Codec::None => {
+                std::io::copy(&mut reader, &mut writer)?;
+            }
+        }
+        Ok(())
+    }
+}
+pub const ARCHIVE_FILE_EXTENSION: &str = "tar.zst";
+
+const IO_ATTEMPTS: u32 = 4;
+const IO_FIRST_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Retention can always get to a day next time, so it doesn't wait around for long.
+const RETENTION_LOCK_WAIT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Network shares like to drop out for a few seconds at a time, so give I/O a few tries, backing
+/// off in between, before calling it a failure.
+pub fn with_retries<T>(
+    what: &str,
+    mut op: impl FnMut() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut backoff = IO_FIRST_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e) if attempt < IO_ATTEMPTS => {
+                debug!("Failed {what} (attempt {attempt}), trying again in {backoff:?}: {e}");
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
 
 pub struct DirManager {
     current_shot_dir: PathBuf,
     shot_dir: PathBuf,
+    vid_dir: PathBuf,
+    day_starts_at_hour: u32,
 }
 
 impl DirManager {
-    pub fn new(shot_dir: &String, vid_dir: &String) -> DirManager {
+    pub fn new(shot_dir: &String, vid_dir: &String, day_starts_at_hour: u32) -> DirManager {
         let shot_dir = PathBuf::from(shot_dir);
         let vid_dir = PathBuf::from(vid_dir);
 
         create_dir_all(&shot_dir).expect("Couldn't create directory for shots!");
-        create_dir_all(vid_dir).expect("Couldn't create directory for videos!");
+        create_dir_all(&vid_dir).expect("Couldn't create directory for videos!");
 
         DirManager {
-            current_shot_dir: Self::get_current_shot_dir_in(&shot_dir),
+            current_shot_dir: Self::get_current_shot_dir_in(&shot_dir, day_starts_at_hour),
             shot_dir,
+            vid_dir,
+            day_starts_at_hour,
         }
     }
 
     pub fn make_shot_output_dir(&mut self) -> std::io::Result<&Path> {
-        self.current_shot_dir = Self::get_current_shot_dir_in(&self.shot_dir);
+        self.current_shot_dir =
+            Self::get_current_shot_dir_in(&self.shot_dir, self.day_starts_at_hour);
 
-        create_dir_all(&self.current_shot_dir).expect("Couldn't create output directory!");
+        with_retries(&format!("creating {:?}", self.current_shot_dir), || {
+            create_dir_all(&self.current_shot_dir)
+        })?;
         Ok(self.current_shot_dir.as_path())
     }
 
@@ -41,21 +175,151 @@ impl DirManager {
         self.current_shot_dir.clone()
     }
 
-    pub fn decompress(target: &Path) {
-        debug!("Going to iterate_and_operate({target:?}, {COMPRESSED_FILE_EXTENSION}, Self::actually_decompress)");
+    /// Whether the disk the shots are going to has less than `min_free_bytes` left.
+    pub fn low_on_space(&self, min_free_bytes: u64) -> bool {
+        if min_free_bytes == 0 {
+            return false;
+        }
+
+        match fs2::available_space(&self.shot_dir) {
+            Ok(free) => free < min_free_bytes,
+            Err(e) => {
+                debug!("Couldn't check free space for {:?}: {e:?}", self.shot_dir);
+                false
+            }
+        }
+    }
+
+    /// Where to put something until it's completely written. Hidden, so nothing globbing for frames
+    /// or movies picks up a half-written one.
+    pub fn partial_path(path: &Path) -> PathBuf {
+        let mut name = std::ffi::OsString::from(".");
+        name.push(path.file_name().unwrap_or_default());
+        path.with_file_name(name)
+    }
+
+    /// Write to a temporary name and rename into place, so losing power mid-write never leaves a
+    /// truncated file behind under the real name.
+    pub fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+        let partial = Self::partial_path(path);
+        {
+            let mut f = File::create(&partial)?;
+            f.write_all(contents)?;
+            f.sync_all()?;
+        }
+        rename(partial, path)
+    }
+
+    /// Where the `n`th of the other monitors' frames go in a day, counting from 1.
+    pub fn monitor_dir(day_dir: &Path, n: usize) -> PathBuf {
+        day_dir.join(format!("{MONITOR_DIR_PREFIX}{n}"))
+    }
+
+    /// Every other monitor's directory in a day, in order.
+    pub fn monitor_dirs(day_dir: &Path) -> Vec<PathBuf> {
+        let Ok(entries) = read_dir(day_dir) else {
+            return Vec::new();
+        };
+
+        let mut numbered: Vec<(usize, PathBuf)> = entries
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let n = name.strip_prefix(MONITOR_DIR_PREFIX)?.parse().ok()?;
+                Some((n, entry.path()))
+            })
+            .collect();
+        numbered.sort();
+        numbered.into_iter().map(|(_, dir)| dir).collect()
+    }
 
-        Self::iterate_and_operate(target, COMPRESSED_FILE_EXTENSION, Self::actually_decompress)
+    /// The frame called `name` in `dir`, whether or not it's been compressed since.
+    pub fn find_frame(dir: &Path, name: &str) -> Option<PathBuf> {
+        let compressed = Codec::COMPRESSING
+            .iter()
+            .filter_map(|codec| Some(dir.join(format!("{name}.{}", codec.extension()?))));
+        std::iter::once(dir.join(name))
+            .chain(compressed)
+            .find(|frame| frame.exists())
     }
 
-    pub fn compress(target: &Path, target_extension: &str) {
-        Self::iterate_and_operate(target, target_extension, Self::actually_compress)
+    /// What's in `frame`, decompressed on the way out if it needs to be.
+    pub fn read_frame(frame: &Path) -> Result<Vec<u8>, anyhow::Error> {
+        let codec = frame
+            .extension()
+            .and_then(Codec::from_extension)
+            .unwrap_or(Codec::None);
+        let mut contents = Vec::new();
+        codec.decode(BufReader::new(File::open(frame)?), &mut contents)?;
+        Ok(contents)
+    }
+
+    pub fn record_frame_in(day_dir: &Path, metadata: &FrameMetadata, contents: &[u8]) {
+        if let Err(e) = frame_metadata::append(day_dir, metadata) {
+            warn!(
+                "Couldn't record metadata for frame {}: {e:?}",
+                metadata.frame
+            );
+        }
+
+        if let Err(e) = manifest::add_frame(day_dir, metadata, contents) {
+            warn!(
+                "Couldn't add frame {} to the day's manifest: {e:?}",
+                metadata.frame
+            );
+        }
+    }
+
+    pub fn record_video_in(day_dir: &Path, video: &Path, encoder: Option<&str>) {
+        if let Err(e) = manifest::set_video(day_dir, video, encoder) {
+            warn!("Couldn't note {video:?} in the manifest for {day_dir:?}: {e:?}");
+        }
+    }
+
+    pub fn record_video_failure_in(day_dir: &Path, reason: &str) {
+        if let Err(e) = manifest::set_video_failure(day_dir, reason) {
+            warn!("Couldn't note the failed movie in the manifest for {day_dir:?}: {e:?}");
+        }
+    }
+
+    /// Undo compress, whichever codec it was done with.
+    pub fn decompress(target: &Path, compression: &Compression) {
+        for codec in Codec::COMPRESSING {
+            let extension = codec.extension().unwrap();
+            debug!(
+                "Going to iterate_and_operate({target:?}, {extension}, Self::actually_decompress)"
+            );
+
+            Self::iterate_and_operate(
+                target,
+                extension,
+                compression.worker_count(),
+                &Self::actually_decompress,
+            )
+        }
+    }
+
+    pub fn compress(target: &Path, target_extension: &str, compression: &Compression) {
+        if compression.codec == Codec::None {
+            return;
+        }
+
+        Self::iterate_and_operate(
+            target,
+            target_extension,
+            compression.worker_count(),
+            &|entry| Self::actually_compress(entry, compression),
+        )
     }
 
     fn iterate_and_operate(
         target: &Path,
         target_extension: &str,
-        op: fn(&Path) -> Result<(), anyhow::Error>,
+        workers: usize,
+        op: &(dyn Fn(&Path) -> Result<(), anyhow::Error> + Sync),
     ) {
+        let mut to_do = Vec::new();
         for entry_maybe in read_dir(target).unwrap() {
             let entry = match entry_maybe {
                 Ok(e) => e,
@@ -83,26 +347,41 @@ impl DirManager {
                 continue;
             }
 
-            let done = op(entry_path.as_path());
-            if let Err(e) = done {
-                warn!("Some issue with {entry:?}: {e:?}");
-            };
+            to_do.push(entry_path);
         }
+
+        // Each file is small, so rather than splitting any one of them up, hand them out to workers
+        // one at a time.
+        let to_do = Mutex::new(to_do.into_iter());
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let next = to_do.lock().unwrap().next();
+                    let Some(entry_path) = next else {
+                        break;
+                    };
+
+                    if let Err(e) = op(entry_path.as_path()) {
+                        warn!("Some issue with {entry_path:?}: {e:?}");
+                    }
+                });
+            }
+        });
     }
 
-    fn actually_compress(entry: &Path) -> Result<(), anyhow::Error> {
+    fn actually_compress(entry: &Path, compression: &Compression) -> Result<(), anyhow::Error> {
         let mut new_file_name = entry.as_os_str().to_owned();
         new_file_name.push(".");
-        new_file_name.push(COMPRESSED_FILE_EXTENSION);
+        new_file_name.push(compression.codec.extension().unwrap());
 
         {
-            let orig_file = std::fs::File::open(entry)?;
+            let orig_file = File::open(entry)?;
             let reader = BufReader::new(&orig_file);
 
-            let compressed_file = std::fs::File::create(&new_file_name)?;
+            let compressed_file = File::create(&new_file_name)?;
             let writer = BufWriter::new(&compressed_file);
 
-            zstd::stream::copy_encode(reader, writer, DEFAULT_COMPRESSION_LEVEL)?;
+            compression.codec.encode(reader, writer, compression)?;
         }
 
         remove_file(entry)?;
@@ -110,22 +389,30 @@ impl DirManager {
         Ok(())
     }
 
+    fn encoder<W: Write>(
+        writer: W,
+        compression: &Compression,
+    ) -> Result<zstd::Encoder<'static, W>, anyhow::Error> {
+        let mut encoder = zstd::Encoder::new(writer, compression.level)?;
+        encoder.long_distance_matching(compression.long_distance_matching)?;
+        Ok(encoder)
+    }
+
     fn actually_decompress(entry: &Path) -> Result<(), anyhow::Error> {
-        let new_file_name = entry
-            .as_os_str()
-            .to_os_string()
-            .into_string()
-            .unwrap()
-            .replace(COMPRESSED_FILE_EXTENSION, "");
+        let codec = entry
+            .extension()
+            .and_then(Codec::from_extension)
+            .ok_or_else(|| anyhow::anyhow!("Don't know how {entry:?} was compressed"))?;
+        let new_file_name = entry.with_extension("");
 
         {
-            let orig_file = std::fs::File::open(entry)?;
+            let orig_file = File::open(entry)?;
             let reader = BufReader::new(&orig_file);
 
-            let compressed_file = std::fs::File::create(new_file_name)?;
-            let writer = BufWriter::new(&compressed_file);
+            let decompressed_file = File::create(new_file_name)?;
+            let writer = BufWriter::new(&decompressed_file);
 
-            zstd::stream::copy_decode(reader, writer)?;
+            codec.decode(reader, writer)?;
         }
 
         remove_file(entry)?;
@@ -133,8 +420,360 @@ impl DirManager {
         Ok(())
     }
 
-    fn get_current_shot_dir_in(root_dir: &Path) -> PathBuf {
-        let now = Local::now();
+    /// Where a day's shots go once they're packed up, next to the day's directory:
+    /// 2023/04/05 -> 2023/04/2023-04-05.tar.zst
+    pub fn archive_path_for(day_dir: &Path) -> PathBuf {
+        day_dir.with_file_name(format!("{}.{ARCHIVE_FILE_EXTENSION}", day_key(day_dir)))
+    }
+
+    /// Pack everything in a day's directory into a single archive, then get rid of the directory.
+    pub fn archive(day_dir: &Path, compression: &Compression) -> Result<PathBuf, anyhow::Error> {
+        let archive_path = Self::archive_path_for(day_dir);
+        let partial = Self::partial_path(&archive_path);
+
+        {
+            let writer = Self::encoder(File::create(&partial)?, compression)?;
+            let mut tar = tar::Builder::new(writer);
+            // Filler frames are symlinks, keep them that way rather than storing copies.
+            tar.follow_symlinks(false);
+            tar.append_dir_all(".", day_dir)?;
+            tar.into_inner()?.finish()?.sync_all()?;
+        }
+        rename(&partial, &archive_path)?;
+
+        std::fs::remove_dir_all(day_dir)?;
+        Ok(archive_path)
+    }
+
+    /// Put a day's directory back the way it was if it got archived. Does nothing if it wasn't.
+    pub fn unarchive(day_dir: &Path) -> Result<(), anyhow::Error> {
+        let archive_path = Self::archive_path_for(day_dir);
+        if !archive_path.is_file() {
+            return Ok(());
+        }
+
+        info!("Unpacking {archive_path:?}");
+        create_dir_all(day_dir)?;
+        let reader = zstd::Decoder::new(File::open(&archive_path)?)?;
+        tar::Archive::new(reader).unpack(day_dir)?;
+
+        remove_file(archive_path)?;
+        Ok(())
+    }
+
+    /// How many frames the day in `day_dir` has, and how many bytes they take up, whether it's been
+    /// archived or not.
+    pub fn count_frames(day_dir: &Path) -> Result<(u64, u64), anyhow::Error> {
+        if !day_dir.is_dir() {
+            return Self::count_archived_frames(day_dir);
+        }
+
+        if let Some(manifest) = manifest::load(day_dir) {
+            return Ok((manifest.frames, manifest.bytes));
+        }
+
+        let (mut frames, mut bytes) = (0, 0);
+        for entry in read_dir(day_dir)? {
+            let entry = entry?;
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(char::is_numeric)
+            {
+                frames += 1;
+                bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+            }
+        }
+        Ok((frames, bytes))
+    }
+
+    /// How many frames, and how many bytes of them, are packed away in a day's archive.
+    pub fn count_archived_frames(day_dir: &Path) -> Result<(u64, u64), anyhow::Error> {
+        let reader = zstd::Decoder::new(File::open(Self::archive_path_for(day_dir))?)?;
+        let mut archive = tar::Archive::new(reader);
+
+        let (mut frames, mut bytes) = (0, 0);
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let is_frame = entry
+                .path()?
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(char::is_numeric));
+            if is_frame {
+                frames += 1;
+                bytes += entry.size();
+            }
+        }
+        Ok((frames, bytes))
+    }
+
+    /// Get rid of (or archive) the shots for days whose movie has been around for long enough.
+    pub fn expire_shots(config: &Config) {
+        let Some(retention) = &config.shot_retention else {
+            return;
+        };
+
+        let shot_root = Path::new(&config.shot_output_dir);
+        let cutoff = Self::shot_day(&Local::now(), config.day_starts_at_hour)
+            - Duration::days(retention.keep_days.into());
+
+        let day_glob = shot_root
+            .join("[0-9][0-9][0-9][0-9]")
+            .join("[0-1][0-9]")
+            .join("[0-3][0-9]");
+        let archive_glob = shot_root
+            .join("[0-9][0-9][0-9][0-9]")
+            .join("[0-1][0-9]")
+            .join(format!(
+                "[0-9][0-9][0-9][0-9]-[0-1][0-9]-[0-3][0-9].{ARCHIVE_FILE_EXTENSION}"
+            ));
+
+        let mut day_dirs = BTreeSet::new();
+        for pattern in [day_glob, archive_glob] {
+            for found in glob(&pattern.to_string_lossy())
+                .unwrap()
+                .filter_map(Result::ok)
+            {
+                if found.is_dir() {
+                    day_dirs.insert(found);
+                } else {
+                    // 2023/04/2023-04-05.tar.zst belongs to 2023/04/05
+                    let day = found.file_name().unwrap().to_string_lossy()[8..10].to_string();
+                    day_dirs.insert(found.with_file_name(day));
+                }
+            }
+        }
+
+        let video_name = config.video_name();
+        for day_dir in day_dirs {
+            let key = day_key(&day_dir);
+            let day = match NaiveDate::parse_from_str(&key, "%Y-%m-%d") {
+                Ok(day) if day < cutoff => day,
+                _ => continue,
+            };
+
+            // Movies only get their real name once they're completely written, so one that's there
+            // and isn't empty is one we can rely on.
+            let video = Path::new(&config.vid_output_dir).join(video_name.for_day(day));
+            if !video.metadata().is_ok_and(|m| m.is_file() && m.len() > 0) {
+                debug!("No movie for {key} yet, keeping its shots");
+                continue;
+            }
+
+            let _lock = match DayLock::acquire(&day_dir, RETENTION_LOCK_WAIT) {
+                Ok(lock) => lock,
+                Err(e) => {
+                    warn!("Couldn't lock {key}, leaving its shots alone: {e:?}");
+                    continue;
+                }
+            };
+            let expired = match retention.action.as_str() {
+                // Already done on some earlier run.
+                "archive" if !day_dir.is_dir() => continue,
+                "archive" => Self::archive(&day_dir, &config.compression).map(|_| ()),
+                _ => Self::delete_day(&day_dir),
+            };
+            match expired {
+                Ok(()) => info!("Expired shots for {key}"),
+                Err(e) => warn!("Couldn't expire shots for {key}: {e:?}"),
+            }
+        }
+    }
+
+    /// Get rid of share copies older than share_copy's keep_days, and the notes saying where they
+    /// were uploaded. The masters they were made from stay.
+    pub fn expire_share_copies(config: &Config) {
+        let (Some(keep_days), Some(share_name)) = (
+            config.share_copy.as_ref().and_then(|share| share.keep_days),
+            config.share_video_name(),
+        ) else {
+            return;
+        };
+
+        let cutoff = Local::now().date_naive() - Duration::days(keep_days.into());
+        let share_glob = Path::new(&config.vid_output_dir).join(share_name.glob());
+        for share in glob(&share_glob.to_string_lossy())
+            .unwrap()
+            .filter_map(Result::ok)
+        {
+            let file_name = share.file_name().unwrap().to_string_lossy().to_string();
+            match share_name.parse(&file_name) {
+                Some(day) if day < cutoff => {}
+                _ => continue,
+            }
+
+            let markers = glob(&format!(
+                "{}.*.uploaded",
+                Pattern::escape(&share.to_string_lossy())
+            ))
+            .unwrap()
+            .filter_map(Result::ok);
+            for expired in std::iter::once(share.clone()).chain(markers) {
+                if let Err(e) = remove_file(&expired) {
+                    warn!("Couldn't expire {expired:?}: {e}");
+                }
+            }
+            info!("Expired the share copy at {share:?}");
+        }
+    }
+
+    fn delete_day(day_dir: &Path) -> Result<(), anyhow::Error> {
+        if day_dir.is_dir() {
+            std::fs::remove_dir_all(day_dir)?;
+        }
+
+        let archive_path = Self::archive_path_for(day_dir);
+        if archive_path.is_file() {
+            remove_file(archive_path)?;
+        }
+
+        // Tidy up the month too if that was the last of it, it's fine if it wasn't.
+        if let Some(month_dir) = day_dir.parent() {
+            let _ = std::fs::remove_dir(month_dir);
+        }
+        Ok(())
+    }
+
+    /// Clear out what crashes leave behind: half-written frames, movies, and archives, empty frames,
+    /// and frames caught halfway through being compressed or decompressed.
+    pub fn clean_up_after_crashes(&self, config: &Config) {
+        // Movies are called whatever video_name_template says, which might not look like ours.
+        let video_names: Vec<VideoName> = std::iter::once(config.video_name())
+            .chain(config.share_video_name())
+            .collect();
+        let mut removed = Self::remove_partials(&self.vid_dir, &video_names);
+
+        let month_glob = self
+            .shot_dir
+            .join("[0-9][0-9][0-9][0-9]")
+            .join("[0-1][0-9]");
+        for month_dir in glob(&month_glob.to_string_lossy())
+            .unwrap()
+            .filter_map(Result::ok)
+        {
+            removed += Self::remove_partials(&month_dir, &[]);
+        }
+
+        let day_glob = month_glob.join("[0-3][0-9]");
+        for day_dir in glob(&day_glob.to_string_lossy())
+            .unwrap()
+            .filter_map(Result::ok)
+        {
+            // Somebody else (another ompd, probably) is busy with it, so what looks half-written
+            // might just be in progress.
+            match DayLock::try_acquire(&day_dir) {
+                Ok(Some(_lock)) => {
+                    removed += Self::remove_partials(&day_dir, &[]);
+                    removed += Self::clean_up_frames(&day_dir);
+                }
+                Ok(None) => debug!("{day_dir:?} is in use, not cleaning it up"),
+                Err(e) => warn!("Couldn't lock {day_dir:?} to clean it up: {e:?}"),
+            }
+        }
+
+        if removed > 0 {
+            info!("Cleaned up {removed} files left behind by earlier crashes");
+        }
+    }
+
+    /// Anything written through partial_path (or capture state/manifest's own .tmp files) that
+    /// never got renamed into place, including movies going by `video_names`.
+    fn remove_partials(dir: &Path, video_names: &[VideoName]) -> usize {
+        let Ok(entries) = read_dir(dir) else {
+            return 0;
+        };
+
+        let mut removed = 0;
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_partial = match name.strip_prefix('.') {
+                Some(hidden) => {
+                    hidden.starts_with("ompd-")
+                        || video_names.iter().any(|n| n.parse(hidden).is_some())
+                        || hidden.starts_with(char::is_numeric)
+                        || hidden.ends_with(ARCHIVE_FILE_EXTENSION)
+                }
+                None => name.ends_with(".json.tmp"),
+            };
+
+            if is_partial && entry.file_type().is_ok_and(|t| t.is_file()) {
+                debug!("Removing partially written {:?}", entry.path());
+                match remove_file(entry.path()) {
+                    Ok(()) => removed += 1,
+                    Err(e) => warn!("Couldn't remove {:?}: {e}", entry.path()),
+                }
+            }
+        }
+        removed
+    }
+
+    fn clean_up_frames(day_dir: &Path) -> usize {
+        let Ok(entries) = read_dir(day_dir) else {
+            return 0;
+        };
+
+        let mut to_remove = Vec::new();
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            // Filler frames are symlinks, leave them be.
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if !metadata.is_file()
+                || !entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(char::is_numeric)
+            {
+                continue;
+            }
+
+            if metadata.len() == 0 {
+                to_remove.push(path);
+                continue;
+            }
+
+            let Some(codec) = path.extension().and_then(Codec::from_extension) else {
+                continue;
+            };
+            let plain = path.with_extension("");
+            if !plain.exists() {
+                continue;
+            }
+
+            // Both being there means we died partway through (de)compressing. Whichever one
+            // decompresses cleanly is the one to keep.
+            let intact = File::open(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|f| codec.decode(BufReader::new(f), std::io::sink()));
+            match intact {
+                Ok(()) => to_remove.push(plain),
+                Err(_) => to_remove.push(path),
+            }
+        }
+
+        for path in &to_remove {
+            debug!("Removing leftover {path:?}");
+            if let Err(e) = remove_file(path) {
+                warn!("Couldn't remove {path:?}: {e}");
+            }
+        }
+        to_remove.len()
+    }
+
+    /// Which day's shots something at `when` belongs with.
+    pub fn day_of(&self, when: &DateTime<Local>) -> NaiveDate {
+        Self::shot_day(when, self.day_starts_at_hour)
+    }
+
+    /// Like day_of, for when there's no DirManager handy. Anything before day_starts_at_hour is
+    /// still part of the day before.
+    pub fn shot_day(when: &DateTime<Local>, day_starts_at_hour: u32) -> NaiveDate {
+        (*when - Duration::hours(day_starts_at_hour.into())).date_naive()
+    }
+
+    fn get_current_shot_dir_in(root_dir: &Path, day_starts_at_hour: u32) -> PathBuf {
+        let now = Self::shot_day(&Local::now(), day_starts_at_hour);
 
         root_dir
             .join(now.year().to_string())
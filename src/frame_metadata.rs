@@ -0,0 +1,76 @@
+use chrono::{DateTime, Local};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+pub const FRAME_METADATA_FILE: &str = "frames.jsonl";
+
+/// One line of a day's frames.jsonl, describing a single stored frame.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FrameMetadata {
+    pub frame: u32,
+    pub captured_at: DateTime<Local>,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub app: Option<String>,
+    /// Encrypted with the shot key when encrypt_shots is on, see FrameCipher::encrypt_text.
+    #[serde(default)]
+    pub window_title: Option<String>,
+    /// How long it'd been since any input when we captured this.
+    #[serde(default)]
+    pub idle_secs: Option<u64>,
+    /// Of the screen this came from, width and height are already in physical pixels.
+    #[serde(default)]
+    pub scale_factor: Option<f32>,
+    /// Set on filler, for how long we were away (a gap in capture or idle). captured_at is when
+    /// the time away started.
+    #[serde(default)]
+    pub away_secs: Option<u64>,
+}
+
+pub fn append(day_dir: &Path, metadata: &FrameMetadata) -> Result<(), anyhow::Error> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(day_dir.join(FRAME_METADATA_FILE))?;
+
+    // In one write, since filler gets noted from a different thread than captured frames.
+    let mut line = serde_json::to_string(metadata)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+pub fn read_all(day_dir: &Path) -> Vec<FrameMetadata> {
+    let file = match File::open(day_dir.join(FRAME_METADATA_FILE)) {
+        Ok(f) => f,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut all = Vec::new();
+    for line in BufReader::new(file).lines().map_while(Result::ok) {
+        // A crash mid-write can leave a partial last line, don't let that ruin everything else.
+        match serde_json::from_str(&line) {
+            Ok(m) => all.push(m),
+            Err(e) => warn!("Skipping bad frame metadata in {day_dir:?}: {e}"),
+        }
+    }
+
+    all
+}
+
+pub fn most_common_dimensions(day_dir: &Path) -> Option<(u32, u32)> {
+    let mut counts: HashMap<(u32, u32), usize> = HashMap::new();
+    for m in read_all(day_dir) {
+        *counts.entry((m.width, m.height)).or_default() += 1;
+    }
+
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(dimensions, _)| dimensions)
+}
@@ -0,0 +1,334 @@
+use glob::glob;
+use log::{debug, error, info, warn};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use crate::capture_state::{self, CaptureState};
+use crate::day_index::{day_key, DayIndex};
+use crate::day_lock::DayLock;
+use crate::dir_manager::{with_retries, DirManager};
+use crate::frame_metadata::FrameMetadata;
+
+/*
+Writing a frame to a slow disk or a NAS can take longer than the capture interval, so the capture
+loop hands finished frames off to this thread instead of writing them itself. The queue is bounded
+so a disk that's gone away entirely eventually pushes back instead of eating all our memory.
+*/
+
+const QUEUE_DEPTH: usize = 32;
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Not long, since frames queue up behind us while we wait and we write anyway if we give up.
+const DAY_LOCK_WAIT: Duration = Duration::from_secs(10);
+
+static FOR_SHUTDOWN: OnceLock<FrameWriter> = OnceLock::new();
+
+enum Job {
+    Write {
+        path: PathBuf,
+        contents: Vec<u8>,
+        day_dir: PathBuf,
+        metadata: FrameMetadata,
+    },
+    /// One of the other monitors' frames, which goes along with the main frame written just
+    /// before it.
+    WriteMonitor {
+        path: PathBuf,
+        contents: Vec<u8>,
+    },
+    Flush(mpsc::Sender<()>),
+}
+
+#[derive(Clone)]
+pub struct FrameWriter {
+    tx: SyncSender<Job>,
+}
+
+impl FrameWriter {
+    pub fn spawn(shot_root: PathBuf, spool_dir: Option<PathBuf>) -> FrameWriter {
+        let (tx, rx) = mpsc::sync_channel(QUEUE_DEPTH);
+        let spool = spool_dir.map(|dir| Spool { dir });
+
+        thread::Builder::new()
+            .name("frame-writer".into())
+            .spawn(move || Self::work(rx, &shot_root, spool))
+            .expect("Couldn't spawn the frame writer thread!");
+
+        let writer = FrameWriter { tx };
+        let _ = FOR_SHUTDOWN.set(writer.clone());
+        writer
+    }
+
+    pub fn write(
+        &self,
+        path: PathBuf,
+        contents: Vec<u8>,
+        day_dir: PathBuf,
+        metadata: FrameMetadata,
+    ) {
+        let job = Job::Write {
+            path,
+            contents,
+            day_dir,
+            metadata,
+        };
+
+        if self.tx.send(job).is_err() {
+            panic!("Frame writer thread is gone, can't save screenshots!");
+        }
+    }
+
+    pub fn write_monitor(&self, path: PathBuf, contents: Vec<u8>) {
+        if self.tx.send(Job::WriteMonitor { path, contents }).is_err() {
+            panic!("Frame writer thread is gone, can't save screenshots!");
+        }
+    }
+
+    /// Wait for everything queued so far to hit the disk.
+    pub fn flush(&self) {
+        self.flush_within(None);
+    }
+
+    fn flush_within(&self, timeout: Option<Duration>) {
+        let (done_tx, done_rx) = mpsc::channel();
+        if self.tx.send(Job::Flush(done_tx)).is_err() {
+            warn!("Frame writer thread is gone, nothing to flush");
+            return;
+        }
+
+        let flushed = match timeout {
+            Some(t) => done_rx.recv_timeout(t).is_ok(),
+            None => done_rx.recv().is_ok(),
+        };
+
+        if !flushed {
+            warn!("Gave up waiting for queued frames to be written");
+        }
+    }
+
+    fn work(rx: Receiver<Job>, shot_root: &Path, spool: Option<Spool>) {
+        let index = DayIndex::open(shot_root)
+            .map_err(|e| warn!("Couldn't open the day index, not keeping it up to date: {e:?}"))
+            .ok();
+
+        let mut day_lock: Option<(PathBuf, DayLock)> = None;
+
+        for job in rx {
+            match job {
+                Job::Write {
+                    path,
+                    contents,
+                    day_dir,
+                    metadata,
+                } => {
+                    if day_lock.as_ref().map(|(locked, _)| locked) != Some(&day_dir) {
+                        // Let go of yesterday first so its movie can get going.
+                        day_lock = None;
+                        match DayLock::acquire(&day_dir, DAY_LOCK_WAIT) {
+                            Ok(lock) => day_lock = Some((day_dir.clone(), lock)),
+                            Err(e) => warn!("Couldn't lock {day_dir:?}, writing anyway: {e:?}"),
+                        }
+                    }
+
+                    // Anything already spooled goes first, and if that doesn't work there's no
+                    // point waiting on the shot directory again for this one.
+                    let target_is_up = match &spool {
+                        Some(spool) => spool.drain(shot_root, index.as_ref()),
+                        None => true,
+                    };
+
+                    if target_is_up {
+                        debug!("Writing out a file to {path:?}");
+                        let written = with_retries(&format!("writing {path:?}"), || {
+                            DirManager::write_atomically(&path, &contents)
+                        });
+                        match written {
+                            Ok(()) => {
+                                Self::record(index.as_ref(), &day_dir, &metadata, &contents, true);
+                                continue;
+                            }
+                            Err(e) => error!("Couldn't save screenshot {path:?}: {e:?}"),
+                        }
+                    }
+
+                    if let Some(spool) = &spool {
+                        let spooled = path
+                            .strip_prefix(shot_root)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|relative| spool.hold(relative, &contents, &metadata));
+                        match spooled {
+                            Ok(()) => info!("Holding on to {path:?} until it can be written"),
+                            Err(e) => error!("Couldn't spool {path:?} either, it's lost: {e:?}"),
+                        }
+                    }
+                }
+                Job::WriteMonitor { path, contents } => {
+                    // These don't get spooled, the main frame is the one that counts.
+                    debug!("Writing out a file to {path:?}");
+                    let written = with_retries(&format!("writing {path:?}"), || {
+                        std::fs::create_dir_all(path.parent().unwrap())?;
+                        DirManager::write_atomically(&path, &contents)
+                    });
+                    if let Err(e) = written {
+                        error!("Couldn't save screenshot {path:?}: {e:?}");
+                    }
+                }
+                Job::Flush(done) => {
+                    let _ = done.send(());
+                }
+            }
+        }
+    }
+
+    /// Everything that has to happen once a frame is safely on disk.
+    fn record(
+        index: Option<&DayIndex>,
+        day_dir: &Path,
+        metadata: &FrameMetadata,
+        contents: &[u8],
+        save_state: bool,
+    ) {
+        DirManager::record_frame_in(day_dir, metadata, contents);
+
+        if let Some(index) = index {
+            let indexed = index.record_frame(
+                &day_key(day_dir),
+                contents.len() as u64,
+                metadata.width,
+                metadata.height,
+            );
+            if let Err(e) = indexed {
+                warn!(
+                    "Couldn't add frame {} to the day index: {e:?}",
+                    metadata.frame
+                );
+            }
+        }
+
+        if !save_state {
+            return;
+        }
+
+        let state = CaptureState {
+            next_frame: metadata.frame + 1,
+            last_capture: metadata.captured_at,
+            width: metadata.width,
+            height: metadata.height,
+        };
+        if let Err(e) = capture_state::save(day_dir, &state) {
+            warn!("Couldn't save capture state in {day_dir:?}: {e:?}");
+        }
+    }
+}
+
+/*
+A local stand-in for the shot directory while it's unreachable (a NAS that's asleep, a VPN that
+dropped). Frames land here with their metadata alongside, mirroring where they belong, and get moved
+over as soon as the real thing takes writes again.
+*/
+struct Spool {
+    dir: PathBuf,
+}
+
+const SPOOLED_METADATA_SUFFIX: &str = ".spooled.json";
+
+impl Spool {
+    fn hold(
+        &self,
+        relative: &Path,
+        contents: &[u8],
+        metadata: &FrameMetadata,
+    ) -> Result<(), anyhow::Error> {
+        let frame = self.dir.join(relative);
+        std::fs::create_dir_all(frame.parent().unwrap())?;
+        DirManager::write_atomically(&frame, contents)?;
+        DirManager::write_atomically(&Self::metadata_path(&frame), &serde_json::to_vec(metadata)?)?;
+        Ok(())
+    }
+
+    fn metadata_path(frame: &Path) -> PathBuf {
+        let mut name = frame.as_os_str().to_owned();
+        name.push(SPOOLED_METADATA_SUFFIX);
+        PathBuf::from(name)
+    }
+
+    /// Move everything we're holding over to the shot directory. Returns whether it all made it.
+    fn drain(&self, shot_root: &Path, index: Option<&DayIndex>) -> bool {
+        let pattern = self
+            .dir
+            .join("**")
+            .join(format!("*{SPOOLED_METADATA_SUFFIX}"));
+        let mut held: Vec<PathBuf> = glob(&pattern.to_string_lossy())
+            .unwrap()
+            .filter_map(Result::ok)
+            .collect();
+        if held.is_empty() {
+            return true;
+        }
+        held.sort();
+
+        for metadata_path in held {
+            let frame = PathBuf::from(
+                metadata_path
+                    .to_string_lossy()
+                    .strip_suffix(SPOOLED_METADATA_SUFFIX)
+                    .unwrap(),
+            );
+            let target = shot_root.join(frame.strip_prefix(&self.dir).unwrap());
+
+            match Self::move_over(&frame, &metadata_path, &target) {
+                Ok((contents, metadata)) => {
+                    debug!("Moved spooled {frame:?} to {target:?}");
+                    Self::cleanup(&frame, &metadata_path);
+                    // Whatever is queued behind these is newer, let it be the one to save where
+                    // capture left off.
+                    FrameWriter::record(
+                        index,
+                        target.parent().unwrap(),
+                        &metadata,
+                        &contents,
+                        false,
+                    );
+                }
+                Err(e) => {
+                    debug!("Shot directory still isn't taking writes: {e:?}");
+                    return false;
+                }
+            }
+        }
+
+        info!("Caught up on everything that was spooled");
+        true
+    }
+
+    fn move_over(
+        frame: &Path,
+        metadata_path: &Path,
+        target: &Path,
+    ) -> Result<(Vec<u8>, FrameMetadata), anyhow::Error> {
+        let contents = std::fs::read(frame)?;
+        let metadata = serde_json::from_slice(&std::fs::read(metadata_path)?)?;
+
+        std::fs::create_dir_all(target.parent().unwrap())?;
+        DirManager::write_atomically(target, &contents)?;
+        Ok((contents, metadata))
+    }
+
+    fn cleanup(frame: &Path, metadata_path: &Path) {
+        for path in [frame, metadata_path] {
+            if let Err(e) = std::fs::remove_file(path) {
+                warn!("Couldn't clear {path:?} out of the spool: {e}");
+            }
+        }
+    }
+}
+
+/// Make sure frames we've already captured make it to disk before we exit.
+pub fn flush_before_exit() {
+    if let Some(writer) = FOR_SHUTDOWN.get() {
+        writer.flush_within(Some(SHUTDOWN_FLUSH_TIMEOUT));
+    }
+}
@@ -1,22 +1,126 @@
 mod back_filler;
 use back_filler::BackFiller;
+pub use back_filler::{backfill_progress, BackfillProgress};
+mod capture_health;
+mod capture_state;
 mod capturer;
 pub mod config;
+mod config_watcher;
+mod crypto;
+mod day_index;
+mod day_lock;
+mod days_off;
 mod dir_manager;
+mod frame_metadata;
+mod frame_writer;
+mod manifest;
 pub mod movie_maker;
+mod notify;
+mod uploader;
+pub mod video_name;
 
-use capturer::Capturer;
-use chrono::Local;
+use capture_health::CaptureHealth;
+use capturer::{Capturer, IdleState, Skipped};
+use chrono::{Datelike, Local, NaiveDate};
 use config::Config;
+use config_watcher::ConfigWatcher;
+use days_off::DaysOff;
 use dir_manager::DirManager;
 use log::{error, info, warn};
-use movie_maker::MovieMaker;
+use movie_maker::{MovieError, MovieMaker};
+use std::path::PathBuf;
 use std::thread;
 
-pub fn run(config: Config) {
-    let sleep_interval = std::time::Duration::from_secs(config.interval);
-    let mut d = DirManager::new(&config.shot_output_dir, &config.vid_output_dir);
-    let mut c = Capturer::new(&sleep_interval, &config.shot_type);
+/// Call before exiting so frames that have been captured but not written yet don't get lost.
+pub fn flush_pending_frames() {
+    frame_writer::flush_before_exit();
+}
+
+/// Makes `finished_day`'s movie from `shot_dir` in the background, unless `movie_made` already,
+/// along with the montage or review of the month or year that ended if `new_day` starts a new one.
+fn spawn_movie_maker(
+    config: Config,
+    shot_dir: PathBuf,
+    finished_day: NaiveDate,
+    new_day: NaiveDate,
+    movie_made: bool,
+) {
+    let moviemaker_maybe = thread::Builder::new()
+        .name("moviemaker".into())
+        .spawn(move || {
+            // TODO: Fire up a resizer before doing the movie making, compress when done.
+            info!("Launching movie maker");
+            // NOTE: This is whatever the watcher last took, so it's got any changes since we
+            // started, without a broken config file taking the movie down with it.
+            match MovieMaker::new(config.clone()) {
+                Ok(m) => {
+                    // Already made when the schedule ended for the day.
+                    if !movie_made {
+                        match m.make_movie_from(shot_dir.as_path()) {
+                            Ok(_) => {}
+                            Err(MovieError::TooFewFrames { .. }) => {
+                                info!("Not much happened on {finished_day}, so no movie");
+                            }
+                            Err(e) => {
+                                error!("Couldn't make a movie of {shot_dir:?}: {e:?}");
+                                notify::desktop(
+                                    &format!("ompd couldn't make the movie for {finished_day}"),
+                                    &e.to_string(),
+                                );
+                            }
+                        }
+                    }
+                    if (finished_day.year(), finished_day.month())
+                        < (new_day.year(), new_day.month())
+                    {
+                        m.make_month_montage_if_missing(finished_day.year(), finished_day.month());
+                    }
+                    if finished_day.year() < new_day.year() {
+                        m.make_year_review_if_missing(finished_day.year());
+                    }
+                }
+                Err(e) => {
+                    error!("Couldn't get ready to make the movie for {finished_day}: {e:?}");
+                    notify::desktop(
+                        &format!("ompd couldn't make the movie for {finished_day}"),
+                        &e.to_string(),
+                    );
+                }
+            }
+
+            DirManager::expire_shots(&config);
+            DirManager::expire_share_copies(&config);
+        });
+
+    if let Err(e) = moviemaker_maybe {
+        warn!("Couldn't spawn movie maker thread! {e:?}");
+    }
+}
+
+pub fn run(mut config: Config) {
+    let mut sleep_interval = std::time::Duration::from_secs(config.interval);
+    let mut d = DirManager::new(
+        &config.shot_output_dir,
+        &config.vid_output_dir,
+        config.day_starts_at_hour,
+    );
+    d.clean_up_after_crashes(&config);
+    let mut c = match Capturer::new(&config) {
+        Ok(c) => c,
+        Err(e) => {
+            error!("Can't capture anything: {e:?}");
+            notify::desktop("ompd can't capture anything", &e.to_string());
+            return;
+        }
+    };
+    let mut health = CaptureHealth::new(sleep_interval, &config.capture_retry);
+    let mut min_free_bytes = config.min_free_disk_mb * 1024 * 1024;
+    let mut disk_full = false;
+    let mut watcher = ConfigWatcher::new(&config);
+    let mut days_off = DaysOff::from_config(&config);
+    let mut off_schedule = false;
+    // Which day's movie got made early, because the schedule ended for the day.
+    let mut movie_made_for: Option<NaiveDate> = None;
 
     let starting_time = Local::now();
     let mut last_time = starting_time;
@@ -42,18 +146,123 @@ pub fn run(config: Config) {
     let made_output_d = d.make_shot_output_dir();
     if let Err(e) = made_output_d {
         error!("Couldn't make an output directory: {e:?}");
-        panic!("Couldn't make an output directory!");
+        if config.shot_spool_dir.is_none() {
+            panic!("Couldn't make an output directory!");
+        }
+        warn!("Spooling shots until it's reachable");
     }
 
     c.discover_current_frame(&mut d);
 
+    // If we were already capturing today, count from the last frame we stored so the time we were
+    // down gets filled in like any other gap.
+    if let Some(last_capture) = c.last_capture() {
+        if d.day_of(&last_capture) == d.day_of(&starting_time) && last_capture < starting_time {
+            last_time = last_capture;
+        }
+    }
+
     loop {
+        if let Some(fresh) = watcher.check() {
+            sleep_interval = std::time::Duration::from_secs(fresh.interval);
+            if fresh.interval != config.interval || fresh.capture_retry != config.capture_retry {
+                health = CaptureHealth::new(sleep_interval, &fresh.capture_retry);
+            }
+            min_free_bytes = fresh.min_free_disk_mb * 1024 * 1024;
+            c.reconfigure(&config, &fresh);
+            days_off = DaysOff::from_config(&fresh);
+            config = fresh;
+        }
+
+        if d.low_on_space(min_free_bytes) {
+            if !disk_full {
+                disk_full = true;
+                let message = format!(
+                    "Less than {} MB free for shots, pausing capture until there's room",
+                    config.min_free_disk_mb
+                );
+                error!("{message}");
+                notify::desktop("ompd paused", &message);
+                if let Err(e) = c.deal_with_disk_full(&d) {
+                    warn!("Couldn't even make a disk full frame: {e:?}");
+                }
+            }
+            thread::sleep(sleep_interval);
+            continue;
+        } else if disk_full {
+            info!("There's room on the disk again, back to capturing");
+            disk_full = false;
+        }
+
+        let now = Local::now();
+        let on_schedule = config
+            .schedule
+            .as_ref()
+            .is_none_or(|schedule| schedule.covers(now.naive_local()));
+        if !on_schedule || days_off.contains(d.day_of(&now)) {
+            if !off_schedule {
+                off_schedule = true;
+                info!("Outside the capture schedule or on a day off, pausing until that's over");
+                // The day's over as far as the schedule goes, no need to wait for midnight.
+                let today = d.day_of(&now);
+                let captured_today = c
+                    .last_capture()
+                    .is_some_and(|last| d.day_of(&last) == today);
+                if captured_today && movie_made_for != Some(today) {
+                    c.flush();
+                    spawn_movie_maker(
+                        config.clone(),
+                        d.get_current_shot_dir(),
+                        today,
+                        today,
+                        false,
+                    );
+                    movie_made_for = Some(today);
+                }
+            }
+            thread::sleep(sleep_interval);
+            continue;
+        }
+        if off_schedule {
+            info!("Back to capturing");
+            off_schedule = false;
+            // There's more of the day after all, so its movie needs making again.
+            if movie_made_for == Some(d.day_of(&now)) {
+                movie_made_for = None;
+            }
+        }
+
+        match c.idle_state() {
+            IdleState::Idle => {
+                thread::sleep(sleep_interval);
+                continue;
+            }
+            IdleState::Resumed(idle_since) => {
+                let now = Local::now();
+
+                // If we went idle yesterday, let the regular gap handling below kick off the movie.
+                if d.day_of(&now) == d.day_of(&last_time) {
+                    let idle_secs = (now - idle_since).num_seconds() as u64;
+                    if let Err(e) = c.deal_with_idle(idle_secs, &d) {
+                        warn!("Couldn't make an idle frame: {e:?}");
+                    }
+                    last_time = now;
+                }
+            }
+            IdleState::Active => {}
+        }
+
         let capture_result = c.capture_screen();
         if let Err(e) = capture_result {
-            info!("Couldn't get a good screenshot ({:?}), skip this frame", e);
-            thread::sleep(sleep_interval);
+            if e.is::<Skipped>() {
+                info!("Couldn't get a good screenshot ({:?}), skip this frame", e);
+                thread::sleep(sleep_interval);
+            } else {
+                thread::sleep(health.failed(&e));
+            }
             continue;
         }
+        health.succeeded();
 
         let now = Local::now();
 
@@ -73,28 +282,25 @@ pub fn run(config: Config) {
             if let Ok(capturer::ChangeType::NewDay) = change_result {
                 info!("Brand new day! Let's goooooo");
 
-                let shot_dir = d.get_current_shot_dir();
-                let moviemaker_maybe =
-                    thread::Builder::new()
-                        .name("moviemaker".into())
-                        .spawn(move || {
-                            // TODO: Fire up a resizer before doing the movie making, compress when done.
-                            info!("Launching movie maker");
-                            // NOTE: Get a fresh copy of the config in case something
-                            // has changed since we started.
-                            let m = MovieMaker::new(Config::get_config());
-                            m.make_movie_from(shot_dir.as_path());
-                        });
-
-                if let Err(e) = moviemaker_maybe {
-                    warn!("Couldn't spawn movie maker thread! {e:?}");
-                }
+                // Don't start on yesterday's movie until all of yesterday is on disk.
+                c.flush();
+
+                let finished_day = d.day_of(&last_time);
+                spawn_movie_maker(
+                    config.clone(),
+                    d.get_current_shot_dir(),
+                    finished_day,
+                    d.day_of(&now),
+                    movie_made_for == Some(finished_day),
+                );
 
                 // Get ready for today to make sure we have the right path to make movies in.
                 let made_output_dir = d.make_shot_output_dir();
                 if let Err(e) = made_output_dir {
                     error!("Couldn't make new output directory?!: {e:?}");
-                    break;
+                    if config.shot_spool_dir.is_none() {
+                        break;
+                    }
                 }
                 c.set_current_frame(0);
             }
@@ -102,9 +308,9 @@ pub fn run(config: Config) {
             // The only other type is Nop and ... that's a Nop
         }
 
-        c.store(capture_result.unwrap(), d.current_shot_dir());
+        c.store(capture_result.unwrap(), &d);
         last_time = now;
 
-        thread::sleep(sleep_interval);
+        thread::sleep(c.next_interval());
     }
 }
@@ -4,8 +4,10 @@ mod not_windows;
 #[cfg(target_os = "windows")]
 mod windows;
 
+use clap::Parser;
 use env_logger::Builder;
 use log::LevelFilter;
+use std::path::PathBuf;
 
 #[cfg(not(target_os = "windows"))]
 use not_windows::ctrl_c_exit;
@@ -13,25 +15,87 @@ use not_windows::ctrl_c_exit;
 #[cfg(target_os = "windows")]
 use windows::ctrl_c_exit;
 
-use ompd::config::Config;
+use ompd::config::{Config, Overrides};
+
+/// Takes a screenshot every so often, and makes a movie out of each day's.
+///
+/// Everything else lives in the config file, these just win over it (and over OMPD_ environment
+/// variables) for this run.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Read the config from here instead of the usual place
+    #[arg(short, long, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Seconds between screenshots
+    #[arg(short, long, value_name = "SECS")]
+    interval: Option<u64>,
+    /// Where screenshots go
+    #[arg(long, value_name = "DIR")]
+    shot_dir: Option<String>,
+    /// Where movies go
+    #[arg(long, value_name = "DIR")]
+    vid_dir: Option<String>,
+    /// Don't go back and make movies for past days on startup
+    #[arg(long)]
+    no_backfill: bool,
+    /// off, error, warn, info, debug, or trace
+    #[arg(short, long, value_name = "LEVEL")]
+    log_level: Option<LevelFilter>,
+    /// Go over the config and list everything wrong with it, then exit
+    #[arg(long)]
+    check_config: bool,
+}
+
+/// Prints what's wrong with the config, if anything, and exits accordingly.
+fn check_config() -> ! {
+    let (config_path, problems) = Config::check_config();
+    if problems.is_empty() {
+        println!("{} looks good", config_path.display());
+        std::process::exit(0);
+    }
+
+    println!(
+        "{} problems with {}:",
+        problems.len(),
+        config_path.display()
+    );
+    for problem in problems {
+        println!("  - {problem}");
+    }
+    std::process::exit(1);
+}
 
 fn main() {
+    let args = Args::parse();
+
     ctrlc::set_handler(move || {
+        ompd::flush_pending_frames();
         ctrl_c_exit();
     })
     .expect("Couldn't set a clean exit handler!");
 
-    let level_filter = if cfg!(debug_assertions) {
+    let level_filter = args.log_level.unwrap_or(if cfg!(debug_assertions) {
         LevelFilter::max()
     } else {
         LevelFilter::Info
-    };
+    });
 
     Builder::new()
         .filter_level(level_filter)
         .filter_module("wmi", LevelFilter::Error)
         .init();
 
+    Config::set_overrides(Overrides {
+        config_path: args.config,
+        interval: args.interval,
+        shot_output_dir: args.shot_dir,
+        vid_output_dir: args.vid_dir,
+        no_backfill: args.no_backfill,
+    });
+    if args.check_config {
+        check_config();
+    }
     let config = Config::get_config();
     ompd::run(config);
 }
@@ -0,0 +1,92 @@
+use crate::frame_metadata::FrameMetadata;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// A summary of everything in a day's directory, kept up to date as frames come in so nobody has
+/// to go walk the directory to find out.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub frames: u64,
+    pub bytes: u64,
+    pub first_capture: Option<DateTime<Local>>,
+    pub last_capture: Option<DateTime<Local>>,
+    /// e.g. "1920x1080"
+    pub resolutions: BTreeSet<String>,
+    /// The movie made from this day, once there is one.
+    pub video: Option<PathBuf>,
+    /// Which encoder ffmpeg ended up making it with.
+    #[serde(default)]
+    pub video_encoder: Option<String>,
+    /// What was wrong with the last movie made from this day, if it didn't pass muster.
+    #[serde(default)]
+    pub video_failure: Option<String>,
+    /// Degrees clockwise to turn this day's frames by, instead of video_rotation. Only ever set
+    /// by hand, for the days a monitor spent on its side.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<u16>,
+    /// SHA-256 chained over every frame in the order they were written, hex encoded.
+    pub checksum: String,
+}
+
+impl Manifest {
+    fn add_frame(&mut self, metadata: &FrameMetadata, contents: &[u8]) {
+        self.frames += 1;
+        self.bytes += contents.len() as u64;
+        self.first_capture.get_or_insert(metadata.captured_at);
+        self.last_capture = Some(metadata.captured_at);
+        self.resolutions
+            .insert(format!("{}x{}", metadata.width, metadata.height));
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.checksum.as_bytes());
+        hasher.update(Sha256::digest(contents));
+        self.checksum = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+    }
+}
+
+pub fn load(day_dir: &Path) -> Option<Manifest> {
+    let raw = fs::read(day_dir.join(MANIFEST_FILE)).ok()?;
+    serde_json::from_slice(&raw).ok()
+}
+
+fn save(day_dir: &Path, manifest: &Manifest) -> Result<(), anyhow::Error> {
+    // Same as capture state, never leave a half-written one behind.
+    let tmp_path = day_dir.join(format!("{MANIFEST_FILE}.tmp"));
+    fs::write(&tmp_path, serde_json::to_vec_pretty(manifest)?)?;
+    fs::rename(tmp_path, day_dir.join(MANIFEST_FILE))?;
+    Ok(())
+}
+
+pub fn add_frame(
+    day_dir: &Path,
+    metadata: &FrameMetadata,
+    contents: &[u8],
+) -> Result<(), anyhow::Error> {
+    let mut manifest = load(day_dir).unwrap_or_default();
+    manifest.add_frame(metadata, contents);
+    save(day_dir, &manifest)
+}
+
+pub fn set_video(day_dir: &Path, video: &Path, encoder: Option<&str>) -> Result<(), anyhow::Error> {
+    let mut manifest = load(day_dir).unwrap_or_default();
+    manifest.video = Some(video.to_path_buf());
+    manifest.video_encoder = encoder.map(String::from);
+    manifest.video_failure = None;
+    save(day_dir, &manifest)
+}
+
+pub fn set_video_failure(day_dir: &Path, reason: &str) -> Result<(), anyhow::Error> {
+    let mut manifest = load(day_dir).unwrap_or_default();
+    manifest.video_failure = Some(reason.to_string());
+    save(day_dir, &manifest)
+}
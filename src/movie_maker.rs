@@ -1,35 +1,285 @@
+mod builtin;
+mod chapters;
+mod clock;
+mod error;
+mod monitors;
+mod month_montage;
+mod poster;
+mod probe;
+mod progress;
+mod quality;
+mod segments;
+mod selection;
+mod settings;
+mod share_copy;
+mod subtitles;
+mod timing;
+mod title_card;
+mod watermark;
+mod year_review;
+
+use crate::config::{
+    Compression, DropStatic, FillerFrame, MonthMontage, ShareCopy, TimestampOverlay, TitleCard,
+    VideoFit, VideoSubtitles, Watermark, YearReview,
+};
+use crate::crypto::{private_temp_dir, FrameCipher, ENCRYPTED_FILE_EXTENSION};
+use crate::day_index::{day_key, DayIndex};
+use crate::day_lock::DayLock;
+use crate::dir_manager::Codec;
+use crate::manifest;
+use crate::uploader::Uploader;
+use crate::video_name::VideoName;
 use crate::Config;
 use crate::DirManager;
-use anyhow::Error;
-use log::error;
+use anyhow::{Context, Error};
+pub use builtin::VIDEO_TYPE as BUILTIN_VIDEO_TYPE;
+use chrono::{DateTime, Local, NaiveDate};
+pub use error::MovieError;
+use glob::{glob, Pattern};
 use log::{debug, info, warn};
-use std::fs;
+pub use monitors::MONITOR_LAYOUTS;
+use progress::Tracker;
+pub use progress::{current_progress, EncodeProgress};
+pub use quality::QUALITY_PRESETS;
+pub use settings::VideoSettings;
+pub use share_copy::SHARE_VIDEO_TYPE;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output, Stdio};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+pub use subtitles::SUBTITLE_CODECS;
+use timing::Timeline;
+
+/// Where frames that won't decode get moved to, inside their day's directory.
+const QUARANTINE_DIR: &str = "quarantine";
+
+/// Long enough for whoever else has the day to finish making its movie.
+const DAY_LOCK_WAIT: Duration = Duration::from_secs(60 * 60);
+
+#[cfg(target_os = "macos")]
+const HARDWARE_ENCODERS: &[&str] = &["h264_videotoolbox"];
+#[cfg(target_os = "windows")]
+const HARDWARE_ENCODERS: &[&str] = &["h264_nvenc", "h264_qsv", "h264_amf"];
+#[cfg(target_os = "linux")]
+const HARDWARE_ENCODERS: &[&str] = &["h264_nvenc", "h264_vaapi"];
+#[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+const HARDWARE_ENCODERS: &[&str] = &[];
+
+const VAAPI_DEVICE: &str = "/dev/dri/renderD128";
+
+/// How much nicer than usual background ffmpegs are, out of 19.
+#[cfg(not(windows))]
+const NICENESS: &str = "10";
+
+/// Shot types ffmpeg can pick apart when they're piped in back to back, and what decodes them.
+const STREAM_DECODERS: &[(&str, &str)] = &[
+    ("bmp", "bmp"),
+    ("jpeg", "mjpeg"),
+    ("jpg", "mjpeg"),
+    ("png", "png"),
+    ("webp", "webp"),
+];
+
+/// Degrees clockwise frames can be turned by, in movies.
+pub const VALID_ROTATIONS: &[u16] = &[0, 90, 180, 270];
+
+/// How much of ffmpeg's complaining to hang on to when it fails.
+const STDERR_TAIL_LINES: usize = 20;
+
+/// For background_audio, when aiming for video_target_mb.
+const AUDIO_BITRATE: u64 = 128_000;
+
+/// Below this it's all mush anyway, so go over video_target_mb rather than lower.
+const MIN_TARGET_BITRATE: u64 = 50_000;
+
+/// Containers that keep their index in a moov atom, which ffmpeg puts at the end unless asked.
+const FASTSTART_VIDEO_TYPES: &[&str] = &["mp4", "mov", "m4v"];
+
+/// What to look for when background_audio is a directory.
+const AUDIO_EXTENSIONS: &[&str] = &["aac", "flac", "m4a", "mp3", "ogg", "opus", "wav"];
+
+/// ffmpeg filters turning frames `degrees` clockwise.
+fn rotate_filters(degrees: u16) -> Vec<String> {
+    match degrees {
+        90 => vec!["transpose=clock".into()],
+        180 => vec!["hflip".into(), "vflip".into()],
+        270 => vec!["transpose=cclock".into()],
+        _ => Vec::new(),
+    }
+}
+
+fn is_hardware_encoder(encoder: &str) -> bool {
+    ["_nvenc", "_qsv", "_amf", "_vaapi", "_videotoolbox"]
+        .iter()
+        .any(|suffix| encoder.ends_with(suffix))
+}
+
+/// Checking takes a few ffmpeg runs, so only do it once.
+static DETECTED_ENCODER: OnceLock<Option<String>> = OnceLock::new();
+
+/// What ffmpeg reads from.
+struct FfmpegInput {
+    /// Input options and inputs, in order.
+    args: Vec<String>,
+    /// Frames to decompress and feed it, for an input reading from stdin.
+    stdin_frames: Option<Vec<PathBuf>>,
+    /// Degrees clockwise to turn the frames by, before anything else happens to them.
+    rotation: u16,
+    /// A filter_complex putting the other monitors' inputs next to the frames, which then does the
+    /// turning instead.
+    stack: Option<String>,
+}
+
+/// What goes into ffmpeg besides the frames.
+struct Extras {
+    /// Go after scaling, so they work in output pixels.
+    filters: Vec<String>,
+    /// An ffmetadata file with chapters in it.
+    chapters: Option<PathBuf>,
+    /// To play under the movie.
+    audio: Option<PathBuf>,
+    /// Instead of video_bitrate, to hit video_target_mb.
+    bitrate: Option<u64>,
+    /// An image to show before the frames.
+    title_card: Option<PathBuf>,
+    /// Every frame to feed ffmpeg through stdin, title card and all, instead of having it read
+    /// them off the disk.
+    stream: Option<Vec<PathBuf>>,
+    /// How long each frame after the title card is up for, when they aren't all the same.
+    durations: Option<Vec<f64>>,
+    subtitles: Option<PathBuf>,
+    rotation: u16,
+    /// A concat list of each other monitor's frames, to stack next to these.
+    monitors: Vec<PathBuf>,
+}
 
 pub struct MovieMaker {
     output_dir: PathBuf,
-    frame_rate: u32,
+    shot_root: PathBuf,
+    video_fps: Option<u32>,
+    target_video_seconds: Option<u32>,
     file_extension: String,
     output_width: u32,
     output_height: u32,
     ffmpeg: String,
     compress_when_done: bool,
+    archive_when_done: bool,
+    compression: Compression,
     output_type: String,
+    video_name: VideoName,
+    share_copy: Option<ShareCopy>,
+    share_name: Option<VideoName>,
+    video_fit: VideoFit,
+    monitor_layout: Option<String>,
+    video_rotation: u16,
+    video_timing: String,
+    gap_speedup: Option<u32>,
+    skip_gaps: bool,
+    keep_gap_cards: bool,
+    drop_static: Option<DropStatic>,
+    capture_interval: u64,
+    video_encoder: String,
+    builtin_encoder: bool,
+    video_codec: Option<String>,
+    video_encoder_fallbacks: Vec<String>,
+    video_crf: Option<u8>,
+    video_preset: Option<String>,
+    video_bitrate: Option<String>,
+    video_target_mb: Option<f64>,
+    video_segments: Option<u32>,
+    min_frames_for_video: u64,
+    low_priority: bool,
+    ffmpeg_threads: Option<u32>,
+    ffmpeg_input_args: Vec<String>,
+    ffmpeg_output_args: Vec<String>,
+    year_review: Option<YearReview>,
+    month_montage: Option<MonthMontage>,
+    video_clock: Option<TimestampOverlay>,
+    video_subtitles: Option<VideoSubtitles>,
+    title_card: Option<TitleCard>,
+    filler_frame: FillerFrame,
+    watermark: Option<Watermark>,
+    video_poster: bool,
+    background_audio: Option<PathBuf>,
+    cipher: Option<FrameCipher>,
+    uploader: Uploader,
 }
 
 impl MovieMaker {
-    pub fn new(config: Config) -> MovieMaker {
-        MovieMaker {
+    pub fn new(config: Config) -> Result<MovieMaker, MovieError> {
+        let config = quality::with_preset(config);
+        let cipher = if config.encrypt_shots {
+            let key_file = Path::new(&config.encryption_key_file);
+            let cipher = FrameCipher::from_key_file(key_file)
+                .with_context(|| format!("Couldn't load the shot encryption key {key_file:?}"))?;
+            Some(cipher)
+        } else {
+            None
+        };
+        Ok(MovieMaker {
+            uploader: Uploader::new(&config),
+            video_name: config.video_name(),
+            share_name: config.share_video_name(),
             output_dir: PathBuf::from(config.vid_output_dir),
-            frame_rate: ((9 * 60 * 60) / 20) / 60,
+            shot_root: PathBuf::from(config.shot_output_dir),
+            video_fps: config.video_fps,
+            target_video_seconds: config.target_video_seconds,
             file_extension: config.shot_type,
             output_width: config.vid_width,
             output_height: config.vid_height,
             ffmpeg: config.ffmpeg,
             compress_when_done: config.compress_shots,
+            archive_when_done: config.archive_shots,
+            compression: config.compression,
             output_type: config.video_type,
-        }
+            video_fit: config.video_fit,
+            monitor_layout: config.monitor_layout,
+            video_rotation: config.video_rotation,
+            video_timing: config.video_timing,
+            gap_speedup: config.gap_speedup,
+            skip_gaps: config.skip_gaps,
+            keep_gap_cards: config.keep_gap_cards,
+            drop_static: config.drop_static,
+            capture_interval: config.interval,
+            video_encoder: config.video_encoder,
+            builtin_encoder: config.video_backend == "builtin",
+            video_codec: config.video_codec,
+            video_encoder_fallbacks: config.video_encoder_fallbacks,
+            video_crf: config.video_crf,
+            video_preset: config.video_preset,
+            video_bitrate: config.video_bitrate,
+            video_target_mb: config.video_target_mb,
+            video_segments: config.video_segments,
+            min_frames_for_video: config.min_frames_for_video,
+            low_priority: false,
+            ffmpeg_threads: None,
+            ffmpeg_input_args: config.ffmpeg_input_args,
+            ffmpeg_output_args: config.ffmpeg_output_args,
+            year_review: config.year_review,
+            month_montage: config.month_montage,
+            video_clock: config.video_clock,
+            video_subtitles: config.video_subtitles,
+            title_card: config.title_card,
+            filler_frame: config.filler_frame,
+            watermark: config.watermark,
+            video_poster: config.video_poster,
+            share_copy: config.share_copy,
+            background_audio: config.background_audio.map(PathBuf::from),
+            cipher,
+        })
+    }
+
+    /// For making movies without getting in the way, like when backfilling, going by
+    /// backfill_low_priority and backfill_ffmpeg_threads.
+    pub fn for_backfill(mut self, config: &Config) -> MovieMaker {
+        self.low_priority = config.backfill_low_priority;
+        self.ffmpeg_threads = config.backfill_ffmpeg_threads;
+        self
     }
 
     pub fn has_muxer(ffmpeg: &str, extension: &str) -> Result<bool, Error> {
@@ -38,9 +288,9 @@ impl MovieMaker {
         let output = Command::new(ffmpeg)
             .arg("-muxers")
             .output()
-            .unwrap_or_else(|_| panic!("Couldn't ask {} for muxers!", ffmpeg));
+            .map_err(|e| anyhow::anyhow!("Couldn't run {ffmpeg} to ask for its muxers: {e}"))?;
 
-        let stdout_raw = String::from_utf8(output.stdout).unwrap();
+        let stdout_raw = String::from_utf8_lossy(&output.stdout);
         let stdout = stdout_raw.lines().collect::<Vec<_>>();
 
         let needle = format!("E  {extension}");
@@ -58,101 +308,1254 @@ impl MovieMaker {
         ))
     }
 
-    pub fn make_movie_from(&self, input_dir: &Path) {
-        self.fix_missing_frames(input_dir);
-
-        let mut ancestors = input_dir.ancestors();
-        let day = ancestors
-            .next()
-            .unwrap()
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap();
-        let month = ancestors
-            .next()
-            .unwrap()
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap();
-        let year = ancestors
-            .next()
-            .unwrap()
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap();
-
-        let out_f = format!("ompd-{}-{}-{}.{}", year, month, day, &self.output_type);
-
-        let mut to_run = Command::new(&self.ffmpeg);
-        to_run.args([
-            // Frame rate to generate
-            "-r",
-            &self.frame_rate.to_string(),
-            // Where to find input frames and what format to expect
-            "-i",
-            &input_dir
-                .join(format!("%05d.{}", self.file_extension))
-                .to_string_lossy(),
-            // Output size
-            "-s",
-            &format!("{}x{}", self.output_width, self.output_height),
-            // Pixel format -- maybe only relevant on MacOS?
-            "-pix_fmt",
-            "yuv420p",
+    /// Makes the movie of the day in `input_dir`, and returns where it ended up.
+    pub fn make_movie_from(&self, input_dir: &Path) -> Result<PathBuf, MovieError> {
+        let _lock = DayLock::acquire(input_dir, DAY_LOCK_WAIT)?;
+        let (frames, _) = DirManager::count_frames(input_dir)?;
+        if frames < self.min_frames_for_video {
+            return Err(MovieError::TooFewFrames {
+                frames,
+                min: self.min_frames_for_video,
+            });
+        }
+        DirManager::unarchive(input_dir)?;
+        let capture_times = (self.video_clock.is_some() || self.video_timing == "captured")
+            .then(|| clock::capture_times(input_dir, &self.file_extension));
+
+        let key = day_key(input_dir);
+        let date = NaiveDate::parse_from_str(&key, "%Y-%m-%d")
+            .map_err(|e| anyhow::anyhow!("{input_dir:?} isn't a day's directory: {e}"))?;
+        let out_path = self.output_dir.join(self.video_name.for_day(date));
+        // Keeps the extension so ffmpeg still knows which muxer to use.
+        let partial_path = DirManager::partial_path(&out_path);
+
+        // ffmpeg can't read encrypted frames, so give it decrypted copies to chew on instead.
+        // They're cleaned up whenever this goes out of scope, errors and panics included.
+        let decrypted = match &self.cipher {
+            Some(cipher) => Some(cipher.decrypt_dir_to_temp(input_dir)?),
+            None => None,
+        };
+        let frames_dir = decrypted
+            .as_ref()
+            .map_or(input_dir.to_path_buf(), |d| d.path().to_path_buf());
+
+        let made = self.encode_day(
+            input_dir,
+            &frames_dir,
+            capture_times.as_ref(),
+            date,
+            &out_path,
+        );
+
+        if let Some(Err(e)) = decrypted.map(|d| d.close()) {
+            warn!("Couldn't clean up decrypted frames in {frames_dir:?}: {e}");
+        }
+
+        // Compressing or archiving the stills behind a broken movie would leave nothing to watch,
+        // so bail before getting that far.
+        let used_encoder = match made {
+            Ok(used_encoder) => used_encoder,
+            Err(e) => {
+                if let MovieError::Invalid(reason) = &e {
+                    DirManager::record_video_failure_in(input_dir, reason);
+                }
+                if partial_path.exists() {
+                    if let Err(e) = fs::remove_file(&partial_path) {
+                        warn!("Couldn't remove the unfinished movie at {partial_path:?}: {e}");
+                    }
+                }
+                return Err(e);
+            }
+        };
+
+        // Only a finished movie gets the real name, a half-made one would look done to BackFiller.
+        if let Err(e) = File::open(&partial_path).and_then(|f| f.sync_all()) {
+            warn!("Couldn't flush {partial_path:?} to disk: {e}");
+        }
+        fs::rename(&partial_path, &out_path)?;
+
+        DirManager::record_video_in(input_dir, &out_path, used_encoder.as_deref());
+
+        let indexed = DayIndex::open(&self.shot_root).and_then(|index| {
+            index.mark_video(&key, &out_path)?;
+            index.set_video_settings(&key, &serde_json::to_string(&self.video_settings())?)
+        });
+        if let Err(e) = indexed {
+            warn!("Couldn't note the new movie in the day index: {e:?}");
+        }
+
+        // With a share copy around, that's the one to send.
+        if self.share_copy.is_none() {
+            self.uploader.upload(&out_path);
+        } else {
+            match self.make_share_copy(date) {
+                Ok(share) => self.uploader.upload(&share),
+                Err(e) => {
+                    warn!("Couldn't make a share copy of {out_path:?}, will try again: {e:?}")
+                }
+            }
+        }
+
+        // Encrypted frames are already as small as they're going to get, but archiving them still
+        // saves on file count.
+        if self.archive_when_done {
+            info!("Archiving stills");
+            match DirManager::archive(input_dir, &self.compression) {
+                Ok(archive) => info!("Packed {input_dir:?} into {archive:?}"),
+                Err(e) => warn!("Couldn't archive {input_dir:?}: {e:?}"),
+            }
+        } else if self.compress_when_done && self.cipher.is_none() {
+            info!("Compressing stills");
+            DirManager::compress(input_dir, self.file_extension.as_str(), &self.compression);
+            for monitor_dir in DirManager::monitor_dirs(input_dir) {
+                DirManager::compress(
+                    &monitor_dir,
+                    self.file_extension.as_str(),
+                    &self.compression,
+                );
+            }
+        }
+        info!("All done with {input_dir:?}!");
+        Ok(out_path)
+    }
+
+    /// Encodes the frames in `frames_dir` into the partial path for `out_path`, checks how it
+    /// came out, and saves a poster next to it. Returns the encoder that made it, None meaning
+    /// ffmpeg's default.
+    fn encode_day(
+        &self,
+        input_dir: &Path,
+        frames_dir: &Path,
+        capture_times: Option<&BTreeMap<usize, DateTime<Local>>>,
+        date: NaiveDate,
+        out_path: &Path,
+    ) -> Result<Option<String>, MovieError> {
+        // Compressed frames can be decompressed on their way into ffmpeg, which saves writing the
+        // whole day back out to disk first. Not with other monitors to line up with, though.
+        let stacking = self.monitor_layout.is_some()
+            && !self.builtin_encoder
+            && !DirManager::monitor_dirs(input_dir).is_empty();
+        let streaming = self.fix_missing_frames(
+            input_dir,
+            frames_dir,
+            self.stream_decoder().is_some() && !stacking,
+        )?;
+        let partial_path = DirManager::partial_path(out_path);
+
+        let all_frames = Self::contiguous_frames(frames_dir, &self.file_extension);
+        let selected = self.select_frames(input_dir, frames_dir, all_frames);
+        if selected.len() < all_frames {
+            info!("Using {} of {all_frames} frames", selected.len());
+        }
+        let source_dir = if selected.len() < all_frames && !streaming {
+            selection::link_selected(frames_dir, &self.file_extension, &selected)?
+        } else {
+            frames_dir.to_path_buf()
+        };
+
+        let frames = selected.len();
+        let (rate_num, rate_den) = self.frame_rate(frames);
+        info!("Making a movie of {frames} frames at {rate_num}/{rate_den} fps");
+        let rotation = self.rotation_for(input_dir);
+
+        // Anything ffmpeg needs besides the frames themselves.
+        // Its own for every encode, since a backfill can be making the same date for another host
+        // at the same time. Subtitles have window titles in them, so it's private too.
+        let work = private_temp_dir(&format!("ompd-{date}-"))?;
+        let work_dir = work.path().to_path_buf();
+        let title_card = self.title_card_image(date);
+        let title_card_path = match &title_card {
+            // ffmpeg turns it along with the frames, so it has to start out turned the other way.
+            Some(card) if !self.builtin_encoder => {
+                let card = builtin::rotate(card.clone(), (360 - rotation) % 360);
+                // Piped in frames all have to be the same kind of image.
+                let extension = if streaming {
+                    &self.file_extension
+                } else {
+                    "png"
+                };
+                self.write_title_card(&card, &work_dir, extension)
+            }
+            _ => None,
+        };
+        let title_card = title_card.filter(|_| self.builtin_encoder || title_card_path.is_some());
+        let lead_in = match title_card {
+            Some(_) => self.title_card_frames((rate_num, rate_den)),
+            None => 0,
+        };
+        // Captured timing only goes as far as ffmpeg's concat demuxer.
+        let captured = match capture_times {
+            Some(times) if self.video_timing == "captured" && !self.builtin_encoder => {
+                let filler = selection::filler_frames(input_dir, &self.file_extension, all_frames);
+                Some(Timeline::captured(
+                    times,
+                    &selected,
+                    &filler,
+                    lead_in,
+                    (rate_num, rate_den),
+                ))
+            }
+            _ => None,
+        };
+        let timeline = captured
+            .clone()
+            .unwrap_or_else(|| Timeline::even(frames + lead_in, (rate_num, rate_den)));
+
+        let filters = match (&self.video_clock, capture_times) {
+            (Some(overlay), Some(times)) => self
+                .clock_filters(overlay, times, &selected, &timeline, lead_in, &work_dir)
+                .unwrap_or_else(|e| {
+                    warn!("Couldn't set up the clock, making the movie without it: {e:?}");
+                    Vec::new()
+                }),
+            _ => Vec::new(),
+        };
+
+        let chapters = self.chapters_for(input_dir, &selected, &timeline, lead_in, &work_dir);
+        let subtitles = self.subtitles_for(input_dir, &selected, &timeline, lead_in, &work_dir);
+
+        let mut tracker = Tracker::new(&date.to_string(), frames + lead_in);
+        let encoded = if self.builtin_encoder {
+            let watermark = self
+                .watermark_image(self.output_width & !1)
+                .unwrap_or_else(|e| {
+                    warn!("Couldn't load the watermark, making the movie without it: {e:?}");
+                    None
+                });
+            builtin::encode(
+                frames + lead_in,
+                (rate_num, rate_den),
+                (self.output_width, self.output_height),
+                &partial_path,
+                &mut tracker,
+                |frame, size| {
+                    let mut img = match title_card.as_ref().filter(|_| frame < lead_in) {
+                        Some(title_card) => title_card.clone(),
+                        None => {
+                            let frame = frame - lead_in;
+                            let path =
+                                source_dir.join(format!("{frame:05}.{}", self.file_extension));
+                            let img = builtin::rotate(image::open(path)?.to_rgb8(), rotation);
+                            builtin::fit(&img, size, &self.video_fit)
+                        }
+                    };
+                    if let Some(watermark) = &watermark {
+                        self.apply_watermark(&mut img, watermark);
+                    }
+                    Ok(img)
+                },
+            )
+            .map(|_| Some(builtin::ENCODER_NAME.to_string()))
+            .map_err(|e| MovieError::Other(e.context("Issue with the built in encoder")))
+        } else {
+            let audio = self.pick_audio();
+            let has_audio = audio.is_some();
+            let stream = streaming.then(|| {
+                let card = title_card_path.iter().cycle().take(lead_in).cloned();
+                let frames = selected.iter().filter_map(|frame| {
+                    let name = format!("{frame:05}.{}", self.file_extension);
+                    DirManager::find_frame(frames_dir, &name)
+                });
+                card.chain(frames).collect()
+            });
+            let durations = captured.map(|timeline| timeline.durations()[lead_in..].to_vec());
+            let frame_secs = rate_den as f64 / rate_num as f64;
+            // Without them, the movie's still worth making with just the main monitor.
+            let monitors = self
+                .monitors_for(input_dir, &selected, frame_secs, durations.as_deref())
+                .unwrap_or_else(|e| {
+                    warn!("Couldn't get the other monitors ready, leaving them out: {e:?}");
+                    None
+                });
+            let extras = Extras {
+                filters,
+                chapters,
+                audio,
+                bitrate: self.target_bitrate(frames + lead_in, (rate_num, rate_den), has_audio),
+                title_card: title_card_path,
+                stream,
+                durations,
+                subtitles,
+                rotation,
+                monitors: monitors.as_ref().map_or(Vec::new(), |m| m.lists.clone()),
+            };
+            let in_pieces = self.segments_for(frames, &extras).and_then(|pieces| {
+                self.encode_in_segments(
+                    &date.to_string(),
+                    &source_dir,
+                    &partial_path,
+                    &pieces,
+                    (rate_num, rate_den),
+                    &extras,
+                )
+                .inspect_err(|e| warn!("Couldn't make it in pieces, trying it in one go: {e}"))
+                .ok()
+            });
+            let encoded = match in_pieces {
+                Some(used_encoder) => Ok(used_encoder),
+                None => self.encode_with_ffmpeg(
+                    input_dir,
+                    &source_dir,
+                    &partial_path,
+                    (rate_num, rate_den),
+                    &extras,
+                    &mut tracker,
+                ),
+            };
+            if let Some(Err(e)) = monitors.map(|m| m.frames.close()) {
+                warn!("Couldn't clean up the other monitors' frames: {e}");
+            }
+            encoded
+        };
+
+        let checked = encoded.and_then(|used_encoder| {
+            self.check_movie(&partial_path, frames + lead_in, (rate_num, rate_den))
+                .map_err(|e| MovieError::Invalid(e.to_string()))?;
+            Ok(used_encoder)
+        });
+
+        // Has to happen before the decrypted frames go away.
+        if self.video_poster && checked.is_ok() {
+            match self.make_poster(input_dir, frames_dir, &selected, out_path) {
+                Ok(poster) => info!("Saved a poster at {poster:?}"),
+                Err(e) => warn!("Couldn't make a poster for {out_path:?}: {e:?}"),
+            }
+        }
+
+        if let Err(e) = work.close() {
+            warn!("Couldn't clean up {work_dir:?}: {e}");
+        }
+        if source_dir.exists() && source_dir != frames_dir {
+            if let Err(e) = fs::remove_dir_all(&source_dir) {
+                warn!("Couldn't clean up {source_dir:?}: {e}");
+            }
+        }
+
+        checked
+    }
+
+    /// Returns the encoder that worked, None meaning ffmpeg's default.
+    fn encode_with_ffmpeg(
+        &self,
+        input_dir: &Path,
+        frames_dir: &Path,
+        partial_path: &Path,
+        rate: (u32, u32),
+        extras: &Extras,
+        tracker: &mut Tracker,
+    ) -> Result<Option<String>, MovieError> {
+        let (mut input, mut output_options) = self.frames_input(frames_dir, rate, extras, None)?;
+        for list in &extras.monitors {
+            input.extend([
+                "-f".into(),
+                "concat".into(),
+                "-safe".into(),
+                "0".into(),
+                "-i".into(),
+                list.to_string_lossy().into(),
+            ]);
+        }
+        let (extra_inputs, extra_options) = self.extra_inputs(extras);
+        input.extend(extra_inputs);
+        // Anything for the output has to wait until every input is in.
+        output_options.extend(extra_options);
+        input.extend(output_options);
+
+        let (rate_num, rate_den) = rate;
+        let lead_in_secs = match extras.title_card {
+            Some(_) => self.title_card_frames(rate) as f64 * rate_den as f64 / rate_num as f64,
+            None => 0.0,
+        };
+        let input = FfmpegInput {
+            args: input,
+            stdin_frames: extras.stream.clone(),
+            rotation: extras.rotation,
+            stack: self.stack_filter(extras.monitors.len(), extras.rotation, lead_in_secs),
+        };
+        self.encode(
+            &input,
+            &extras.filters,
+            input_dir,
+            partial_path,
+            extras.bitrate,
+            tracker,
+        )
+    }
+
+    /// Input options for reading the frames, and any output options that go with them. With
+    /// `frames`, only that stretch of them (plus the title card, if it starts at the first).
+    fn frames_input(
+        &self,
+        frames_dir: &Path,
+        (rate_num, rate_den): (u32, u32),
+        extras: &Extras,
+        frames: Option<Range<usize>>,
+    ) -> Result<(Vec<String>, Vec<String>), Error> {
+        let frame_rate = format!("{rate_num}/{rate_den}");
+        if let Some(decoder) = self.stream_decoder().filter(|_| extras.stream.is_some()) {
+            return Ok((
+                vec![
+                    "-f".into(),
+                    "image2pipe".into(),
+                    "-framerate".into(),
+                    frame_rate,
+                    "-c:v".into(),
+                    decoder.into(),
+                    "-i".into(),
+                    "-".into(),
+                ],
+                Vec::new(),
+            ));
+        }
+
+        if self.file_extension == "avif"
+            || extras.title_card.is_some()
+            || extras.durations.is_some()
+            || frames.is_some()
+        {
+            // ffmpeg's image sequence reader doesn't know AVIF, but it can read them one at a time
+            // through its mp4 demuxer, so hand it a concat list instead. Same for a title card,
+            // which is a different kind of image than the frames.
+            let frame_secs = rate_den as f64 / rate_num as f64;
+            let lead_in = self.title_card_frames((rate_num, rate_den)) as f64 * frame_secs;
+            // fix_missing_frames already made sure there aren't any holes.
+            let frames = frames
+                .unwrap_or_else(|| 0..Self::contiguous_frames(frames_dir, &self.file_extension));
+            let title_card = extras
+                .title_card
+                .as_deref()
+                .filter(|_| frames.start == 0)
+                .map(|card| (card, lead_in));
+            let durations = extras.durations.as_deref();
+            let list =
+                self.write_concat_list(frames_dir, frames, frame_secs, durations, title_card)?;
+            // A constant output rate would drop the frames that are up for less than a frame
+            // and repeat the rest, undoing the durations, so let each one keep its own.
+            let output_rate = match durations {
+                Some(_) => vec!["-fps_mode".into(), "vfr".into()],
+                None => vec!["-r".into(), frame_rate],
+            };
+            return Ok((
+                vec![
+                    "-f".into(),
+                    "concat".into(),
+                    "-safe".into(),
+                    "0".into(),
+                    "-i".into(),
+                    list.to_string_lossy().into(),
+                ],
+                output_rate,
+            ));
+        }
+
+        Ok((
+            vec![
+                // Frame rate to generate
+                "-r".into(),
+                frame_rate,
+                // Where to find input frames and what format to expect
+                "-i".into(),
+                frames_dir
+                    .join(format!("%05d.{}", self.file_extension))
+                    .to_string_lossy()
+                    .into(),
+            ],
+            Vec::new(),
+        ))
+    }
+
+    /// Inputs for the audio, subtitles, and chapters in `extras`, coming after the frames, and
+    /// the output options that put them in the movie.
+    fn extra_inputs(&self, extras: &Extras) -> (Vec<String>, Vec<String>) {
+        let mut input: Vec<String> = Vec::new();
+        let mut output_options: Vec<String> = Vec::new();
+        let mut inputs = 1 + extras.monitors.len();
+        // Stacked monitors come out of the filter_complex, rather than straight from the frames.
+        let stacked = !extras.monitors.is_empty();
+        let video = match stacked {
+            true => "[v]",
+            false => "0:v:0",
+        };
+        if stacked {
+            output_options.extend(["-map".into(), video.into()]);
+        }
+        if let Some(audio) = &extras.audio {
+            // Loop it for as long as it takes, then cut it off when the frames run out.
+            input.extend([
+                "-stream_loop".into(),
+                "-1".into(),
+                "-i".into(),
+                audio.to_string_lossy().into(),
+            ]);
+            if !stacked {
+                output_options.extend(["-map".into(), video.into()]);
+            }
+            output_options.extend(["-map".into(), format!("{inputs}:a:0"), "-shortest".into()]);
+            inputs += 1;
+        }
+        if let (Some(subtitles), Some(codec)) = (&extras.subtitles, self.subtitle_codec()) {
+            input.extend(["-i".into(), subtitles.to_string_lossy().into()]);
+            // Once anything's mapped by hand, everything has to be.
+            if extras.audio.is_none() && !stacked {
+                output_options.extend(["-map".into(), video.into()]);
+            }
+            output_options.extend([
+                "-map".into(),
+                format!("{inputs}:s:0"),
+                "-c:s".into(),
+                codec.into(),
+            ]);
+            inputs += 1;
+        }
+        if let Some(chapters) = &extras.chapters {
+            input.extend(["-i".into(), chapters.to_string_lossy().into()]);
+            output_options.extend(["-map_chapters".into(), inputs.to_string()]);
+        }
+        if extras.audio.is_some() && extras.bitrate.is_some() {
+            output_options.extend(["-b:a".into(), AUDIO_BITRATE.to_string()]);
+        }
+        (input, output_options)
+    }
+
+    /// Runs ffmpeg on `input` through each encoder in turn, leaving its output in `log_dir`.
+    /// `filters` go after scaling, so they work in output pixels. With a `bitrate`, software
+    /// encoders get two passes to hit it. Returns the encoder that worked, None meaning ffmpeg's
+    /// default.
+    fn encode(
+        &self,
+        input: &FfmpegInput,
+        filters: &[String],
+        log_dir: &Path,
+        partial_path: &Path,
+        bitrate: Option<u64>,
+        tracker: &mut Tracker,
+    ) -> Result<Option<String>, MovieError> {
+        let output_args = vec![
             // Clobber existing files
+            "-y".to_string(),
+            // Where to store the output, until it's done
+            partial_path.to_string_lossy().into(),
+        ];
+
+        // The first pass only writes down what it learned about the frames, for the second. Each
+        // encode has its own log_dir, so encodes going at the same time keep their notes apart.
+        let pass_log = log_dir.join("ffmpeg-passlog");
+        let pass_log_arg = pass_log.to_string_lossy().to_string();
+        let first_pass_args: Vec<String> = [
+            "-pass",
+            "1",
+            "-passlogfile",
+            &pass_log_arg,
+            "-an",
+            "-f",
+            "null",
             "-y",
-            // Where to store the output
-            &self.output_dir.join(out_f).to_string_lossy(),
-        ]);
+            "-",
+        ]
+        .map(String::from)
+        .into();
+        let second_pass_args = [
+            "-pass".into(),
+            "2".into(),
+            "-passlogfile".into(),
+            pass_log_arg,
+        ]
+        .into_iter()
+        .chain(output_args.iter().cloned())
+        .collect::<Vec<_>>();
+        let bitrate = bitrate.map(|b| b.to_string());
 
-        debug!("{:?}", to_run);
+        let mut failures = Vec::new();
+        let mut used_encoder = None;
+        let mut output = None;
+        for encoder in self.encoders_to_try() {
+            if output.is_some() {
+                tracker.restart();
+            }
+            let two_pass = bitrate.is_some()
+                && !encoder.as_deref().is_some_and(is_hardware_encoder)
+                && !self.is_animated_image();
+            let attempt = if two_pass {
+                let first_pass = self.run_ffmpeg(
+                    input,
+                    filters,
+                    &first_pass_args,
+                    encoder.as_deref(),
+                    bitrate.as_deref(),
+                    tracker,
+                )?;
+                if first_pass.status.success() {
+                    tracker.restart();
+                    self.run_ffmpeg(
+                        input,
+                        filters,
+                        &second_pass_args,
+                        encoder.as_deref(),
+                        bitrate.as_deref(),
+                        tracker,
+                    )?
+                } else {
+                    first_pass
+                }
+            } else {
+                self.run_ffmpeg(
+                    input,
+                    filters,
+                    &output_args,
+                    encoder.as_deref(),
+                    bitrate.as_deref(),
+                    tracker,
+                )?
+            };
+            let succeeded = attempt.status.success();
+            let encoder_name = encoder.as_deref().unwrap_or("ffmpeg's default").to_string();
+            output = Some(attempt);
+
+            if succeeded {
+                used_encoder = encoder;
+                break;
+            }
+
+            let last_line = String::from_utf8_lossy(&output.as_ref().unwrap().stderr)
+                .lines()
+                .last()
+                .unwrap_or_default()
+                .to_string();
+            warn!("Encoding with {encoder_name} didn't work out: {last_line}");
+            failures.push(format!("{encoder_name} ({last_line})"));
+        }
+        let Some(output) = output else {
+            return Err(MovieError::Ffmpeg {
+                failures: vec!["no encoders to try".to_string()],
+                stderr: String::new(),
+            });
+        };
 
-        let output = to_run.output().expect("Failed to run ffmpeg :(");
-        debug!("Finished with: {:?}", output.status);
+        // ffmpeg-0.log, plus whatever extra the encoder kept (like x264's .mbtree).
+        let pass_logs = glob(&format!(
+            "{}*",
+            Pattern::escape(&pass_log.to_string_lossy())
+        ));
+        for pass_log in pass_logs.into_iter().flatten().filter_map(Result::ok) {
+            if let Err(e) = fs::remove_file(&pass_log) {
+                warn!("Couldn't clean up {pass_log:?}: {e}");
+            }
+        }
 
-        let stdout_raw = String::from_utf8(output.stdout).unwrap();
-        let stderr_raw = String::from_utf8(output.stderr).unwrap();
+        let stdout_raw = String::from_utf8_lossy(&output.stdout);
+        let stderr_raw = String::from_utf8_lossy(&output.stderr);
         let stdout = stdout_raw.lines().collect::<Vec<_>>();
         let stderr = stderr_raw.lines().collect::<Vec<_>>();
 
         // Log ffmpeg output no matter what
-        if let Err(e) = fs::write(input_dir.join("ffmpeg-stdout.log"), stdout.join("\n")) {
+        if let Err(e) = fs::write(log_dir.join("ffmpeg-stdout.log"), stdout.join("\n")) {
             warn!("Couldn't write ffmpeg stdout to file: {e}");
         }
 
-        if let Err(e) = fs::write(input_dir.join("ffmpeg-stderr.log"), stderr.join("\n")) {
+        if let Err(e) = fs::write(log_dir.join("ffmpeg-stderr.log"), stderr.join("\n")) {
             warn!("Couldn't write ffmpeg stderr to file: {e}");
         }
 
         if !output.status.success() {
-            let err = format!(
-                "Issue with ffmpeg - last line of stderr: {}",
-                stderr.last().unwrap()
-            );
-            error!("{}", &err);
-            panic!("{}", &err);
+            let _ = fs::remove_file(partial_path);
+            return Err(MovieError::Ffmpeg {
+                failures,
+                stderr: stderr[stderr.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n"),
+            });
         }
 
-        if self.compress_when_done {
-            info!("Compressing stills");
-            DirManager::compress(input_dir, self.file_extension.as_str());
+        Ok(used_encoder)
+    }
+
+    /// ffmpeg, ready for arguments, at a lower priority than everything else if we're making
+    /// movies in the background.
+    fn ffmpeg_command(&self) -> Command {
+        if !self.low_priority {
+            return Command::new(&self.ffmpeg);
+        }
+
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            use windows::Win32::System::Threading::BELOW_NORMAL_PRIORITY_CLASS;
+
+            let mut to_run = Command::new(&self.ffmpeg);
+            to_run.creation_flags(BELOW_NORMAL_PRIORITY_CLASS.0);
+            to_run
+        }
+
+        #[cfg(not(windows))]
+        {
+            let Ok(nice) = which::which("nice") else {
+                debug!("No nice to run ffmpeg with, running it at the usual priority");
+                return Command::new(&self.ffmpeg);
+            };
+            // Idle I/O too, so reading a day's frames doesn't hold up everything else on the disk.
+            let mut to_run = match which::which("ionice") {
+                Ok(ionice) => {
+                    let mut to_run = Command::new(ionice);
+                    to_run.args(["-c", "3"]).arg(nice);
+                    to_run
+                }
+                Err(_) => Command::new(nice),
+            };
+            to_run.args(["-n", NICENESS]).arg(&self.ffmpeg);
+            to_run
         }
-        info!("All done with {input_dir:?}!");
     }
 
-    fn fix_missing_frames(&self, in_dir: &Path) {
+    fn run_ffmpeg(
+        &self,
+        input: &FfmpegInput,
+        filters: &[String],
+        output: &[String],
+        encoder: Option<&str>,
+        bitrate: Option<&str>,
+        tracker: &mut Tracker,
+    ) -> std::io::Result<Output> {
+        let mut to_run = self.ffmpeg_command();
+        // Progress comes out as key=value lines on stdout as ffmpeg goes.
+        to_run.args(["-progress", "pipe:1", "-nostats"]);
+        to_run.args(self.build_ffmpeg_args(input, filters, output, encoder, bitrate));
+        to_run.stdout(Stdio::piped()).stderr(Stdio::piped());
+        if input.stdin_frames.is_some() {
+            to_run.stdin(Stdio::piped());
+        }
+        debug!("{:?}", to_run);
+
+        let mut child = to_run.spawn()?;
+
+        // Closing stdin once the last frame is in is how ffmpeg knows that's all of them.
+        let feeder = match (child.stdin.take(), input.stdin_frames.clone()) {
+            (Some(mut stdin), Some(frames)) => Some(thread::spawn(move || -> Result<(), Error> {
+                for frame in frames {
+                    stdin.write_all(&DirManager::read_frame(&frame)?)?;
+                }
+                Ok(())
+            })),
+            _ => None,
+        };
+
+        // Keep stderr drained on the side, or ffmpeg stalls once the pipe fills up.
+        let mut stderr = child.stderr.take().unwrap();
+        let stderr_reader = thread::spawn(move || {
+            let mut raw = Vec::new();
+            let _ = stderr.read_to_end(&mut raw);
+            raw
+        });
+
+        let mut stdout = Vec::new();
+        for line in BufReader::new(child.stdout.take().unwrap())
+            .lines()
+            .map_while(Result::ok)
+        {
+            tracker.observe(&line);
+            stdout.extend_from_slice(line.as_bytes());
+            stdout.push(b'\n');
+        }
+
+        let status = child.wait()?;
+        debug!("Finished with: {:?}", status);
+        if let Some(Ok(Err(e))) = feeder.map(|f| f.join()) {
+            // If ffmpeg gave up early this is just the pipe closing on us, otherwise the movie
+            // will be short some frames and won't pass check_movie.
+            warn!("Couldn't feed every frame to ffmpeg: {e:?}");
+        }
+        Ok(Output {
+            status,
+            stdout,
+            stderr: stderr_reader.join().unwrap_or_default(),
+        })
+    }
+
+    fn build_ffmpeg_args(
+        &self,
+        input: &FfmpegInput,
+        filters: &[String],
+        output: &[String],
+        encoder: Option<&str>,
+        bitrate: Option<&str>,
+    ) -> Vec<String> {
+        let mut args: Vec<String> = Vec::new();
+        let vaapi = encoder.is_some_and(|e| e.ends_with("_vaapi"));
+
+        if vaapi {
+            args.extend(["-vaapi_device".into(), VAAPI_DEVICE.into()]);
+        }
+        args.extend(self.ffmpeg_input_args.iter().cloned());
+        args.extend(input.args.iter().cloned());
+
+        // The stack turns each monitor itself.
+        let mut chain = match &input.stack {
+            Some(_) => Vec::new(),
+            None => rotate_filters(input.rotation),
+        };
+        chain.extend(self.fit_filters());
+        chain.extend(filters.iter().cloned());
+        let chain = self.with_watermark(chain.join(","));
+        // Stacking takes more than one input, so it all has to go through a filter_complex.
+        let (filter_arg, chain, out_label) = match &input.stack {
+            Some(stack) => ("-filter_complex", format!("{stack},{chain}"), "[v]"),
+            None => ("-vf", chain, ""),
+        };
+
+        if self.output_type == "gif" {
+            // GIFs only get 256 colors, so work out the best ones for the day instead of using a
+            // generic palette.
+            args.extend([
+                filter_arg.into(),
+                format!("{chain},split[a][b];[a]palettegen[p];[b][p]paletteuse{out_label}"),
+            ]);
+        } else if vaapi {
+            // VAAPI wants frames already on the GPU, so scale and convert on the way up.
+            args.extend([
+                filter_arg.into(),
+                format!("{chain},format=nv12,hwupload{out_label}"),
+            ]);
+        } else if input.stack.is_some() {
+            args.extend([
+                filter_arg.into(),
+                format!("{chain}{out_label}"),
+                "-pix_fmt".into(),
+                "yuv420p".into(),
+            ]);
+        } else if !filters.is_empty()
+            || input.rotation != 0
+            || self.watermark.is_some()
+            || self.video_fit.mode != "stretch"
+        {
+            args.extend(["-vf".into(), chain, "-pix_fmt".into(), "yuv420p".into()]);
+        } else {
+            args.extend([
+                // Output size
+                "-s".into(),
+                format!("{}x{}", self.output_width, self.output_height),
+                // Pixel format -- maybe only relevant on MacOS?
+                "-pix_fmt".into(),
+                "yuv420p".into(),
+            ]);
+        }
+
+        if let Some(encoder) = encoder {
+            args.extend(["-c:v".into(), encoder.into()]);
+        }
+        if let Some(threads) = self.ffmpeg_threads {
+            args.extend(["-threads".into(), threads.to_string()]);
+        }
+
+        if self.is_animated_image() {
+            // Loop forever, like people expect a GIF to.
+            args.extend(["-loop".into(), "0".into()]);
+        }
+
+        // Hardware encoders each have their own idea of quality settings, but they all take a
+        // bitrate.
+        let bitrate = bitrate.or(self.video_bitrate.as_deref());
+        if !encoder.is_some_and(is_hardware_encoder) && !self.is_animated_image() {
+            // A CRF wins out over a bitrate, which isn't what anyone asking for a size wants.
+            if let Some(crf) = self.video_crf.filter(|_| self.video_target_mb.is_none()) {
+                args.extend(["-crf".into(), crf.to_string()]);
+            }
+            if let Some(preset) = &self.video_preset {
+                args.extend(["-preset".into(), preset.clone()]);
+            }
+        }
+        if let Some(bitrate) = bitrate.filter(|_| !self.is_animated_image()) {
+            args.extend(["-b:v".into(), bitrate.to_string()]);
+        }
+
+        // Put the index up front so players (and browsers) can start before it's all downloaded.
+        let to_file = output.last().is_some_and(|o| o != "-");
+        if to_file && FASTSTART_VIDEO_TYPES.contains(&self.output_type.as_str()) {
+            args.extend(["-movflags".into(), "+faststart".into()]);
+        }
+
+        args.extend(self.ffmpeg_output_args.iter().cloned());
+        args.extend(output.iter().cloned());
+        args
+    }
+
+    /// An ffmetadata file in `work_dir` marking the time away, if the day had any and the movie
+    /// can hold chapters.
+    fn chapters_for(
+        &self,
+        day_dir: &Path,
+        selected: &[usize],
+        timeline: &Timeline,
+        lead_in: usize,
+        work_dir: &Path,
+    ) -> Option<PathBuf> {
+        if self.builtin_encoder
+            || !chapters::CHAPTER_VIDEO_TYPES.contains(&self.output_type.as_str())
+        {
+            return None;
+        }
+
+        let path = work_dir.join("chapters.txt");
+        let wrote = fs::create_dir_all(work_dir)
+            .map_err(Error::from)
+            .and_then(|_| chapters::write_chapters(day_dir, selected, timeline, lead_in, &path));
+        match wrote {
+            Ok(true) => Some(path),
+            Ok(false) => None,
+            Err(e) => {
+                warn!("Couldn't work out chapters, making the movie without them: {e:?}");
+                None
+            }
+        }
+    }
+
+    /// Which of the day's frames go into the movie, in order.
+    fn select_frames(&self, day_dir: &Path, frames_dir: &Path, frames: usize) -> Vec<usize> {
+        let selected = self.select_around_gaps(day_dir, frames_dir, frames);
+        let Some(drop_static) = &self.drop_static else {
+            return selected;
+        };
+
+        // Shots come in every capture_interval, so that's how many frames a minute is.
+        let per_minute = (60 / self.capture_interval.max(1)).max(1) as usize;
+        let max_gap = match drop_static.min_per_minute {
+            0 => usize::MAX,
+            min => (per_minute / min as usize).max(1),
+        };
+        let kept = selection::without_static(
+            frames_dir,
+            &self.file_extension,
+            &selected,
+            drop_static.threshold,
+            max_gap,
+        );
+        debug!(
+            "Left out {} of {} frames for not changing",
+            selected.len() - kept.len(),
+            selected.len()
+        );
+        kept
+    }
+
+    /// The day's frames, without or hurrying through time away and stretches nothing changed.
+    fn select_around_gaps(&self, day_dir: &Path, frames_dir: &Path, frames: usize) -> Vec<usize> {
+        if self.skip_gaps {
+            let filler = selection::filler_frames(day_dir, &self.file_extension, frames);
+            let selected = selection::without_gaps(&filler, self.keep_gap_cards);
+            // A day of nothing but filler still needs something to show.
+            if !selected.is_empty() {
+                return selected;
+            }
+        }
+
+        let Some(speedup) = self.gap_speedup.filter(|s| *s > 1) else {
+            return (0..frames).collect();
+        };
+
+        let still = selection::still_frames(day_dir, frames_dir, &self.file_extension, frames);
+        selection::sped_up(&still, speedup as usize)
+    }
+
+    /// How far to turn `day_dir`'s frames, going by its manifest before video_rotation.
+    fn rotation_for(&self, day_dir: &Path) -> u16 {
+        match manifest::load(day_dir).and_then(|m| m.rotation) {
+            Some(rotation) if VALID_ROTATIONS.contains(&rotation) => rotation,
+            Some(rotation) => {
+                warn!(
+                    "Ignoring rotation {rotation} in {day_dir:?}'s manifest, pick from: {VALID_ROTATIONS:?}"
+                );
+                self.video_rotation
+            }
+            None => self.video_rotation,
+        }
+    }
+
+    /// Gets every frame to the movie's size, however video_fit says to.
+    fn fit_filters(&self) -> Vec<String> {
+        let (width, height) = (self.output_width, self.output_height);
+        let (across, down) = self.video_fit.alignment();
+        let [r, g, b] = self.video_fit.pad_color;
+
+        match self.video_fit.mode.as_str() {
+            "contain" => vec![
+                format!(
+                    "scale={width}:{height}:force_original_aspect_ratio=decrease:flags=lanczos"
+                ),
+                format!(
+                    "pad={width}:{height}:(ow-iw)*{across}:(oh-ih)*{down}:color=0x{r:02x}{g:02x}{b:02x}"
+                ),
+            ],
+            "cover" => vec![
+                format!(
+                    "scale={width}:{height}:force_original_aspect_ratio=increase:flags=lanczos"
+                ),
+                format!("crop={width}:{height}:(iw-ow)*{across}:(ih-oh)*{down}"),
+            ],
+            _ => vec![format!("scale={width}:{height}:flags=lanczos")],
+        }
+    }
+
+    /// Bits per second of video that'll come out to about video_target_mb.
+    fn target_bitrate(
+        &self,
+        frames: usize,
+        (rate_num, rate_den): (u32, u32),
+        audio: bool,
+    ) -> Option<u64> {
+        let target_mb = self.video_target_mb?;
+        let seconds = (frames as f64 * rate_den as f64 / rate_num as f64).max(1.0);
+
+        // Leave a bit of room for the container, and for the audio if there is any.
+        let bits = target_mb * 1024.0 * 1024.0 * 8.0 * 0.97;
+        let audio_bits = if audio { AUDIO_BITRATE } else { 0 };
+        let bitrate = (bits / seconds) as u64;
+        Some(bitrate.saturating_sub(audio_bits).max(MIN_TARGET_BITRATE))
+    }
+
+    /// background_audio, or if that's a directory, any audio file in it.
+    fn pick_audio(&self) -> Option<PathBuf> {
+        let audio = self.background_audio.as_ref()?;
+        if self.builtin_encoder || self.is_animated_image() {
+            return None;
+        }
+        if !audio.is_dir() {
+            return Some(audio.clone());
+        }
+
+        let mut tracks: Vec<PathBuf> = match fs::read_dir(audio) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.extension()
+                        .and_then(|e| e.to_str())
+                        .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+                })
+                .collect(),
+            Err(e) => {
+                warn!("Couldn't look for audio in {audio:?}, making a silent movie: {e}");
+                return None;
+            }
+        };
+        if tracks.is_empty() {
+            warn!("No audio in {audio:?}, making a silent movie");
+            return None;
+        }
+
+        // Random enough to not play the same thing every day.
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.subsec_nanos() as usize);
+        Some(tracks.swap_remove(nanos % tracks.len()))
+    }
+
+    /// What ffmpeg should decode piped in frames with, if they can be piped in at all.
+    fn stream_decoder(&self) -> Option<&'static str> {
+        // Piped in frames can't each be up for their own length of time.
+        if self.builtin_encoder || self.video_timing == "captured" {
+            return None;
+        }
+        STREAM_DECODERS
+            .iter()
+            .find(|(extension, _)| *extension == self.file_extension)
+            .map(|(_, decoder)| *decoder)
+    }
+
+    /// GIF and animated WebP, for dropping straight into chat or a wiki.
+    fn is_animated_image(&self) -> bool {
+        matches!(self.output_type.as_str(), "gif" | "webp")
+    }
+
+    /// The encoder we'd like, then the ones to fall back to if it doesn't work out.
+    fn encoders_to_try(&self) -> Vec<Option<String>> {
+        // There's only the one way to make these, so let ffmpeg pick it.
+        if self.is_animated_image() {
+            return vec![None];
+        }
+
+        let mut encoders = vec![self.pick_encoder()];
+        let fallbacks = self
+            .video_codec
+            .iter()
+            .chain(&self.video_encoder_fallbacks)
+            .map(|e| Some(e.clone()));
+        for fallback in fallbacks {
+            if !encoders.contains(&fallback) {
+                encoders.push(fallback);
+            }
+        }
+        encoders
+    }
+
+    /// Which encoder to hand ffmpeg, or None to let it pick its (software) default.
+    fn pick_encoder(&self) -> Option<String> {
+        match self.video_encoder.as_str() {
+            "software" => self.video_codec.clone(),
+            // Asking for a particular codec means wanting that over whatever hardware is around.
+            "auto" if self.video_codec.is_some() => self.video_codec.clone(),
+            "auto" => DETECTED_ENCODER
+                .get_or_init(|| self.detect_hardware_encoder())
+                .clone(),
+            encoder => Some(encoder.to_string()),
+        }
+    }
+
+    fn detect_hardware_encoder(&self) -> Option<String> {
+        // ffmpeg lists encoders it was built with whether or not the hardware is there, so make
+        // sure each one can actually encode something before picking it.
+        let input = FfmpegInput {
+            args: ["-f", "lavfi", "-i", "color=black:s=256x256:d=0.1"]
+                .map(String::from)
+                .to_vec(),
+            stdin_frames: None,
+            rotation: 0,
+            stack: None,
+        };
+        let output = ["-f", "null", "-"].map(String::from);
+
+        for encoder in HARDWARE_ENCODERS {
+            let works = Command::new(&self.ffmpeg)
+                .args(self.build_ffmpeg_args(&input, &[], &output, Some(encoder), None))
+                .output()
+                .is_ok_and(|o| o.status.success());
+            if works {
+                info!("Using {encoder} to encode movies");
+                return Some(encoder.to_string());
+            }
+            debug!("{encoder} isn't usable here");
+        }
+
+        info!("No hardware encoders available, sticking with software");
+        None
+    }
+
+    /// As a fraction, (numerator, denominator), so a target length comes out exact.
+    fn frame_rate(&self, frames: usize) -> (u32, u32) {
+        match (self.video_fps, self.target_video_seconds) {
+            (_, Some(seconds)) => (frames.max(1) as u32, seconds),
+            (Some(fps), None) => (fps, 1),
+            // A 9 hour day of shots every 20 seconds makes a one minute movie.
+            (None, None) => (((9 * 60 * 60) / 20) / 60, 1),
+        }
+    }
+
+    /// About how long a movie of `frames` frames comes out, before any get left out.
+    pub fn movie_seconds(&self, frames: usize) -> f64 {
+        let (rate_num, rate_den) = self.frame_rate(frames);
+        frames as f64 * rate_den as f64 / rate_num as f64
+    }
+
+    /// How many frames ffmpeg will see, counting up from 00000 until one is missing.
+    fn contiguous_frames(frames_dir: &Path, extension: &str) -> usize {
+        (0..)
+            .take_while(|frame| {
+                DirManager::find_frame(frames_dir, &format!("{frame:05}.{extension}")).is_some()
+            })
+            .count()
+    }
+
+    /// Lists `frames` of the ones in `frames_dir` for ffmpeg's concat demuxer, each up for
+    /// `frame_secs` unless `durations` says otherwise, after any title card.
+    fn write_concat_list(
+        &self,
+        frames_dir: &Path,
+        frames: Range<usize>,
+        frame_secs: f64,
+        durations: Option<&[f64]>,
+        title_card: Option<(&Path, f64)>,
+    ) -> Result<PathBuf, Error> {
+        let mut list = String::from("ffconcat version 1.0\n");
+        if let Some((card, card_secs)) = title_card {
+            let path = card.to_string_lossy().replace('\'', r"'\''");
+            list.push_str(&format!("file '{path}'\nduration {card_secs}\n"));
+        }
+
+        for frame in frames.clone() {
+            let name = format!("{frame:05}.{}", self.file_extension);
+            let secs = durations
+                .and_then(|durations| durations.get(frame))
+                .unwrap_or(&frame_secs);
+            list.push_str(&format!("file '{name}'\nduration {secs}\n"));
+        }
+
+        let list_path = match frames.start {
+            0 => frames_dir.join("frames.ffconcat"),
+            start => frames_dir.join(format!("frames-{start:05}.ffconcat")),
+        };
+        fs::write(&list_path, list)?;
+        Ok(list_path)
+    }
+
+    /// Returns whether the frame was corrupt and got moved into `day_dir`'s quarantine/. A frame
+    /// in `frames_dir` that's only a decrypted copy gets dropped, and its encrypted original is
+    /// what goes into quarantine, or it'd break the next try at the movie too.
+    fn quarantine_if_corrupt(&self, day_dir: &Path, frames_dir: &Path, frame: &Path) -> bool {
+        let problem = match self.check_frame(frame) {
+            Ok(()) => return false,
+            Err(e) => e,
+        };
+
+        let original = if frames_dir == day_dir {
+            frame.to_path_buf()
+        } else {
+            let _ = fs::remove_file(frame);
+            let mut name = frame.file_name().unwrap().to_owned();
+            name.push(format!(".{ENCRYPTED_FILE_EXTENSION}"));
+            day_dir.join(name)
+        };
+
+        let quarantine_dir = day_dir.join(QUARANTINE_DIR);
+        let quarantined = quarantine_dir.join(original.file_name().unwrap());
+        warn!("{original:?} looks corrupt ({problem}), moving it to {quarantined:?}");
+
+        if let Err(e) =
+            fs::create_dir_all(&quarantine_dir).and_then(|_| fs::rename(&original, &quarantined))
+        {
+            warn!("Couldn't quarantine {original:?}, getting rid of it instead: {e}");
+            let _ = fs::remove_file(&original);
+        }
+        true
+    }
+
+    fn check_frame(&self, frame: &Path) -> Result<(), Error> {
+        if self.file_extension == "avif" {
+            // We can't decode AVIF, but a truncated write won't even have the header.
+            let contents = DirManager::read_frame(frame)?;
+            if contents.len() < 12 || &contents[4..8] != b"ftyp" {
+                return Err(anyhow::anyhow!("missing AVIF header"));
+            }
+            return Ok(());
+        }
+
+        image::load_from_memory(&DirManager::read_frame(frame)?)?;
+        Ok(())
+    }
+
+    /// Makes sure the frames in `in_dir` (`day_dir`'s, or decrypted copies of them) run from 00000
+    /// without any gaps, decompressing them all first unless `leave_compressed`. Returns whether
+    /// any of them are still compressed.
+    fn fix_missing_frames(
+        &self,
+        day_dir: &Path,
+        in_dir: &Path,
+        leave_compressed: bool,
+    ) -> Result<bool, MovieError> {
         let expected_extension = self.file_extension.as_str();
+        let frame_suffix = format!(".{expected_extension}");
 
-        debug!("Going to decompress, first");
-        DirManager::decompress(in_dir);
+        if !leave_compressed {
+            debug!("Going to decompress, first");
+            DirManager::decompress(in_dir, &self.compression);
+        }
+
+        // "00001.png.zst" -> ("00001.png", ".zst")
+        let split_name = |frame: &Path| {
+            let name = frame.file_name().unwrap_or_default().to_string_lossy();
+            let frame_name = Codec::strip_extension(&name).to_string();
+            let codec_suffix = name[frame_name.len()..].to_string();
+            (frame_name, codec_suffix)
+        };
 
         let mut found_frames = Vec::new();
 
         debug!("Gathering up frames in {in_dir:?}");
-        for entry_maybe in fs::read_dir(in_dir).unwrap() {
+        for entry_maybe in fs::read_dir(in_dir)? {
             let entry = match entry_maybe {
                 Ok(e) => e,
                 Err(e) => {
@@ -161,52 +1564,56 @@ impl MovieMaker {
                 }
             };
 
-            if entry.file_type().unwrap().is_symlink() {
+            if entry.file_type().map_or(true, |t| t.is_symlink()) {
                 continue;
             }
 
-            let extension = match entry.path().extension() {
-                Some(e) => e.to_os_string(),
-                None => {
-                    debug!("No extension on {entry:?} eh? carry on!");
-                    continue;
-                }
-            };
-
-            if extension == expected_extension {
+            if split_name(&entry.path()).0.ends_with(&frame_suffix) {
                 found_frames.push(entry.path());
             }
         }
 
+        // One truncated frame is enough to make ffmpeg give up on the whole day, so move any bad
+        // ones out of the way and let the gap filling below cover for them.
+        found_frames.retain(|frame| !self.quarantine_if_corrupt(day_dir, in_dir, frame));
+
         if found_frames.is_empty() {
-            panic!("Uhh, no frames AT ALL in {in_dir:?}?!");
+            return Err(MovieError::NoFrames(in_dir.to_path_buf()));
         }
 
         debug!("Sorting, to be safe");
-        found_frames.sort_by(|a, b| a.file_name().unwrap().cmp(b.file_name().unwrap()));
+        found_frames.sort_by_cached_key(|frame| split_name(frame).0);
 
-        let expected_first_frame = in_dir.join(format!("00000.{expected_extension}"));
-        if found_frames[0] != expected_first_frame {
+        let (first_name, first_suffix) = split_name(&found_frames[0]);
+        if first_name != format!("00000{frame_suffix}") {
+            let expected_first_frame = in_dir.join(format!("00000{frame_suffix}{first_suffix}"));
             debug!(
                 "Looks like {expected_first_frame:?} was missing, copying earliest into position"
             );
-            fs::copy(&found_frames[0], &expected_first_frame)
-                .expect("Couldn't create first frame?!");
+            fs::copy(&found_frames[0], &expected_first_frame)?;
             found_frames.insert(0, expected_first_frame);
         }
 
-        let mut expected_file: PathBuf;
-        let mut prev_file: PathBuf;
+        let mut prev_file = found_frames[0].clone();
 
         debug!("Checking for any missing frames");
         for expected_index in 0..found_frames.len() {
-            expected_file = in_dir.join(format!("{expected_index:05}.{expected_extension}"));
+            let expected_name = format!("{expected_index:05}{frame_suffix}");
 
-            if !expected_file.exists() {
-                prev_file = in_dir.join(format!("{:05}.{expected_extension}", expected_index - 1));
-                info!("Missing {expected_file:?}. Copying {prev_file:?} into place");
-                fs::copy(&prev_file, &expected_file).expect("Couldn't copy a missing frame?!");
+            match DirManager::find_frame(in_dir, &expected_name) {
+                Some(found) => prev_file = found,
+                None => {
+                    let expected_file =
+                        in_dir.join(format!("{expected_name}{}", split_name(&prev_file).1));
+                    info!("Missing {expected_file:?}. Copying {prev_file:?} into place");
+                    fs::copy(&prev_file, &expected_file)?;
+                    prev_file = expected_file;
+                }
             }
         }
+
+        Ok(found_frames
+            .iter()
+            .any(|frame| !split_name(frame).1.is_empty()))
     }
 }
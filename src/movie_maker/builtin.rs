@@ -0,0 +1,176 @@
+use super::progress::Tracker;
+use crate::config::VideoFit;
+use anyhow::Error;
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage};
+use rav1e::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/*
+For machines where installing ffmpeg isn't an option. Frames get encoded to AV1 with rav1e and
+written out as IVF, about the simplest container there is, which VLC, mpv, and ffmpeg itself all
+play. It's nowhere near as quick as ffmpeg, but it gets there.
+*/
+
+pub const ENCODER_NAME: &str = "rav1e";
+pub const VIDEO_TYPE: &str = "ivf";
+
+const SPEED_PRESET: u8 = 10;
+
+/// `load_frame` hands over each frame in turn, already fit to the size it's asked for.
+pub fn encode(
+    frames: usize,
+    (rate_num, rate_den): (u32, u32),
+    (width, height): (u32, u32),
+    out_path: &Path,
+    tracker: &mut Tracker,
+    load_frame: impl Fn(usize, (u32, u32)) -> Result<RgbImage, Error>,
+) -> Result<(), Error> {
+    // 4:2:0 needs even dimensions.
+    let (width, height) = (width & !1, height & !1);
+
+    let config = Config::new()
+        .with_encoder_config(EncoderConfig {
+            width: width as usize,
+            height: height as usize,
+            time_base: Rational::new(rate_den.into(), rate_num.into()),
+            speed_settings: SpeedSettings::from_preset(SPEED_PRESET),
+            ..Default::default()
+        })
+        .with_threads(0);
+    let mut ctx: Context<u8> = config
+        .new_context()
+        .map_err(|e| anyhow::anyhow!("Bad encoder config: {e}"))?;
+
+    let mut out = BufWriter::new(File::create(out_path)?);
+    write_ivf_header(&mut out, width, height, rate_num, rate_den, frames)?;
+
+    for frame in 0..frames {
+        let img = load_frame(frame, (width, height))?;
+
+        let mut to_send = ctx.new_frame();
+        let (y, u, v) = to_yuv420(&img);
+        let chroma_width = (width / 2) as usize;
+        to_send.planes[0].copy_from_raw_u8(&y, width as usize, 1);
+        to_send.planes[1].copy_from_raw_u8(&u, chroma_width, 1);
+        to_send.planes[2].copy_from_raw_u8(&v, chroma_width, 1);
+
+        ctx.send_frame(to_send)
+            .map_err(|e| anyhow::anyhow!("Couldn't encode frame {frame}: {e:?}"))?;
+        write_packets(&mut ctx, &mut out)?;
+        tracker.frames_done(frame as u64 + 1);
+    }
+
+    ctx.flush();
+    write_packets(&mut ctx, &mut out)?;
+
+    out.into_inner()?.sync_all()?;
+    Ok(())
+}
+
+/// Turns `img` `degrees` clockwise, the same way ffmpeg's transpose would.
+pub fn rotate(img: RgbImage, degrees: u16) -> RgbImage {
+    match degrees {
+        90 => imageops::rotate90(&img),
+        180 => imageops::rotate180(&img),
+        270 => imageops::rotate270(&img),
+        _ => img,
+    }
+}
+
+/// Squeezes, pads, or crops `img` into the movie's size, the same way ffmpeg would.
+pub fn fit(img: &RgbImage, (width, height): (u32, u32), video_fit: &VideoFit) -> RgbImage {
+    let (across, down) = video_fit.alignment();
+    let (img_width, img_height) = img.dimensions();
+    let width_scale = width as f32 / img_width as f32;
+    let height_scale = height as f32 / img_height as f32;
+
+    let scale = match video_fit.mode.as_str() {
+        "contain" => width_scale.min(height_scale),
+        "cover" => width_scale.max(height_scale),
+        _ => return imageops::resize(img, width, height, FilterType::Triangle),
+    };
+    let scaled_width = ((img_width as f32 * scale).round() as u32).max(1);
+    let scaled_height = ((img_height as f32 * scale).round() as u32).max(1);
+    let scaled = imageops::resize(img, scaled_width, scaled_height, FilterType::Triangle);
+
+    if video_fit.mode == "cover" {
+        let x = ((scaled_width - width.min(scaled_width)) as f32 * across) as u32;
+        let y = ((scaled_height - height.min(scaled_height)) as f32 * down) as u32;
+        return imageops::crop_imm(&scaled, x, y, width, height).to_image();
+    }
+
+    let mut canvas = RgbImage::from_pixel(width, height, Rgb(video_fit.pad_color));
+    let x = ((width - scaled_width.min(width)) as f32 * across) as i64;
+    let y = ((height - scaled_height.min(height)) as f32 * down) as i64;
+    imageops::overlay(&mut canvas, &scaled, x, y);
+    canvas
+}
+
+fn write_packets(ctx: &mut Context<u8>, out: &mut impl Write) -> Result<(), Error> {
+    loop {
+        match ctx.receive_packet() {
+            Ok(packet) => {
+                out.write_all(&(packet.data.len() as u32).to_le_bytes())?;
+                out.write_all(&packet.input_frameno.to_le_bytes())?;
+                out.write_all(&packet.data)?;
+            }
+            Err(EncoderStatus::Encoded) => continue,
+            Err(EncoderStatus::NeedMoreData) | Err(EncoderStatus::LimitReached) => return Ok(()),
+            Err(e) => return Err(anyhow::anyhow!("Encoder gave up: {e:?}")),
+        }
+    }
+}
+
+fn write_ivf_header(
+    out: &mut impl Write,
+    width: u32,
+    height: u32,
+    rate_num: u32,
+    rate_den: u32,
+    frames: usize,
+) -> Result<(), Error> {
+    out.write_all(b"DKIF")?;
+    out.write_all(&0u16.to_le_bytes())?; // version
+    out.write_all(&32u16.to_le_bytes())?; // header length
+    out.write_all(b"AV01")?;
+    out.write_all(&(width as u16).to_le_bytes())?;
+    out.write_all(&(height as u16).to_le_bytes())?;
+    // Frames per second, as rate / scale.
+    out.write_all(&rate_num.to_le_bytes())?;
+    out.write_all(&rate_den.to_le_bytes())?;
+    out.write_all(&(frames as u32).to_le_bytes())?;
+    out.write_all(&0u32.to_le_bytes())?;
+    Ok(())
+}
+
+/// BT.601 limited range, with chroma averaged over each 2x2 block.
+fn to_yuv420(img: &RgbImage) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let (width, height) = img.dimensions();
+    let mut y = Vec::with_capacity((width * height) as usize);
+    for pixel in img.pixels() {
+        let [r, g, b] = pixel.0.map(i32::from);
+        y.push((((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8);
+    }
+
+    let mut u = Vec::with_capacity((width * height / 4) as usize);
+    let mut v = Vec::with_capacity((width * height / 4) as usize);
+    for cy in (0..height).step_by(2) {
+        for cx in (0..width).step_by(2) {
+            let (mut r, mut g, mut b) = (0, 0, 0);
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let [pr, pg, pb] = img.get_pixel(cx + dx, cy + dy).0.map(i32::from);
+                r += pr;
+                g += pg;
+                b += pb;
+            }
+            let (r, g, b) = (r / 4, g / 4, b / 4);
+            u.push((((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8);
+            v.push((((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8);
+        }
+    }
+
+    (y, u, v)
+}
@@ -0,0 +1,121 @@
+use super::timing::Timeline;
+use crate::frame_metadata::{self, FrameMetadata};
+use anyhow::Error;
+use chrono::{DateTime, Duration, Local};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// Containers that ffmpeg can put chapters in.
+pub const CHAPTER_VIDEO_TYPES: &[&str] = &["mkv", "mp4", "mov", "m4v"];
+
+struct Chapter {
+    first_frame: usize,
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+    away: bool,
+}
+
+impl Chapter {
+    fn title(&self) -> String {
+        let span = format!("{}–{}", self.from.format("%H:%M"), self.to.format("%H:%M"));
+        match self.away {
+            true => format!("{span} away"),
+            false => span,
+        }
+    }
+}
+
+/// Splits the day into stretches at the capturing and time away, going by the filler noted in
+/// frames.jsonl. Frames it doesn't mention belong to whatever came before them.
+fn chapters_in(metadata: &[FrameMetadata], frames: usize) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = Vec::new();
+
+    for m in metadata.iter().filter(|m| (m.frame as usize) < frames) {
+        let away = m.away_secs.is_some();
+        let to = m.captured_at + Duration::seconds(m.away_secs.unwrap_or(0) as i64);
+
+        match chapters.last_mut() {
+            Some(last) if !last.away && !away => last.to = to,
+            _ => {
+                if let Some(last) = chapters.last_mut().filter(|last| last.away) {
+                    // We're back, so that's when the time away ended.
+                    last.to = last.to.max(m.captured_at);
+                }
+                chapters.push(Chapter {
+                    first_frame: if chapters.is_empty() {
+                        0
+                    } else {
+                        m.frame as usize
+                    },
+                    from: m.captured_at,
+                    to,
+                    away,
+                });
+            }
+        }
+    }
+
+    chapters
+}
+
+fn day_chapters(day_dir: &Path, frames: usize) -> Vec<Chapter> {
+    let mut metadata = frame_metadata::read_all(day_dir);
+    metadata.sort_by_key(|m| m.frame);
+    chapters_in(&metadata, frames)
+}
+
+/// The frames that stand in for time away.
+pub fn away_stretches(day_dir: &Path, frames: usize) -> Vec<Range<usize>> {
+    let chapters = day_chapters(day_dir, frames);
+    chapters
+        .iter()
+        .enumerate()
+        .filter(|(_, chapter)| chapter.away)
+        .map(|(i, chapter)| {
+            chapter.first_frame..chapters.get(i + 1).map_or(frames, |next| next.first_frame)
+        })
+        .collect()
+}
+
+/// Writes chapters for the day in `day_dir` as an ffmetadata file, as long as there was some time
+/// away to mark. Returns whether it did. `selected` is the day's frames that made it into the
+/// movie, in order, after `lead_in` frames of title card, played along `timeline`.
+pub fn write_chapters(
+    day_dir: &Path,
+    selected: &[usize],
+    timeline: &Timeline,
+    lead_in: usize,
+    out: &Path,
+) -> Result<bool, Error> {
+    let frames = selected.last().map_or(0, |last| last + 1);
+    let chapters = day_chapters(day_dir, frames);
+    if !chapters.iter().any(|c| c.away) {
+        return Ok(false);
+    }
+
+    // Where each chapter starts in the movie, after any frames were left out.
+    let in_movie = |frame: usize| lead_in + selected.partition_point(|&f| f < frame);
+    let millis = |frame: usize| (timeline.at(frame) * 1000.0).round() as u64;
+    let mut contents = String::from(";FFMETADATA1\n");
+    for (i, chapter) in chapters.iter().enumerate() {
+        let start = in_movie(chapter.first_frame);
+        let end = chapters
+            .get(i + 1)
+            .map_or(lead_in + selected.len(), |next| in_movie(next.first_frame));
+        if start == end {
+            continue;
+        }
+        // The title card goes with whatever comes first.
+        let start = if i == 0 { 0 } else { start };
+        contents.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            millis(start),
+            millis(end),
+            chapter.title()
+        ));
+    }
+
+    fs::write(out, contents)?;
+    Ok(true)
+}
@@ -0,0 +1,145 @@
+use super::timing::Timeline;
+use super::MovieMaker;
+use crate::config::TimestampOverlay;
+use crate::frame_metadata;
+use anyhow::Error;
+use chrono::{DateTime, Local};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+The time of day in a corner of the movie, drawn by ffmpeg as it goes. drawtext can't look up when
+each frame was captured, so we work that out ahead of time and hand it a sendcmd file that swaps
+the text whenever the clock should change.
+*/
+
+/// When each frame in `day_dir` was captured, going by frames.jsonl and then file times for any
+/// frames it doesn't mention. Has to be done before decompressing, which makes new files.
+pub fn capture_times(day_dir: &Path, extension: &str) -> BTreeMap<usize, DateTime<Local>> {
+    let mut times: BTreeMap<usize, DateTime<Local>> = frame_metadata::read_all(day_dir)
+        .into_iter()
+        .map(|m| (m.frame as usize, m.captured_at))
+        .collect();
+
+    let Ok(entries) = fs::read_dir(day_dir) else {
+        return times;
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.extension().is_none_or(|e| e != extension) {
+            continue;
+        }
+        let Some(frame) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+            times.entry(frame).or_insert_with(|| modified.into());
+        }
+    }
+
+    times
+}
+
+/// ffmpeg wants ':' escaped in filter options, and is happier with '/' even on Windows.
+pub(super) fn filter_path(path: &Path) -> String {
+    let path = path
+        .to_string_lossy()
+        .replace('\\', "/")
+        .replace(':', r"\:");
+    format!("'{path}'")
+}
+
+/// A drawtext option setting the text, escaped once for drawtext and again for sendcmd.
+fn text_option(text: &str) -> String {
+    let option = format!(
+        "text={}",
+        text.replace('\\', r"\\")
+            .replace(':', r"\:")
+            .replace('\'', r"\'")
+    );
+    option
+        .chars()
+        .flat_map(|c| match c {
+            '\\' | '\'' | ' ' | '\t' | ',' | ';' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect()
+}
+
+impl MovieMaker {
+    /// Filters that draw the clock over the `selected` frames played along `timeline`, after
+    /// `lead_in` frames of title card. Leaves what they need in `work_dir`, which has to stick around until
+    /// ffmpeg is done.
+    pub(super) fn clock_filters(
+        &self,
+        overlay: &TimestampOverlay,
+        times: &BTreeMap<usize, DateTime<Local>>,
+        selected: &[usize],
+        timeline: &Timeline,
+        lead_in: usize,
+        work_dir: &Path,
+    ) -> Result<Vec<String>, Error> {
+        fs::create_dir_all(work_dir)?;
+
+        // Frames we don't know about (like filler) show the time of the last one we do.
+        let mut commands = String::new();
+        let mut shown = String::new();
+        for (frame, &source) in selected.iter().enumerate() {
+            let Some(when) = times
+                .range(..=source)
+                .next_back()
+                .or_else(|| times.iter().next())
+                .map(|(_, when)| when)
+            else {
+                break;
+            };
+
+            let text = when.format(&overlay.format).to_string();
+            if text != shown {
+                let at = timeline.at(lead_in + frame);
+                commands.push_str(&format!(
+                    "{at:.3} drawtext@clock reinit {};\n",
+                    text_option(&text)
+                ));
+                shown = text;
+            }
+        }
+
+        let commands_path = work_dir.join("clock.cmd");
+        fs::write(&commands_path, commands)?;
+        let font_path: PathBuf = work_dir.join("clock.ttf");
+        fs::write(&font_path, include_bytes!("../Ubuntu-Regular.ttf"))?;
+
+        let font_size = overlay
+            .font_size
+            .unwrap_or((self.output_height as f32 / 30.0).max(12.0));
+        let padding = (font_size / 4.0) as u32;
+        let (x, y) = match overlay.corner.as_str() {
+            "top-left" => (format!("{padding}"), format!("{padding}")),
+            "top-right" => (format!("w-tw-{padding}"), format!("{padding}")),
+            "bottom-left" => (format!("{padding}"), format!("h-th-{padding}")),
+            _ => (format!("w-tw-{padding}"), format!("h-th-{padding}")),
+        };
+
+        // The title card doesn't need a clock on it.
+        let enable = match lead_in {
+            0 => String::new(),
+            _ => format!(":enable='gte(t,{})'", timeline.at(lead_in)),
+        };
+
+        // drawtext won't start without some text, but the first command swaps it out right away.
+        Ok(vec![
+            format!("sendcmd=f={}", filter_path(&commands_path)),
+            format!(
+                "drawtext@clock=fontfile={}:text=--:expansion=none:fontsize={font_size}:\
+                 fontcolor=white:box=1:boxcolor=black:boxborderw={padding}:x={x}:y={y}{enable}",
+                filter_path(&font_path)
+            ),
+        ])
+    }
+}
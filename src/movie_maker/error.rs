@@ -0,0 +1,69 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Why a day didn't get its movie. The day's stills are left alone whenever this comes back, so
+/// it's always safe to try again.
+#[derive(Debug)]
+pub enum MovieError {
+    /// Nothing usable in the day's directory to make a movie out of.
+    NoFrames(PathBuf),
+    /// Fewer frames than min_frames_for_video, so it isn't worth a movie.
+    TooFewFrames {
+        frames: u64,
+        min: u64,
+    },
+    /// ffmpeg wouldn't make the movie with any of the encoders it tried.
+    Ffmpeg {
+        /// Each encoder that was tried, and why it didn't work out.
+        failures: Vec<String>,
+        /// The end of what ffmpeg had to say the last time, the rest is in ffmpeg-stderr.log.
+        stderr: String,
+    },
+    /// A movie got made, but it didn't pass a look afterwards.
+    Invalid(String),
+    Io(io::Error),
+    /// Anything else in the way, like locking, unpacking, or decrypting the day.
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::NoFrames(dir) => write!(f, "no frames at all in {dir:?}"),
+            MovieError::TooFewFrames { frames, min } => {
+                write!(
+                    f,
+                    "only {frames} frames, fewer than min_frames_for_video ({min})"
+                )
+            }
+            MovieError::Ffmpeg { failures, .. } => {
+                write!(f, "every encoder failed: {}", failures.join(", "))
+            }
+            MovieError::Invalid(reason) => write!(f, "the movie didn't come out right, {reason}"),
+            MovieError::Io(e) => write!(f, "{e}"),
+            MovieError::Other(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MovieError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MovieError {
+    fn from(e: io::Error) -> Self {
+        MovieError::Io(e)
+    }
+}
+
+impl From<anyhow::Error> for MovieError {
+    fn from(e: anyhow::Error) -> Self {
+        MovieError::Other(e)
+    }
+}
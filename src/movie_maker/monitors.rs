@@ -0,0 +1,156 @@
+use super::MovieMaker;
+use crate::crypto::{private_temp_dir, FrameCipher, ENCRYPTED_FILE_EXTENSION};
+use crate::DirManager;
+use anyhow::Error;
+use log::{debug, info};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/*
+With capture_monitors set to "all", every monitor besides the main one gets its frames saved in the
+day's monitor-1/, monitor-2/, ... under the same frame numbers as the main one, and with a
+monitor_layout they all go into the movie next to each other. Each monitor gets its own concat list
+of the same frames the main one uses, then a filter_complex scales them all to the same height (or
+width) and stacks them before the usual fitting to the movie's size. A monitor that missed a frame
+just keeps showing the one before it.
+*/
+
+pub const MONITOR_LAYOUTS: &[&str] = &["hstack", "vstack"];
+
+/// Every other monitor's frames, ready for ffmpeg.
+pub(super) struct Monitors {
+    /// Copies of the frames, decrypted and decompressed. Gone once this is dropped.
+    pub frames: TempDir,
+    /// A concat list for each monitor, in order.
+    pub lists: Vec<PathBuf>,
+}
+
+impl MovieMaker {
+    /// The other monitors' copies of the `selected` frames in `day_dir`, each up for `frame_secs`
+    /// unless `durations` says otherwise. None if there aren't any or they aren't going in.
+    pub(super) fn monitors_for(
+        &self,
+        day_dir: &Path,
+        selected: &[usize],
+        frame_secs: f64,
+        durations: Option<&[f64]>,
+    ) -> Result<Option<Monitors>, Error> {
+        if self.monitor_layout.is_none() || self.builtin_encoder {
+            return Ok(None);
+        }
+        let monitor_dirs = DirManager::monitor_dirs(day_dir);
+        if monitor_dirs.is_empty() {
+            return Ok(None);
+        }
+
+        info!("Stacking {} other monitors in", monitor_dirs.len());
+        let frames = private_temp_dir("ompd-monitors-")?;
+        let mut lists = Vec::new();
+        for (n, monitor_dir) in monitor_dirs.iter().enumerate() {
+            let out_dir = frames.path().join(n.to_string());
+            fs::create_dir(&out_dir)?;
+            self.copy_monitor_frames(monitor_dir, &out_dir, selected)?;
+            let list =
+                self.write_concat_list(&out_dir, 0..selected.len(), frame_secs, durations, None)?;
+            lists.push(list);
+        }
+
+        Ok(Some(Monitors { frames, lists }))
+    }
+
+    /// Copies the `selected` frames in `monitor_dir` into `out_dir` ready to read, numbered from
+    /// 00000. Any it's missing get the one before, or the first there is for the ones up front.
+    fn copy_monitor_frames(
+        &self,
+        monitor_dir: &Path,
+        out_dir: &Path,
+        selected: &[usize],
+    ) -> Result<(), Error> {
+        let mut prev: Option<PathBuf> = None;
+        let mut missing = 0;
+        for (i, frame) in selected.iter().enumerate() {
+            let out = out_dir.join(format!("{i:05}.{}", self.file_extension));
+            match self.read_monitor_frame(monitor_dir, *frame)? {
+                Some(contents) => {
+                    FrameCipher::write_private(&out, &contents)?;
+                    // The ones before the first it has all get this one.
+                    for earlier in 0..missing {
+                        let earlier = out_dir.join(format!("{earlier:05}.{}", self.file_extension));
+                        fs::copy(&out, earlier)?;
+                    }
+                    missing = 0;
+                    prev = Some(out);
+                }
+                None => match &prev {
+                    Some(prev) => {
+                        fs::copy(prev, &out)?;
+                    }
+                    None => missing += 1,
+                },
+            }
+        }
+
+        if prev.is_none() {
+            return Err(anyhow::anyhow!("No frames in {monitor_dir:?}"));
+        }
+        Ok(())
+    }
+
+    /// What's in `monitor_dir`'s copy of `frame`, decrypted and decompressed, if it has one.
+    fn read_monitor_frame(
+        &self,
+        monitor_dir: &Path,
+        frame: usize,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let mut name = format!("{frame:05}.{}", self.file_extension);
+        if self.cipher.is_some() {
+            name.push_str(&format!(".{ENCRYPTED_FILE_EXTENSION}"));
+        }
+        let Some(path) = DirManager::find_frame(monitor_dir, &name) else {
+            debug!("No {name} in {monitor_dir:?}");
+            return Ok(None);
+        };
+
+        let contents = DirManager::read_frame(&path)?;
+        match &self.cipher {
+            Some(cipher) => Ok(Some(cipher.decrypt(&contents)?)),
+            None => Ok(Some(contents)),
+        }
+    }
+
+    /// The filter_complex that takes the frames (input 0) and `monitors` other monitors (the
+    /// inputs after it), turns them all `rotation` degrees, and puts them side by side or on top
+    /// of each other. The others sit on black for `lead_in_secs` while the title card's up.
+    pub(super) fn stack_filter(
+        &self,
+        monitors: usize,
+        rotation: u16,
+        lead_in_secs: f64,
+    ) -> Option<String> {
+        let layout = self.monitor_layout.as_deref()?;
+        if monitors == 0 {
+            return None;
+        }
+
+        // Stacking needs them all the same height across, or the same width down.
+        let size = match layout {
+            "vstack" => format!("scale={}:-2:flags=lanczos", self.output_width),
+            _ => format!("scale=-2:{}:flags=lanczos", self.output_height),
+        };
+
+        let mut filter = String::new();
+        let mut labels = String::new();
+        for input in 0..=monitors {
+            let mut chain = super::rotate_filters(rotation);
+            if input > 0 && lead_in_secs > 0.0 {
+                chain.push(format!("tpad=start_duration={lead_in_secs}:color=black"));
+            }
+            chain.extend([size.clone(), "setsar=1".into(), "format=rgb24".into()]);
+            filter.push_str(&format!("[{input}:v]{}[m{input}];", chain.join(",")));
+            labels.push_str(&format!("[m{input}]"));
+        }
+        filter.push_str(&format!("{labels}{layout}=inputs={}", monitors + 1));
+        Some(filter)
+    }
+}
@@ -0,0 +1,214 @@
+use super::clock::filter_path;
+use super::progress::Tracker;
+use super::{FfmpegInput, MovieMaker};
+use crate::DirManager;
+use anyhow::{anyhow, Error};
+use chrono::{Datelike, NaiveDate};
+use log::{debug, info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+The whole month at a glance: a calendar grid with a week to a row, where every day's movie plays in
+its own cell at the same time. Days without a movie, and the cells before the 1st and after the last
+day, stay black.
+
+It's all one lavfi filtergraph (a movie source per day, each scaled into its cell, then xstack), so
+ffmpeg sees a single input and the grid goes through the same encoding as a day's movie.
+*/
+
+/// Every cell plays at this rate, whatever its day's movie was made at.
+const MONTAGE_FPS: u32 = 30;
+
+const DAYS_PER_WEEK: u32 = 7;
+
+/// Where each day goes in the grid.
+struct CalendarLayout {
+    /// Empty cells before the 1st.
+    offset: u32,
+    days: u32,
+    rows: u32,
+    cell_width: u32,
+    cell_height: u32,
+}
+
+impl CalendarLayout {
+    fn new(first: NaiveDate, week_starts: &str, width: u32, height: u32) -> CalendarLayout {
+        let offset = match week_starts {
+            "sunday" => first.weekday().num_days_from_sunday(),
+            _ => first.weekday().num_days_from_monday(),
+        };
+        let days = days_in_month(first);
+        let rows = (offset + days).div_ceil(DAYS_PER_WEEK);
+        CalendarLayout {
+            offset,
+            days,
+            rows,
+            // Most encoders only take even sizes.
+            cell_width: (width / DAYS_PER_WEEK).max(2) & !1,
+            cell_height: (height / rows).max(2) & !1,
+        }
+    }
+
+    fn cells(&self) -> u32 {
+        self.rows * DAYS_PER_WEEK
+    }
+
+    /// The day of the month in `cell`, if any.
+    fn day_in(&self, cell: u32) -> Option<u32> {
+        let day = cell.checked_sub(self.offset)? + 1;
+        (day <= self.days).then_some(day)
+    }
+
+    /// xstack's layout, each cell's top left corner in pixels.
+    fn xstack_layout(&self) -> String {
+        (0..self.cells())
+            .map(|cell| {
+                let x = cell % DAYS_PER_WEEK * self.cell_width;
+                let y = cell / DAYS_PER_WEEK * self.cell_height;
+                format!("{x}_{y}")
+            })
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+}
+
+fn days_in_month(first: NaiveDate) -> u32 {
+    let next_month = first
+        .checked_add_months(chrono::Months::new(1))
+        .expect("A month after a real date should be real too");
+    (next_month - first).num_days() as u32
+}
+
+impl MovieMaker {
+    pub fn month_montage_path(&self, year: i32, month: u32) -> PathBuf {
+        self.output_dir.join(format!(
+            "ompd-{year}-{month:02}-montage.{}",
+            self.output_type
+        ))
+    }
+
+    /// Make the montage of `year`'s `month` if we're supposed to and haven't yet.
+    pub fn make_month_montage_if_missing(&self, year: i32, month: u32) {
+        if self.month_montage.is_none() || self.month_montage_path(year, month).exists() {
+            return;
+        }
+
+        info!("Making the montage of {year}-{month:02}");
+        match self.make_month_montage(year, month) {
+            Ok(montage) => info!("Made {montage:?}"),
+            Err(e) => warn!("Couldn't make the montage of {year}-{month:02}: {e:?}"),
+        }
+    }
+
+    pub fn make_month_montage(&self, year: i32, month: u32) -> Result<PathBuf, Error> {
+        let montage = self
+            .month_montage
+            .as_ref()
+            .ok_or_else(|| anyhow!("month_montage isn't set up"))?;
+        if self.builtin_encoder {
+            return Err(anyhow!("Can't make a month montage without ffmpeg"));
+        }
+        let first = NaiveDate::from_ymd_opt(year, month, 1)
+            .ok_or_else(|| anyhow!("{year}-{month:02} isn't a month"))?;
+
+        let layout = CalendarLayout::new(
+            first,
+            &montage.week_starts,
+            self.output_width,
+            self.output_height,
+        );
+        let movies: Vec<Option<PathBuf>> = (1..=layout.days)
+            .map(|day| {
+                let date = first.with_day(day)?;
+                let movie = self.output_dir.join(self.video_name.for_day(date));
+                movie.is_file().then_some(movie)
+            })
+            .collect();
+        let found = movies.iter().flatten().count();
+        if found == 0 {
+            return Err(anyhow!("No movies from {year}-{month:02} to put together"));
+        }
+        debug!(
+            "{found} of {} days have movies, in {} rows of {}x{} cells",
+            layout.days, layout.rows, layout.cell_width, layout.cell_height
+        );
+
+        let work_dir = std::env::temp_dir().join(format!("ompd-{year}-{month:02}-montage"));
+        fs::create_dir_all(&work_dir)?;
+        let font_path = work_dir.join("montage.ttf");
+        fs::write(&font_path, include_bytes!("../Ubuntu-Regular.ttf"))?;
+
+        let graph = montage_graph(&layout, &movies, montage.seconds, &font_path);
+        let args: Vec<String> = vec!["-f".into(), "lavfi".into(), "-i".into(), graph];
+
+        let out_path = self.month_montage_path(year, month);
+        let partial_path = DirManager::partial_path(&out_path);
+        let frames = (montage.seconds * MONTAGE_FPS as f64).ceil() as usize;
+        let mut tracker = Tracker::new(&format!("{year}-{month:02} montage"), frames);
+        let input = FfmpegInput {
+            args,
+            stdin_frames: None,
+            // Days were already turned the right way when their movies were made.
+            rotation: 0,
+            stack: None,
+        };
+        self.encode(&input, &[], &work_dir, &partial_path, None, &mut tracker)?;
+        fs::rename(&partial_path, &out_path)?;
+
+        if let Err(e) = fs::remove_dir_all(&work_dir) {
+            warn!("Couldn't clean up {work_dir:?}: {e}");
+        }
+
+        self.uploader.upload(&out_path);
+        Ok(out_path)
+    }
+}
+
+/// The lavfi filtergraph for the whole grid, `seconds` long. Movies that end early hold their last
+/// frame, longer ones get cut off.
+fn montage_graph(
+    layout: &CalendarLayout,
+    movies: &[Option<PathBuf>],
+    seconds: f64,
+    font_path: &Path,
+) -> String {
+    let (width, height) = (layout.cell_width, layout.cell_height);
+    let font_size = (height / 8).max(8);
+    let mut graph: Vec<String> = Vec::new();
+
+    for cell in 0..layout.cells() {
+        let day = layout.day_in(cell);
+        let movie = day.and_then(|day| movies[day as usize - 1].as_ref());
+        let mut chain = match movie {
+            Some(movie) => format!(
+                "movie={},fps={MONTAGE_FPS},\
+                 scale={width}:{height}:force_original_aspect_ratio=decrease,\
+                 pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,setsar=1,\
+                 tpad=stop_mode=clone:stop_duration={seconds},\
+                 trim=duration={seconds},setpts=PTS-STARTPTS",
+                filter_path(movie)
+            ),
+            None => format!("color=c=black:s={width}x{height}:r={MONTAGE_FPS}:d={seconds}"),
+        };
+        if let Some(day) = day {
+            chain.push_str(&format!(
+                ",drawtext=fontfile={}:text={day}:fontsize={font_size}:fontcolor=white:\
+                 box=1:boxcolor=black@0.5:boxborderw={}:x={font_size}/2:y={font_size}/2",
+                filter_path(font_path),
+                font_size / 4
+            ));
+        }
+        graph.push(format!("{chain}[cell{cell}]"));
+    }
+
+    let inputs: String = (0..layout.cells())
+        .map(|cell| format!("[cell{cell}]"))
+        .collect();
+    graph.push(format!(
+        "{inputs}xstack=inputs={}:layout={}",
+        layout.cells(),
+        layout.xstack_layout()
+    ));
+    graph.join(";")
+}
@@ -0,0 +1,92 @@
+use super::{builtin, selection, MovieMaker};
+use crate::DirManager;
+use anyhow::{anyhow, Error};
+use image::{ImageOutputFormat, RgbImage};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+/*
+A still from each movie, saved next to it under the same name, for file browsers and anything else
+that wants a preview. It's the frame in the middle of the day for how much was on screen, going by
+how big each frame is on disk (a busy screen doesn't compress as well), and never a filler card.
+*/
+
+const POSTER_EXTENSION: &str = "jpg";
+const POSTER_QUALITY: u8 = 85;
+
+impl MovieMaker {
+    /// Which of the `selected` frames in `frames_dir` stands in best for the whole day.
+    fn poster_frame(&self, day_dir: &Path, frames_dir: &Path, selected: &[usize]) -> Option<usize> {
+        let frames = selected.last()? + 1;
+        let filler = selection::filler_frames(day_dir, &self.file_extension, frames);
+
+        let mut sizes: Vec<(u64, usize)> = selected
+            .iter()
+            .filter(|&&frame| !filler[frame])
+            .filter_map(|&frame| {
+                let name = format!("{frame:05}.{}", self.file_extension);
+                let found = DirManager::find_frame(frames_dir, &name)?;
+                fs::metadata(found).ok().map(|m| (m.len(), frame))
+            })
+            .collect();
+        sizes.sort();
+        sizes.get(sizes.len() / 2).map(|&(_, frame)| frame)
+    }
+
+    /// Saves a poster for `video`, out of the `selected` frames in `frames_dir`.
+    pub(super) fn make_poster(
+        &self,
+        day_dir: &Path,
+        frames_dir: &Path,
+        selected: &[usize],
+        video: &Path,
+    ) -> Result<PathBuf, Error> {
+        let frame = self
+            .poster_frame(day_dir, frames_dir, selected)
+            .ok_or_else(|| anyhow!("there's nothing but filler"))?;
+        let frame_path =
+            DirManager::find_frame(frames_dir, &format!("{frame:05}.{}", self.file_extension))
+                .ok_or_else(|| anyhow!("frame {frame} went missing"))?;
+
+        let img = match DirManager::read_frame(&frame_path)
+            .and_then(|contents| Ok(image::load_from_memory(&contents)?))
+        {
+            Ok(img) => img.to_rgb8(),
+            // We can't decode everything (like AVIF), but ffmpeg can.
+            Err(_) if !self.builtin_encoder => self.decode_with_ffmpeg(&frame_path)?,
+            Err(e) => return Err(e),
+        };
+        let img = builtin::rotate(img, self.rotation_for(day_dir));
+        let poster = builtin::fit(
+            &img,
+            (self.output_width, self.output_height),
+            &self.video_fit,
+        );
+
+        let mut encoded = Cursor::new(Vec::new());
+        poster.write_to(&mut encoded, ImageOutputFormat::Jpeg(POSTER_QUALITY))?;
+        let poster_path = video.with_extension(POSTER_EXTENSION);
+        DirManager::write_atomically(&poster_path, &encoded.into_inner())?;
+        Ok(poster_path)
+    }
+
+    fn decode_with_ffmpeg(&self, frame: &Path) -> Result<RgbImage, Error> {
+        let output = self
+            .ffmpeg_command()
+            .arg("-i")
+            .arg(frame)
+            .args(["-frames:v", "1", "-c:v", "png", "-f", "image2pipe", "-"])
+            .output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "ffmpeg couldn't read {frame:?}: {}",
+                String::from_utf8_lossy(&output.stderr)
+                    .lines()
+                    .last()
+                    .unwrap_or_default()
+            ));
+        }
+        Ok(image::load_from_memory(&output.stdout)?.to_rgb8())
+    }
+}
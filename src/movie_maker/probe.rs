@@ -0,0 +1,145 @@
+use super::MovieMaker;
+use anyhow::{anyhow, Error};
+use std::fs;
+use std::path::Path;
+
+/*
+Looking inside finished movies with ffmpeg. Exiting cleanly doesn't mean ffmpeg wrote anything
+worth keeping, and once a movie looks done its frames get compressed or archived away, so make
+sure it really is a movie of the day first.
+*/
+
+/// A packet out of a movie: when it shows up, in seconds, and how big it is.
+pub struct Packet {
+    pub at: f64,
+    pub bytes: u64,
+}
+
+impl MovieMaker {
+    /// Every video packet in `movie`, in the order they're shown, without decoding anything.
+    pub(super) fn packets_in(&self, movie: &Path) -> Result<Vec<Packet>, Error> {
+        let output = self
+            .ffmpeg_command()
+            .arg("-i")
+            .arg(movie)
+            .args(["-map", "0:v:0", "-c", "copy", "-f", "framecrc", "-"])
+            .output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "ffmpeg couldn't read it: {}",
+                String::from_utf8_lossy(&output.stderr)
+                    .lines()
+                    .last()
+                    .unwrap_or_default()
+            ));
+        }
+
+        // Timestamps are in units of the time base, which comes first as "#tb 0: 1/15360".
+        let mut time_base = 1.0;
+        let mut packets = Vec::new();
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            if let Some(tb) = line.strip_prefix("#tb 0:") {
+                if let Some((num, den)) = tb.trim().split_once('/') {
+                    time_base = num.parse::<f64>()? / den.parse::<f64>()?;
+                }
+                continue;
+            }
+
+            // stream, dts, pts, duration, size, checksum
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if line.starts_with('#') || fields.len() < 5 {
+                continue;
+            }
+            if let (Ok(pts), Ok(bytes)) = (fields[2].parse::<i64>(), fields[4].parse::<u64>()) {
+                packets.push(Packet {
+                    at: pts as f64 * time_base,
+                    bytes,
+                });
+            }
+        }
+
+        packets.sort_by(|a, b| a.at.total_cmp(&b.at));
+        Ok(packets)
+    }
+
+    /// Makes sure `movie` has video in it, that its first and last frames decode, and that it
+    /// has about `frames` frames played at `rate`.
+    pub(super) fn check_movie(
+        &self,
+        movie: &Path,
+        frames: usize,
+        (rate_num, rate_den): (u32, u32),
+    ) -> Result<(), Error> {
+        if fs::metadata(movie)?.len() == 0 {
+            return Err(anyhow!("it's empty"));
+        }
+        // There might not be an ffmpeg to look with, and ffmpeg can't read animated WebP anyway.
+        if self.builtin_encoder || self.output_type == "webp" {
+            return Ok(());
+        }
+
+        let packets = self.packets_in(movie)?;
+        let Some(last) = packets.last() else {
+            return Err(anyhow!("there's no video in it"));
+        };
+
+        // Frame rate conversion can drop or repeat the odd frame.
+        let slack = (frames / 100).max(2);
+        if packets.len().abs_diff(frames) > slack {
+            return Err(anyhow!(
+                "it has {} frames instead of {frames}",
+                packets.len()
+            ));
+        }
+        let frame_secs = rate_den as f64 / rate_num as f64;
+        let expected_secs = frames as f64 * frame_secs;
+        let secs = last.at - packets[0].at + frame_secs;
+        if (secs - expected_secs).abs() > slack as f64 * frame_secs {
+            return Err(anyhow!(
+                "it's {secs:.1}s long instead of {expected_secs:.1}s"
+            ));
+        }
+
+        self.decodes(movie, &[], &["-frames:v", "1"])
+            .map_err(|e| anyhow!("the first frame doesn't decode: {e}"))?;
+        self.decodes(movie, &["-sseof", "-1"], &[])
+            .map_err(|e| anyhow!("the last frame doesn't decode: {e}"))?;
+        Ok(())
+    }
+
+    /// Decodes video out of `movie`, failing at the first error or if there wasn't any.
+    fn decodes(
+        &self,
+        movie: &Path,
+        input_options: &[&str],
+        output_options: &[&str],
+    ) -> Result<(), Error> {
+        let output = self
+            .ffmpeg_command()
+            .args(["-v", "error", "-xerror"])
+            .args(input_options)
+            .arg("-i")
+            .arg(movie)
+            .args(["-map", "0:v:0"])
+            .args(output_options)
+            .args(["-f", "framecrc", "-"])
+            .output()?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+                    .lines()
+                    .last()
+                    .unwrap_or("ffmpeg failed")
+            ));
+        }
+        let decoded = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .any(|line| !line.starts_with('#'));
+        match decoded {
+            true => Ok(()),
+            false => Err(anyhow!("nothing came out")),
+        }
+    }
+}
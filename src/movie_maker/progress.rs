@@ -0,0 +1,90 @@
+use log::info;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const LOG_EVERY: Duration = Duration::from_secs(15);
+
+/// How far along the movie that's being made right now is.
+#[derive(Clone, Debug)]
+pub struct EncodeProgress {
+    pub day: String,
+    pub frames_done: u64,
+    pub frames_total: u64,
+    pub eta: Option<Duration>,
+}
+
+static CURRENT: Mutex<Option<EncodeProgress>> = Mutex::new(None);
+
+/// What's being encoded right now, if anything.
+pub fn current_progress() -> Option<EncodeProgress> {
+    CURRENT.lock().unwrap().clone()
+}
+
+/// Follows along with ffmpeg's `-progress` output, logging every so often so a long encode
+/// doesn't look hung.
+pub struct Tracker {
+    progress: EncodeProgress,
+    started: Instant,
+    last_logged: Instant,
+}
+
+impl Tracker {
+    pub fn new(day: &str, frames_total: usize) -> Tracker {
+        let progress = EncodeProgress {
+            day: day.to_string(),
+            frames_done: 0,
+            frames_total: frames_total as u64,
+            eta: None,
+        };
+        *CURRENT.lock().unwrap() = Some(progress.clone());
+
+        Tracker {
+            progress,
+            started: Instant::now(),
+            last_logged: Instant::now(),
+        }
+    }
+
+    /// Start over, e.g. when falling back to a different encoder.
+    pub fn restart(&mut self) {
+        *self = Tracker::new(&self.progress.day, self.progress.frames_total as usize);
+    }
+
+    /// Take in a line of ffmpeg's progress output, which comes as key=value pairs.
+    pub fn observe(&mut self, line: &str) {
+        if let Some(frames_done) = line
+            .strip_prefix("frame=")
+            .and_then(|f| f.trim().parse::<u64>().ok())
+        {
+            self.frames_done(frames_done);
+        }
+    }
+
+    pub fn frames_done(&mut self, frames_done: u64) {
+        self.progress.frames_done = frames_done;
+        self.progress.eta = (frames_done > 0).then(|| {
+            let remaining = self.progress.frames_total.saturating_sub(frames_done);
+            self.started
+                .elapsed()
+                .mul_f64(remaining as f64 / frames_done as f64)
+        });
+        *CURRENT.lock().unwrap() = Some(self.progress.clone());
+
+        if self.last_logged.elapsed() >= LOG_EVERY {
+            self.last_logged = Instant::now();
+            let percent = 100 * frames_done / self.progress.frames_total.max(1);
+            info!(
+                "Encoding {}: {percent}% ({frames_done}/{} frames), about {}s left",
+                self.progress.day,
+                self.progress.frames_total,
+                self.progress.eta.unwrap_or_default().as_secs()
+            );
+        }
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        *CURRENT.lock().unwrap() = None;
+    }
+}
@@ -0,0 +1,83 @@
+use crate::Config;
+
+/*
+quality_preset, for anyone who'd rather not learn ffmpeg: a codec, CRF, encoder preset, frame rate,
+and size that go well together. Anything set by hand still wins. Shots come in at a fixed rate, so
+a higher frame rate here means a shorter (and smaller) movie, not a smoother one.
+*/
+
+pub const QUALITY_PRESETS: &[&str] = &["tiny", "share", "archive"];
+
+/// Containers the presets' codecs can go in, anything else keeps ffmpeg's codec for it.
+const PRESET_CODEC_TYPES: &[&str] = &["mp4", "mkv", "mov", "m4v"];
+
+struct QualityPreset {
+    codec: &'static str,
+    crf: u8,
+    encoder_preset: &'static str,
+    fps: u32,
+    /// Of vid_width and vid_height.
+    scale: f32,
+}
+
+fn quality_preset(name: &str) -> Option<QualityPreset> {
+    match name {
+        // As small as it gets while still being able to tell what's going on.
+        "tiny" => Some(QualityPreset {
+            codec: "libx265",
+            crf: 32,
+            encoder_preset: "slow",
+            fps: 45,
+            scale: 0.5,
+        }),
+        // H.264 plays everywhere, which matters more than size when sending it to people.
+        "share" => Some(QualityPreset {
+            codec: "libx264",
+            crf: 23,
+            encoder_preset: "medium",
+            fps: 27,
+            scale: 1.0,
+        }),
+        // For keeps, so it's worth the encoding time.
+        "archive" => Some(QualityPreset {
+            codec: "libx265",
+            crf: 18,
+            encoder_preset: "slower",
+            fps: 15,
+            scale: 1.0,
+        }),
+        _ => None,
+    }
+}
+
+/// `config` with its quality_preset filled in wherever it's been left alone.
+pub fn with_preset(mut config: Config) -> Config {
+    // With a share copy to send around, the movie itself is the one worth keeping.
+    let preset_name = match (&config.quality_preset, &config.share_copy) {
+        (Some(name), _) => name.as_str(),
+        (None, Some(_)) => "archive",
+        (None, None) => return config,
+    };
+    let Some(preset) = quality_preset(preset_name) else {
+        return config;
+    };
+
+    if PRESET_CODEC_TYPES.contains(&config.video_type.as_str()) {
+        config
+            .video_codec
+            .get_or_insert_with(|| preset.codec.to_string());
+        config.video_crf.get_or_insert(preset.crf);
+        config
+            .video_preset
+            .get_or_insert_with(|| preset.encoder_preset.to_string());
+    }
+    if config.target_video_seconds.is_none() {
+        config.video_fps.get_or_insert(preset.fps);
+    }
+    if preset.scale < 1.0 {
+        // Most encoders only take even sizes.
+        config.vid_width = ((config.vid_width as f32 * preset.scale) as u32).max(2) & !1;
+        config.vid_height = ((config.vid_height as f32 * preset.scale) as u32).max(2) & !1;
+    }
+    config
+}
@@ -0,0 +1,179 @@
+use super::error::MovieError;
+use super::progress::Tracker;
+use super::{Extras, FfmpegInput, MovieMaker, FASTSTART_VIDEO_TYPES};
+use anyhow::anyhow;
+use log::{debug, info, warn};
+use std::fs::{self, File};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+/*
+With video_segments, a long day gets encoded a few pieces at a time on separate cores, and then the
+pieces are joined end to end without encoding them again. Audio, subtitles, and chapters are for
+the whole movie, so they go in at the join. A clock's times are for the whole movie too, so days
+with one still get made in one go.
+*/
+
+/// Any shorter and the extra ffmpeg runs cost more than they save.
+const MIN_SEGMENT_FRAMES: usize = 300;
+
+impl MovieMaker {
+    /// Which of the `frames` frames go in each piece, if it's worth making the movie in pieces.
+    pub(super) fn segments_for(&self, frames: usize, extras: &Extras) -> Option<Vec<Range<usize>>> {
+        let pieces = (self.video_segments? as usize).min(frames / MIN_SEGMENT_FRAMES);
+        // GIFs and animated WebPs can't be joined without encoding them again, and pieces of the
+        // other monitors would need lists of their own.
+        if pieces < 2
+            || !extras.filters.is_empty()
+            || !extras.monitors.is_empty()
+            || self.is_animated_image()
+        {
+            return None;
+        }
+
+        let per_piece = frames.div_ceil(pieces);
+        Some(
+            (0..frames)
+                .step_by(per_piece)
+                .map(|start| start..(start + per_piece).min(frames))
+                .collect(),
+        )
+    }
+
+    /// Encodes each of the `pieces` of the frames in `frames_dir` at once, then joins them up at
+    /// `partial_path`. Returns the encoder that worked, None meaning ffmpeg's default.
+    pub(super) fn encode_in_segments(
+        &self,
+        day: &str,
+        frames_dir: &Path,
+        partial_path: &Path,
+        pieces: &[Range<usize>],
+        rate: (u32, u32),
+        extras: &Extras,
+    ) -> Result<Option<String>, MovieError> {
+        // Its own name every time, so another day (or another ompd) making pieces doesn't trip
+        // over these, and it goes away on its own if something goes wrong.
+        let segments = tempfile::Builder::new()
+            .prefix(&format!("ompd-{day}-segments-"))
+            .tempdir()?;
+        let segments_dir = segments.path();
+        let lead_in = match extras.title_card {
+            Some(_) => self.title_card_frames(rate),
+            None => 0,
+        };
+        info!("Making the movie in {} pieces", pieces.len());
+
+        let encode_piece = |piece: usize, frames: &Range<usize>| {
+            let (mut args, output_options) =
+                self.frames_input(frames_dir, rate, extras, Some(frames.clone()))?;
+            args.extend(output_options);
+            // The title card only goes before the first piece.
+            let card = if frames.start == 0 { lead_in } else { 0 };
+            let stdin_frames = extras.stream.as_ref().map(|stream| {
+                stream
+                    .get(lead_in + frames.start - card..lead_in + frames.end)
+                    .map(<[PathBuf]>::to_vec)
+                    .unwrap_or_default()
+            });
+            let input = FfmpegInput {
+                args,
+                stdin_frames,
+                rotation: extras.rotation,
+                stack: None,
+            };
+
+            let log_dir = segments_dir.join(format!("{piece:03}"));
+            fs::create_dir_all(&log_dir)?;
+            let segment_path = segments_dir.join(format!("{piece:03}.{}", self.output_type));
+            let label = format!("{day} piece {} of {}", piece + 1, pieces.len());
+            let mut tracker = Tracker::new(&label, card + frames.len());
+            let used_encoder = self.encode(
+                &input,
+                &[],
+                &log_dir,
+                &segment_path,
+                extras.bitrate,
+                &mut tracker,
+            )?;
+            Ok::<_, MovieError>((segment_path, used_encoder))
+        };
+        let encoded: Vec<Result<(PathBuf, Option<String>), MovieError>> = thread::scope(|scope| {
+            let handles: Vec<_> = pieces
+                .iter()
+                .enumerate()
+                .map(|(piece, frames)| scope.spawn(move || encode_piece(piece, frames)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow!("Encoding a piece panicked").into()))
+                })
+                .collect()
+        });
+        let encoded = encoded.into_iter().collect::<Result<Vec<_>, _>>()?;
+
+        // Pieces from different encoders won't go together without encoding them again.
+        let used_encoder = encoded[0].1.clone();
+        if encoded.iter().any(|(_, encoder)| *encoder != used_encoder) {
+            return Err(anyhow!("The pieces didn't all come out of the same encoder").into());
+        }
+
+        let segment_paths: Vec<PathBuf> = encoded.into_iter().map(|(path, _)| path).collect();
+        self.join_segments(segments_dir, &segment_paths, partial_path, extras)?;
+
+        let segments_dir = segments_dir.to_path_buf();
+        if let Err(e) = segments.close() {
+            warn!("Couldn't clean up {segments_dir:?}: {e}");
+        }
+        Ok(used_encoder)
+    }
+
+    /// Puts the `segments` end to end at `partial_path`, along with the audio, subtitles, and
+    /// chapters in `extras`.
+    fn join_segments(
+        &self,
+        segments_dir: &Path,
+        segments: &[PathBuf],
+        partial_path: &Path,
+        extras: &Extras,
+    ) -> Result<(), MovieError> {
+        let mut list = String::from("ffconcat version 1.0\n");
+        for segment in segments {
+            let path = segment.to_string_lossy().replace('\'', r"'\''");
+            list.push_str(&format!("file '{path}'\n"));
+        }
+        let list_path = segments_dir.join("segments.ffconcat");
+        fs::write(&list_path, list)?;
+
+        let (extra_inputs, extra_options) = self.extra_inputs(extras);
+        let mut to_run = self.ffmpeg_command();
+        to_run.args(["-y", "-v", "error", "-f", "concat", "-safe", "0", "-i"]);
+        to_run
+            .arg(&list_path)
+            .args(extra_inputs)
+            .args(extra_options);
+        to_run.args(["-c:v", "copy"]);
+        if FASTSTART_VIDEO_TYPES.contains(&self.output_type.as_str()) {
+            to_run.args(["-movflags", "+faststart"]);
+        }
+        to_run.args(&self.ffmpeg_output_args).arg(partial_path);
+        debug!("{:?}", to_run);
+
+        let output = to_run.output()?;
+        let made = fs::metadata(partial_path).is_ok_and(|m| m.len() > 0);
+        if !output.status.success() || !made {
+            let _ = fs::remove_file(partial_path);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(MovieError::Ffmpeg {
+                failures: vec!["joining the pieces".to_string()],
+                stderr: stderr.lines().last().unwrap_or_default().to_string(),
+            });
+        }
+
+        File::open(partial_path)?.sync_all()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use super::chapters;
+use crate::DirManager;
+use image::GrayImage;
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+Which of the day's frames make it into the movie. Usually that's all of them, but stretches where
+nothing changes (filler for time away, or a screen nobody touched) can be hurried through or left
+out.
+*/
+
+/// Renumbered links to the frames that made the cut live in here, for ffmpeg to read in order.
+const SELECTED_DIR: &str = ".selected";
+
+/// Frames get shrunk down to this before comparing them, which is plenty to see a window move or
+/// a page scroll, and makes a cursor blinking or a clock ticking over count for next to nothing.
+const COMPARE_SIZE: (u32, u32) = (160, 90);
+
+/// Whether each frame is filler for time away, going by what's noted in `day_dir` and which frames
+/// are only symlinks to another.
+pub fn filler_frames(day_dir: &Path, extension: &str, frames: usize) -> Vec<bool> {
+    let mut filler = vec![false; frames];
+    for stretch in chapters::away_stretches(day_dir, frames) {
+        filler[stretch].fill(true);
+    }
+
+    for (frame, is_filler) in filler.iter_mut().enumerate() {
+        let path = day_dir.join(format!("{frame:05}.{extension}"));
+        if fs::symlink_metadata(path).is_ok_and(|m| m.is_symlink()) {
+            *is_filler = true;
+        }
+    }
+
+    filler
+}
+
+/// Whether each frame in `frames_dir` is filler or the same as the one before.
+pub fn still_frames(
+    day_dir: &Path,
+    frames_dir: &Path,
+    extension: &str,
+    frames: usize,
+) -> Vec<bool> {
+    let mut still = filler_frames(day_dir, extension, frames);
+
+    let mut previous: Option<Vec<u8>> = None;
+    for (frame, is_still) in still.iter_mut().enumerate() {
+        let contents = DirManager::find_frame(frames_dir, &format!("{frame:05}.{extension}"))
+            .and_then(|found| DirManager::read_frame(&found).ok());
+        if contents.is_some() && contents == previous {
+            *is_still = true;
+        }
+        previous = contents;
+    }
+
+    still
+}
+
+/// Every frame, except only one in `speedup` of each still stretch. The first one is always kept
+/// so a gap's card still shows up.
+pub fn sped_up(still: &[bool], speedup: usize) -> Vec<usize> {
+    let mut selected = Vec::new();
+    let mut run = 0;
+    for (frame, &is_still) in still.iter().enumerate() {
+        run = if is_still { run + 1 } else { 0 };
+        if run <= 1 || (run - 1) % speedup == 0 {
+            selected.push(frame);
+        }
+    }
+    selected
+}
+
+/// Shrunk down and in grays, for comparing. None if it's a kind of image we can't decode.
+fn comparable(contents: &[u8]) -> Option<GrayImage> {
+    let img = image::load_from_memory(contents).ok()?;
+    Some(
+        img.thumbnail_exact(COMPARE_SIZE.0, COMPARE_SIZE.1)
+            .to_luma8(),
+    )
+}
+
+/// The average difference per pixel between `a` and `b`, out of 255.
+fn difference(a: &GrayImage, b: &GrayImage) -> f32 {
+    let total: u64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(a, b)| a.abs_diff(*b) as u64)
+        .sum();
+    total as f32 / a.as_raw().len().max(1) as f32
+}
+
+/// The `candidates` that look more than `threshold` different from the last one kept, and at
+/// least one every `max_gap` frames regardless. Frames we can't decode only go by exact repeats.
+pub fn without_static(
+    frames_dir: &Path,
+    extension: &str,
+    candidates: &[usize],
+    threshold: f32,
+    max_gap: usize,
+) -> Vec<usize> {
+    let mut kept: Vec<usize> = Vec::new();
+    let mut last: Option<(Vec<u8>, Option<GrayImage>)> = None;
+    for &frame in candidates {
+        let Some(contents) = DirManager::find_frame(frames_dir, &format!("{frame:05}.{extension}"))
+            .and_then(|found| DirManager::read_frame(&found).ok())
+        else {
+            kept.push(frame);
+            continue;
+        };
+
+        // Decoding is the slow part, so don't unless the bytes are different.
+        let mut look = None;
+        let overdue = kept
+            .last()
+            .is_none_or(|&last_kept| frame - last_kept >= max_gap);
+        let changed = match &last {
+            None => true,
+            Some((last_contents, _)) if *last_contents == contents => false,
+            Some((_, last_look)) => {
+                look = comparable(&contents);
+                match (last_look, &look) {
+                    (Some(last_look), Some(look)) => difference(last_look, look) > threshold,
+                    _ => true,
+                }
+            }
+        };
+
+        if overdue || changed {
+            let look = look.or_else(|| comparable(&contents));
+            last = Some((contents, look));
+            kept.push(frame);
+        }
+    }
+    kept
+}
+
+/// Every frame that isn't filler, except for the first of each gap if `keep_cards`.
+pub fn without_gaps(filler: &[bool], keep_cards: bool) -> Vec<usize> {
+    (0..filler.len())
+        .filter(|&frame| {
+            let starts_gap = frame == 0 || !filler[frame - 1];
+            !filler[frame] || (keep_cards && starts_gap)
+        })
+        .collect()
+}
+
+/// Links the `selected` frames into their own directory, numbered from 00000 like ffmpeg expects.
+pub fn link_selected(
+    frames_dir: &Path,
+    extension: &str,
+    selected: &[usize],
+) -> std::io::Result<PathBuf> {
+    let selected_dir = frames_dir.join(SELECTED_DIR);
+    if selected_dir.exists() {
+        fs::remove_dir_all(&selected_dir)?;
+    }
+    fs::create_dir(&selected_dir)?;
+
+    for (frame, source) in selected.iter().enumerate() {
+        // Filler is a symlink, which would get linked as-is rather than what it points to.
+        let source = fs::canonicalize(frames_dir.join(format!("{source:05}.{extension}")))?;
+        let linked = selected_dir.join(format!("{frame:05}.{extension}"));
+        if let Err(e) = fs::hard_link(&source, &linked) {
+            debug!("Couldn't link {source:?}, copying it instead: {e}");
+            fs::copy(&source, &linked)?;
+        }
+    }
+
+    Ok(selected_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sped_up, without_gaps};
+
+    #[test]
+    fn sped_up_keeps_one_in_speedup() {
+        let still = [false, true, true, true, true, true, false];
+        assert_eq!(sped_up(&still, 2), vec![0, 1, 3, 5, 6]);
+        assert_eq!(sped_up(&still, 4), vec![0, 1, 5, 6]);
+        assert_eq!(sped_up(&still, 10), vec![0, 1, 6]);
+        assert_eq!(sped_up(&still, 1), (0..still.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sped_up_starts_over_each_stretch() {
+        let still = [true, true, true, false, true, true, true];
+        assert_eq!(sped_up(&still, 2), vec![0, 2, 3, 4, 6]);
+        assert_eq!(sped_up(&[false; 3], 5), vec![0, 1, 2]);
+        assert!(sped_up(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn without_gaps_leaves_out_filler() {
+        let filler = [false, true, true, false, true];
+        assert_eq!(without_gaps(&filler, false), vec![0, 3]);
+        assert_eq!(without_gaps(&filler, true), vec![0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn without_gaps_at_the_start() {
+        let filler = [true, true, false, false];
+        assert_eq!(without_gaps(&filler, false), vec![2, 3]);
+        assert_eq!(without_gaps(&filler, true), vec![0, 2, 3]);
+        assert!(without_gaps(&[true; 3], false).is_empty());
+        assert_eq!(without_gaps(&[true; 3], true), vec![0]);
+    }
+}
@@ -0,0 +1,46 @@
+use super::MovieMaker;
+use serde::{Deserialize, Serialize};
+
+/*
+What each movie was made with, kept in the day index, so the backfiller can tell which movies are
+out of date after the config changes and make them again.
+*/
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VideoSettings {
+    pub video_type: String,
+    pub codec: Option<String>,
+    pub crf: Option<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub fps: Option<u32>,
+    pub target_seconds: Option<u32>,
+    /// Of ompd, just for the record. A new version alone isn't reason enough to make a movie again.
+    pub version: String,
+}
+
+impl VideoSettings {
+    /// Whether a movie made with `self` looks like one made with `other` would.
+    pub fn same_output_as(&self, other: &VideoSettings) -> bool {
+        VideoSettings {
+            version: other.version.clone(),
+            ..self.clone()
+        } == *other
+    }
+}
+
+impl MovieMaker {
+    /// What movies get made with right now.
+    pub fn video_settings(&self) -> VideoSettings {
+        VideoSettings {
+            video_type: self.output_type.clone(),
+            codec: self.video_codec.clone(),
+            crf: self.video_crf,
+            width: self.output_width,
+            height: self.output_height,
+            fps: self.video_fps,
+            target_seconds: self.target_video_seconds,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use super::MovieMaker;
+use crate::DirManager;
+use anyhow::{anyhow, Error};
+use chrono::{Duration, Local, NaiveDate};
+use log::{info, warn};
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+/*
+A small copy of each day's movie to send around, made from the movie once it's done so it gets the
+clock, title card, and everything else for free. The movie itself is the master, for keeping and
+for making things like year reviews out of later.
+*/
+
+pub const SHARE_VIDEO_TYPE: &str = "mp4";
+
+const SHARE_AUDIO_BITRATE: &str = "128k";
+
+impl MovieMaker {
+    /// Where the share copy of `date`'s movie goes, if there's to be one.
+    pub fn share_copy_path(&self, date: NaiveDate) -> Option<PathBuf> {
+        let share_name = self.share_name.as_ref()?;
+        Some(self.output_dir.join(share_name.for_day(date)))
+    }
+
+    /// Whether `date` is recent enough to still have a share copy around.
+    fn keeps_share_copy(&self, date: NaiveDate) -> bool {
+        let keep_days = self.share_copy.as_ref().and_then(|share| share.keep_days);
+        keep_days.is_none_or(|days| date >= Local::now().date_naive() - Duration::days(days.into()))
+    }
+
+    /// Make the share copy of `date`'s movie if we're supposed to and haven't yet.
+    pub fn make_share_copy_if_missing(&self, date: NaiveDate) {
+        let Some(share_path) = self.share_copy_path(date) else {
+            return;
+        };
+        let master = self.output_dir.join(self.video_name.for_day(date));
+        if share_path.exists() || !master.is_file() || !self.keeps_share_copy(date) {
+            return;
+        }
+
+        info!("Making the missing share copy for {date}");
+        match self.make_share_copy(date) {
+            Ok(share) => self.uploader.upload(&share),
+            Err(e) => warn!("Couldn't make the share copy for {date}: {e:?}"),
+        }
+    }
+
+    /// Makes the share copy of `date`'s finished movie, and returns where it ended up.
+    pub fn make_share_copy(&self, date: NaiveDate) -> Result<PathBuf, Error> {
+        let share = self
+            .share_copy
+            .as_ref()
+            .ok_or_else(|| anyhow!("share_copy isn't set up"))?;
+        let share_path = self
+            .share_copy_path(date)
+            .ok_or_else(|| anyhow!("video_name_template doesn't leave room for a share copy"))?;
+        let master = self.output_dir.join(self.video_name.for_day(date));
+        let partial_path = DirManager::partial_path(&share_path);
+
+        let mut to_run = self.ffmpeg_command();
+        to_run.args(["-y", "-v", "error", "-i"]).arg(&master);
+        // Whatever audio and subtitles the master has come along, chapters do on their own.
+        to_run.args(["-map", "0:v:0", "-map", "0:a?", "-map", "0:s?"]);
+        to_run.args(["-c:v", &share.codec, "-crf", &share.crf.to_string()]);
+        if let Some(threads) = self.ffmpeg_threads {
+            to_run.args(["-threads", &threads.to_string()]);
+        }
+        if let Some(height) = share.height {
+            to_run.args(["-vf", &format!("scale=-2:{height}:flags=lanczos")]);
+        }
+        to_run.args([
+            "-pix_fmt",
+            "yuv420p",
+            "-c:a",
+            "aac",
+            "-b:a",
+            SHARE_AUDIO_BITRATE,
+        ]);
+        to_run.args(["-c:s", "mov_text", "-movflags", "+faststart"]);
+        to_run.arg(&partial_path);
+
+        let output = to_run.output()?;
+        let made = fs::metadata(&partial_path).is_ok_and(|m| m.len() > 0);
+        if !output.status.success() || !made {
+            let _ = fs::remove_file(&partial_path);
+            return Err(anyhow!(
+                "ffmpeg couldn't make it: {}",
+                String::from_utf8_lossy(&output.stderr)
+                    .lines()
+                    .last()
+                    .unwrap_or("nothing came out")
+            ));
+        }
+
+        File::open(&partial_path)?.sync_all()?;
+        fs::rename(&partial_path, &share_path)?;
+        info!("Made a share copy at {share_path:?}");
+        Ok(share_path)
+    }
+}
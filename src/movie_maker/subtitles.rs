@@ -0,0 +1,141 @@
+use super::timing::Timeline;
+use super::MovieMaker;
+use crate::config::VideoSubtitles;
+use crate::crypto::FrameCipher;
+use crate::frame_metadata::{self, FrameMetadata};
+use log::warn;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+A subtitle track of when each frame was captured and what was in front at the time, out of
+frames.jsonl. Unlike video_clock it's its own stream in the movie, so players can turn it on and
+off.
+*/
+
+/// Movie types that can carry subtitles, and what ffmpeg should write them as.
+pub const SUBTITLE_CODECS: &[(&str, &str)] = &[
+    ("mkv", "srt"),
+    ("mp4", "mov_text"),
+    ("mov", "mov_text"),
+    ("m4v", "mov_text"),
+];
+
+/// What shows while `metadata`'s frame is up.
+fn cue_text(settings: &VideoSubtitles, metadata: &FrameMetadata) -> String {
+    let when = metadata.captured_at.format(&settings.format);
+    if metadata.away_secs.is_some() {
+        return format!("{when} away");
+    }
+
+    let activity: Vec<&str> = [&metadata.app, &metadata.window_title]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    if !settings.app || activity.is_empty() {
+        return when.to_string();
+    }
+    format!("{when}\n{}", activity.join(" — ").replace('\n', " "))
+}
+
+/// e.g. "01:02:03,456"
+fn srt_time(secs: f64) -> String {
+    let millis = (secs * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// SRT for the `selected` frames of the day in `day_dir`, played along `timeline` after
+/// `lead_in` frames of title card. None if frames.jsonl doesn't say anything about them.
+fn subtitles_in(
+    settings: &VideoSubtitles,
+    day_dir: &Path,
+    selected: &[usize],
+    timeline: &Timeline,
+    lead_in: usize,
+    cipher: Option<&FrameCipher>,
+) -> Option<String> {
+    let metadata: BTreeMap<usize, FrameMetadata> = frame_metadata::read_all(day_dir)
+        .into_iter()
+        .map(|mut m| {
+            // A title we can't decrypt is as good as no title.
+            if let Some(cipher) = cipher {
+                m.window_title = m
+                    .window_title
+                    .and_then(|title| cipher.decrypt_text(&title).ok());
+            }
+            (m.frame as usize, m)
+        })
+        .collect();
+
+    // Frames we don't know about (like filler without a note) go with the last one we do.
+    let mut cues: Vec<(usize, usize, String)> = Vec::new();
+    for (frame, &source) in selected.iter().enumerate() {
+        let Some((_, m)) = metadata.range(..=source).next_back() else {
+            continue;
+        };
+        let text = cue_text(settings, m);
+        match cues.last_mut() {
+            Some((_, end, last)) if *last == text && *end == frame => *end = frame + 1,
+            _ => cues.push((frame, frame + 1, text)),
+        }
+    }
+    if cues.is_empty() {
+        return None;
+    }
+
+    let mut srt = String::new();
+    for (i, (start, end, text)) in cues.iter().enumerate() {
+        srt.push_str(&format!(
+            "{}\n{} --> {}\n{text}\n\n",
+            i + 1,
+            srt_time(timeline.at(lead_in + start)),
+            srt_time(timeline.at(lead_in + end)),
+        ));
+    }
+    Some(srt)
+}
+
+impl MovieMaker {
+    /// Writes subtitles for the `selected` frames of `day_dir` into `work_dir`, if movies get
+    /// them, and returns where.
+    pub(super) fn subtitles_for(
+        &self,
+        day_dir: &Path,
+        selected: &[usize],
+        timeline: &Timeline,
+        lead_in: usize,
+        work_dir: &Path,
+    ) -> Option<PathBuf> {
+        let settings = self.video_subtitles.as_ref()?;
+        if self.builtin_encoder {
+            return None;
+        }
+
+        let cipher = self.cipher.as_ref();
+        let srt = subtitles_in(settings, day_dir, selected, timeline, lead_in, cipher)?;
+        let path = work_dir.join("subtitles.srt");
+        match fs::create_dir_all(work_dir).and_then(|_| fs::write(&path, srt)) {
+            Ok(()) => Some(path),
+            Err(e) => {
+                warn!("Couldn't write subtitles, making the movie without them: {e:?}");
+                None
+            }
+        }
+    }
+
+    /// What ffmpeg should write subtitles as, for this kind of movie.
+    pub(super) fn subtitle_codec(&self) -> Option<&'static str> {
+        SUBTITLE_CODECS
+            .iter()
+            .find(|(video_type, _)| *video_type == self.output_type)
+            .map(|(_, codec)| *codec)
+    }
+}
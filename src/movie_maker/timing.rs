@@ -0,0 +1,172 @@
+use chrono::{DateTime, Local};
+use std::collections::BTreeMap;
+
+/*
+When each frame comes up in the movie. Normally every frame gets the same time on screen, but with
+video_timing = "captured" each one is up for as long as it was until the next capture, next to the
+rest of the day, so a stretch captured once a minute takes longer to play through than one captured
+every few seconds. The movie comes out the same length either way.
+*/
+
+/// How far a frame's time on screen can stray from the usual, either way, so one long wait between
+/// captures doesn't take over the movie.
+const MAX_STRETCH: f64 = 4.0;
+
+#[derive(Clone, Debug)]
+pub struct Timeline {
+    /// When each frame starts, in seconds, and then when the last one ends.
+    starts: Vec<f64>,
+}
+
+impl Timeline {
+    /// `frames` frames played evenly at `rate`.
+    pub fn even(frames: usize, (rate_num, rate_den): (u32, u32)) -> Timeline {
+        let frame_secs = rate_den as f64 / rate_num as f64;
+        Timeline {
+            starts: (0..=frames)
+                .map(|frame| frame as f64 * frame_secs)
+                .collect(),
+        }
+    }
+
+    /// `lead_in` frames played evenly at `rate`, then the `selected` frames each for as long as
+    /// they were captured for going by `times`. `filler` frames weren't really captured, so they
+    /// get the usual time.
+    pub fn captured(
+        times: &BTreeMap<usize, DateTime<Local>>,
+        selected: &[usize],
+        filler: &[bool],
+        lead_in: usize,
+        rate: (u32, u32),
+    ) -> Timeline {
+        let even = Self::even(lead_in + selected.len(), rate);
+        let frame_secs = even.at(1);
+
+        // Seconds until the next capture, spread over any frames we don't know about in between.
+        let waits: Vec<Option<f64>> = selected
+            .iter()
+            .map(|&frame| {
+                if filler.get(frame).copied().unwrap_or(false) {
+                    return None;
+                }
+                let when = times.get(&frame)?;
+                let (next, next_when) = times.range(frame + 1..).next()?;
+                let secs = (*next_when - *when).num_milliseconds() as f64 / 1000.0;
+                Some(secs / (next - frame) as f64).filter(|secs| *secs > 0.0)
+            })
+            .collect();
+
+        let mut known: Vec<f64> = waits.iter().flatten().copied().collect();
+        if known.is_empty() {
+            return even;
+        }
+        known.sort_by(f64::total_cmp);
+        let usual = known[known.len() / 2];
+
+        let stretches: Vec<f64> = waits
+            .iter()
+            .map(|wait| {
+                wait.map_or(1.0, |secs| {
+                    (secs / usual).clamp(1.0 / MAX_STRETCH, MAX_STRETCH)
+                })
+            })
+            .collect();
+        let scale = selected.len() as f64 / stretches.iter().sum::<f64>();
+
+        let mut starts = even.starts[..=lead_in].to_vec();
+        for stretch in stretches {
+            let start = starts[starts.len() - 1];
+            starts.push(start + stretch * scale * frame_secs);
+        }
+        Timeline { starts }
+    }
+
+    /// When `frame` comes up, or when the movie ends for anything past the last one.
+    pub fn at(&self, frame: usize) -> f64 {
+        self.starts[frame.min(self.starts.len() - 1)]
+    }
+
+    /// How long each frame is up for.
+    pub fn durations(&self) -> Vec<f64> {
+        self.starts
+            .windows(2)
+            .map(|pair| pair[1] - pair[0])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timeline;
+    use chrono::{DateTime, Local, TimeZone};
+    use std::collections::BTreeMap;
+
+    /// Frames captured this many seconds into the day.
+    fn times(secs: &[(usize, i64)]) -> BTreeMap<usize, DateTime<Local>> {
+        let start = Local.with_ymd_and_hms(2024, 6, 3, 9, 0, 0).unwrap();
+        secs.iter()
+            .map(|&(frame, secs)| (frame, start + chrono::Duration::seconds(secs)))
+            .collect()
+    }
+
+    fn assert_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len(), "{actual:?} vs {expected:?}");
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-9, "{actual:?} vs {expected:?}");
+        }
+    }
+
+    #[test]
+    fn even() {
+        let timeline = Timeline::even(3, (2, 1));
+        assert_close(&timeline.durations(), &[0.5, 0.5, 0.5]);
+        assert_close(&[timeline.at(3)], &[1.5]);
+        assert_close(&[timeline.at(10)], &[1.5]);
+    }
+
+    #[test]
+    fn captured_goes_by_the_waits() {
+        let times = times(&[(0, 0), (1, 10), (2, 20), (3, 50)]);
+        let timeline = Timeline::captured(&times, &[0, 1, 2, 3], &[false; 4], 0, (1, 1));
+        // 30s is three times the usual 10s, and the last one has nothing to go by.
+        assert_close(
+            &timeline.durations(),
+            &[2.0 / 3.0, 2.0 / 3.0, 2.0, 2.0 / 3.0],
+        );
+        assert_close(&[timeline.at(4)], &[4.0]);
+    }
+
+    #[test]
+    fn captured_keeps_the_lead_in_even() {
+        let times = times(&[(0, 0), (1, 10), (2, 40)]);
+        let timeline = Timeline::captured(&times, &[0, 1, 2], &[false; 3], 2, (1, 1));
+        let durations = timeline.durations();
+        assert_close(&durations[..2], &[1.0, 1.0]);
+        assert_close(&[timeline.at(5)], &[5.0]);
+        assert!(durations[3] > durations[2]);
+    }
+
+    #[test]
+    fn captured_limits_the_stretch() {
+        let times = times(&[(0, 0), (1, 10), (2, 20), (3, 10_000), (4, 10_010)]);
+        let timeline = Timeline::captured(&times, &[0, 1, 2, 3, 4], &[false; 5], 0, (1, 1));
+        let durations = timeline.durations();
+        assert!((durations[2] / durations[0] - 4.0).abs() < 1e-9);
+        assert_close(&[timeline.at(5)], &[5.0]);
+    }
+
+    #[test]
+    fn captured_filler_and_skipped_frames() {
+        // Frame 2 is filler, and frame 4 got left out, so frame 3's wait is split over two.
+        let times = times(&[(0, 0), (1, 10), (2, 20), (3, 100), (5, 120)]);
+        let filler = [false, false, true, false, false, false];
+        let timeline = Timeline::captured(&times, &[0, 1, 2, 3, 5], &filler, 0, (1, 1));
+        assert_close(&timeline.durations(), &[1.0; 5]);
+    }
+
+    #[test]
+    fn captured_without_times_is_even() {
+        let timeline = Timeline::captured(&BTreeMap::new(), &[0, 1, 2], &[false; 3], 1, (1, 1));
+        assert_close(&timeline.durations(), &[1.0; 4]);
+    }
+}
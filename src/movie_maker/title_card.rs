@@ -0,0 +1,58 @@
+use super::MovieMaker;
+use crate::capturer::card;
+use chrono::NaiveDate;
+use image::{DynamicImage, RgbImage};
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+A card with the date on it at the start of each movie, so days can be told apart while scrubbing
+through a folder of them. It's drawn just like the filler for time away, but at the movie's size.
+*/
+
+impl MovieMaker {
+    /// How many frames the title card is up for at `rate`, none if there isn't one.
+    pub(super) fn title_card_frames(&self, (rate_num, rate_den): (u32, u32)) -> usize {
+        self.title_card.as_ref().map_or(0, |title_card| {
+            ((title_card.seconds * rate_num as f64 / rate_den as f64).round() as usize).max(1)
+        })
+    }
+
+    /// The title card for `date`, if movies get one.
+    pub(super) fn title_card_image(&self, date: NaiveDate) -> Option<RgbImage> {
+        let title_card = self.title_card.as_ref()?;
+        let date = date.format(&title_card.format).to_string();
+        let mut lines = vec![date.as_str()];
+        lines.extend(title_card.text.iter().flat_map(|text| text.lines()));
+
+        let img = card::draw(
+            &lines,
+            (self.output_width, self.output_height),
+            &self.filler_frame,
+            &card::font(&self.filler_frame),
+        );
+        Some(DynamicImage::ImageRgba8(img).into_rgb8())
+    }
+
+    /// Saves `card` in `work_dir` as an `extension` image for ffmpeg to read. None if that didn't
+    /// work out, so the movie can go ahead without it.
+    pub(super) fn write_title_card(
+        &self,
+        card: &RgbImage,
+        work_dir: &Path,
+        extension: &str,
+    ) -> Option<PathBuf> {
+        let path = work_dir.join(format!("title.{extension}"));
+        let written = fs::create_dir_all(work_dir)
+            .map_err(image::ImageError::IoError)
+            .and_then(|_| card.save(&path));
+        match written {
+            Ok(()) => Some(path),
+            Err(e) => {
+                warn!("Couldn't write the title card, making the movie without it: {e:?}");
+                None
+            }
+        }
+    }
+}
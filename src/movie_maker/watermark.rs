@@ -0,0 +1,85 @@
+use super::clock::filter_path;
+use super::MovieMaker;
+use crate::config;
+use anyhow::Error;
+use image::imageops::{self, FilterType};
+use image::{RgbImage, RgbaImage};
+use std::path::Path;
+
+/*
+A logo or the like over every movie, for people who publish theirs. ffmpeg reads the image in with
+its movie source filter, so it can go on the end of the same -vf chain as everything else.
+*/
+
+impl MovieMaker {
+    /// `chain` with the watermark put over whatever comes out of it, if there is one.
+    pub(super) fn with_watermark(&self, chain: String) -> String {
+        let Some(watermark) = &self.watermark else {
+            return chain;
+        };
+        let (across, down) = config::alignment(&watermark.position);
+        let margin = watermark.margin;
+
+        format!(
+            "{chain}[ompd_base];\
+             movie={},scale={}:-1,format=rgba,colorchannelmixer=aa={}[ompd_watermark];\
+             [ompd_base][ompd_watermark]overlay=x=(W-w-{})*{across}+{margin}:y=(H-h-{})*{down}+{margin}",
+            filter_path(Path::new(&watermark.image)),
+            self.watermark_width(self.output_width),
+            watermark.opacity,
+            margin * 2,
+            margin * 2,
+        )
+    }
+
+    /// The watermark, sized and faded for `width` wide frames, for the built in encoder.
+    pub(super) fn watermark_image(&self, width: u32) -> Result<Option<RgbaImage>, Error> {
+        let Some(watermark) = &self.watermark else {
+            return Ok(None);
+        };
+        let img = image::open(&watermark.image)?.to_rgba8();
+        let scaled_width = self.watermark_width(width);
+        let scaled_height = ((img.height() as f32 * scaled_width as f32 / img.width() as f32)
+            .round() as u32)
+            .max(1);
+
+        let mut img = imageops::resize(&img, scaled_width, scaled_height, FilterType::Lanczos3);
+        for pixel in img.pixels_mut() {
+            pixel[3] = (pixel[3] as f32 * watermark.opacity).round() as u8;
+        }
+        Ok(Some(img))
+    }
+
+    /// Blends `img` from watermark_image into `frame`, wherever the watermark goes.
+    pub(super) fn apply_watermark(&self, frame: &mut RgbImage, img: &RgbaImage) {
+        let Some(watermark) = &self.watermark else {
+            return;
+        };
+        let (across, down) = config::alignment(&watermark.position);
+        let margin = watermark.margin;
+        let room_across = frame.width().saturating_sub(img.width() + margin * 2);
+        let room_down = frame.height().saturating_sub(img.height() + margin * 2);
+        let left = margin + (room_across as f32 * across) as u32;
+        let top = margin + (room_down as f32 * down) as u32;
+
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let (x, y) = (left + x, top + y);
+            if x >= frame.width() || y >= frame.height() {
+                continue;
+            }
+            let alpha = pixel[3] as f32 / 255.0;
+            let under = frame.get_pixel_mut(x, y);
+            for channel in 0..3 {
+                under[channel] = (pixel[channel] as f32 * alpha
+                    + under[channel] as f32 * (1.0 - alpha))
+                    .round() as u8;
+            }
+        }
+    }
+
+    fn watermark_width(&self, width: u32) -> u32 {
+        self.watermark.as_ref().map_or(0, |watermark| {
+            ((width as f32 * watermark.scale).round() as u32).max(1)
+        })
+    }
+}
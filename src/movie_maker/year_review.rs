@@ -0,0 +1,156 @@
+use super::probe::Packet;
+use super::progress::Tracker;
+use super::{FfmpegInput, MovieMaker};
+use crate::DirManager;
+use anyhow::{anyhow, Error};
+use chrono::NaiveDate;
+use glob::glob;
+use log::{debug, info, warn};
+use std::fs;
+use std::path::PathBuf;
+
+/*
+One slice of every day's movie, back to back, for the whole year. The days' movies are already
+made, so this just picks a bit of each and hands ffmpeg a concat list with in and out points.
+
+"busiest" goes by how many bytes the encoder needed for each stretch of the day: a screen that
+isn't changing compresses down to almost nothing, so the biggest stretch is where the most was
+going on.
+*/
+
+impl MovieMaker {
+    pub fn year_review_path(&self, year: i32) -> Option<PathBuf> {
+        let review = self.year_review.as_ref()?;
+        let new_year = NaiveDate::from_ymd_opt(year, 1, 1)?;
+        let name = self.video_name.with_template(&review.name_template);
+        Some(self.output_dir.join(name.for_day(new_year)))
+    }
+
+    /// Make the review of `year` if we're supposed to and haven't yet.
+    pub fn make_year_review_if_missing(&self, year: i32) {
+        match self.year_review_path(year) {
+            Some(path) if !path.exists() => {}
+            _ => return,
+        }
+
+        info!("Making the review of {year}");
+        match self.make_year_review(year) {
+            Ok(review) => info!("Made {review:?}"),
+            Err(e) => warn!("Couldn't make the review of {year}: {e:?}"),
+        }
+    }
+
+    pub fn make_year_review(&self, year: i32) -> Result<PathBuf, Error> {
+        let review = self
+            .year_review
+            .as_ref()
+            .ok_or_else(|| anyhow!("year_review isn't set up"))?;
+        if self.builtin_encoder {
+            return Err(anyhow!("Can't make a year review without ffmpeg"));
+        }
+
+        let day_glob = self.output_dir.join(self.video_name.glob_for_year(year));
+        // By the date in the name, since a template can put the date anywhere.
+        let mut dated: Vec<(NaiveDate, PathBuf)> = glob(&day_glob.to_string_lossy())?
+            .filter_map(Result::ok)
+            .filter_map(|day| {
+                let date = self.video_name.parse(&day.file_name()?.to_string_lossy())?;
+                Some((date, day))
+            })
+            .collect();
+        dated.sort();
+        let days: Vec<PathBuf> = dated.into_iter().map(|(_, day)| day).collect();
+        if days.is_empty() {
+            return Err(anyhow!("No movies from {year} to review"));
+        }
+
+        let work = tempfile::Builder::new()
+            .prefix(&format!("ompd-{year}-review-"))
+            .tempdir()?;
+        let work_dir = work.path();
+
+        let mut list = String::from("ffconcat version 1.0\n");
+        let mut frames = 0;
+        for day in &days {
+            let packets = match self.packets_in(day) {
+                Ok(packets) => packets,
+                Err(e) => {
+                    warn!("Couldn't look inside {day:?}, leaving it out: {e:?}");
+                    continue;
+                }
+            };
+
+            let (start, in_slice) = match review.pick.as_str() {
+                "busiest" => busiest_slice(&packets, review.seconds_per_day),
+                _ => (
+                    0.0,
+                    packets
+                        .iter()
+                        .filter(|p| p.at < review.seconds_per_day)
+                        .count(),
+                ),
+            };
+            debug!("Using {in_slice} frames from {day:?} starting at {start}s");
+            frames += in_slice;
+
+            let path = day.to_string_lossy().replace('\'', r"'\''");
+            list.push_str(&format!(
+                "file '{path}'\ninpoint {start}\noutpoint {}\n",
+                start + review.seconds_per_day
+            ));
+        }
+
+        let list_path = work_dir.join("days.ffconcat");
+        fs::write(&list_path, list)?;
+        let args: Vec<String> = vec![
+            "-f".into(),
+            "concat".into(),
+            "-safe".into(),
+            "0".into(),
+            "-i".into(),
+            list_path.to_string_lossy().into(),
+        ];
+
+        let out_path = self
+            .year_review_path(year)
+            .ok_or_else(|| anyhow!("Can't name a review of {year}"))?;
+        let partial_path = DirManager::partial_path(&out_path);
+        let mut tracker = Tracker::new(&format!("{year} review"), frames);
+        let input = FfmpegInput {
+            args,
+            stdin_frames: None,
+            // Days were already turned the right way when their movies were made.
+            rotation: 0,
+            stack: None,
+        };
+        self.encode(&input, &[], work_dir, &partial_path, None, &mut tracker)?;
+        fs::rename(&partial_path, &out_path)?;
+
+        if let Err(e) = work.close() {
+            warn!("Couldn't clean up after the {year} review: {e}");
+        }
+
+        self.uploader.upload(&out_path);
+        Ok(out_path)
+    }
+}
+
+/// Where the `seconds` long stretch with the most bytes in it starts, and how many frames it has.
+fn busiest_slice(packets: &[Packet], seconds: f64) -> (f64, usize) {
+    let mut best = (0.0, 0, 0);
+    let mut end = 0;
+    let mut bytes = 0;
+
+    for (start, packet) in packets.iter().enumerate() {
+        while end < packets.len() && packets[end].at < packet.at + seconds {
+            bytes += packets[end].bytes;
+            end += 1;
+        }
+        if bytes > best.2 {
+            best = (packet.at, end - start, bytes);
+        }
+        bytes -= packet.bytes;
+    }
+
+    (best.0, best.1)
+}
@@ -0,0 +1,85 @@
+use log::{debug, warn};
+#[cfg(not(target_os = "windows"))]
+use std::process::Command;
+
+/*
+Best effort desktop notification, using whatever each platform ships with. If it doesn't work the
+message is still in the log, which is where it was going to end up anyway.
+*/
+
+#[cfg(target_os = "linux")]
+fn notification_command(title: &str, body: &str) -> Command {
+    let mut cmd = Command::new("notify-send");
+    cmd.args(["--urgency=critical", title, body]);
+    cmd
+}
+
+#[cfg(target_os = "macos")]
+fn notification_command(title: &str, body: &str) -> Command {
+    let script = format!(
+        "display notification {:?} with title {:?}",
+        body.replace('"', "'"),
+        title.replace('"', "'")
+    );
+    let mut cmd = Command::new("/usr/bin/osascript");
+    cmd.args(["-e", &script]);
+    cmd
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn notification_command(title: &str, body: &str) -> Command {
+    let mut cmd = Command::new("notify-send");
+    cmd.args([title, body]);
+    cmd
+}
+
+/// Borrowed from PowerShell, since toasts have to come from a registered app and we aren't one.
+#[cfg(target_os = "windows")]
+const TOAST_APP_ID: &str =
+    r"{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe";
+
+#[cfg(target_os = "windows")]
+fn toast(title: &str, body: &str) -> windows::core::Result<()> {
+    use windows::core::HSTRING;
+    use windows::Data::Xml::Dom::XmlDocument;
+    use windows::UI::Notifications::{ToastNotification, ToastNotificationManager};
+
+    let escape = |text: &str| {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let xml = XmlDocument::new()?;
+    xml.LoadXml(&HSTRING::from(format!(
+        "<toast><visual><binding template=\"ToastGeneric\"><text>{}</text><text>{}</text>\
+         </binding></visual></toast>",
+        escape(title),
+        escape(body)
+    )))?;
+
+    let notifier =
+        ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(TOAST_APP_ID))?;
+    notifier.Show(&ToastNotification::CreateToastNotification(&xml)?)
+}
+
+/// A toast rather than anything like `msg`, which pops up a box that sits there until it's
+/// clicked.
+#[cfg(target_os = "windows")]
+pub fn desktop(title: &str, body: &str) {
+    debug!("Notifying with a toast: {title}");
+    if let Err(e) = toast(title, body) {
+        warn!("Couldn't send a desktop notification: {e}");
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn desktop(title: &str, body: &str) {
+    let mut cmd = notification_command(title, body);
+    debug!("Notifying with {cmd:?}");
+
+    match cmd.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("Desktop notification exited with {status}"),
+        Err(e) => warn!("Couldn't send a desktop notification: {e}"),
+    }
+}
@@ -0,0 +1,117 @@
+mod s3;
+mod sftp;
+mod webdav;
+
+use anyhow::Error;
+use glob::glob;
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::video_name::VideoName;
+
+/*
+Somewhere other than this machine to put finished movies. Each sink leaves a marker file next to the
+movie once it's taken it, e.g. "ompd-2023-04-05.mp4.s3.uploaded", so nothing gets sent twice and
+anything that didn't make it gets picked up by the backfiller next time around.
+*/
+
+pub trait Sink: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn upload(&self, local: &Path, remote_name: &str) -> Result<(), Error>;
+}
+
+pub struct Uploader {
+    sinks: Vec<Box<dyn Sink>>,
+    attempts: u32,
+}
+
+impl Uploader {
+    pub fn new(config: &Config) -> Uploader {
+        let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+        if let Some(s3_config) = &config.s3_upload {
+            sinks.push(Box::new(s3::S3Sink::new(s3_config)));
+        }
+        if let Some(sftp_config) = &config.sftp_upload {
+            sinks.push(Box::new(sftp::SftpSink::new(sftp_config)));
+        }
+        if let Some(webdav_config) = &config.webdav_upload {
+            sinks.push(Box::new(webdav::WebDavSink::new(webdav_config)));
+        }
+
+        Uploader {
+            sinks,
+            attempts: config.upload_attempts,
+        }
+    }
+
+    /// Send a file everywhere it hasn't been sent yet.
+    pub fn upload(&self, local: &Path) {
+        let remote_name = local.file_name().unwrap().to_string_lossy().to_string();
+
+        for sink in &self.sinks {
+            let marker = Self::marker_for(local, sink.name());
+            if marker.exists() {
+                continue;
+            }
+
+            match self.upload_with_retries(sink.as_ref(), local, &remote_name) {
+                Ok(()) => {
+                    info!("Uploaded {local:?} to {}", sink.name());
+                    if let Err(e) = std::fs::write(&marker, "") {
+                        warn!("Couldn't leave an upload marker at {marker:?}: {e}");
+                    }
+                }
+                Err(e) => warn!("Giving up uploading {local:?} to {}: {e:?}", sink.name()),
+            }
+        }
+    }
+
+    /// Upload any movies that didn't make it the first time around.
+    pub fn upload_pending(&self, vid_dir: &Path, video_name: &VideoName) {
+        if self.sinks.is_empty() {
+            return;
+        }
+
+        let video_glob = vid_dir.join(video_name.glob());
+        for video in glob(&video_glob.to_string_lossy())
+            .unwrap()
+            .filter_map(Result::ok)
+        {
+            self.upload(&video);
+        }
+    }
+
+    fn upload_with_retries(
+        &self,
+        sink: &dyn Sink,
+        local: &Path,
+        remote_name: &str,
+    ) -> Result<(), Error> {
+        let mut attempt = 1;
+        loop {
+            match sink.upload(local, remote_name) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < self.attempts => {
+                    let wait = Duration::from_secs(2u64.pow(attempt));
+                    warn!(
+                        "Upload of {local:?} to {} failed (attempt {attempt}), trying again in {wait:?}: {e:?}",
+                        sink.name()
+                    );
+                    sleep(wait);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn marker_for(local: &Path, sink_name: &str) -> PathBuf {
+        let mut marker = local.as_os_str().to_owned();
+        marker.push(format!(".{sink_name}.uploaded"));
+        PathBuf::from(marker)
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Error};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+use super::Sink;
+use crate::config::S3Upload;
+
+/*
+Just enough AWS Signature Version 4 to PUT an object, which is all we need and saves pulling in an
+entire SDK. Uses path style URLs (endpoint/bucket/key) since every S3 clone out there supports those.
+*/
+
+pub struct S3Sink {
+    config: S3Upload,
+}
+
+impl S3Sink {
+    pub fn new(config: &S3Upload) -> S3Sink {
+        S3Sink {
+            config: config.clone(),
+        }
+    }
+
+    /// The endpoint split into where to connect ("https://host:9000") and any path it has on
+    /// the end ("/s3"), which every object's path has to start with, signature and all.
+    fn split_endpoint(&self) -> (&str, &str) {
+        let endpoint = self.config.endpoint.trim_end_matches('/');
+        let host_starts = endpoint.find("://").map_or(0, |i| i + 3);
+        match endpoint[host_starts..].find('/') {
+            Some(path_starts) => endpoint.split_at(host_starts + path_starts),
+            None => (endpoint, ""),
+        }
+    }
+
+    fn host(&self) -> &str {
+        let (origin, _) = self.split_endpoint();
+        origin.split_once("://").map_or(origin, |(_, host)| host)
+    }
+}
+
+impl Sink for S3Sink {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn upload(&self, local: &Path, remote_name: &str) -> Result<(), Error> {
+        let body = std::fs::read(local)?;
+        let key = format!("{}{remote_name}", self.config.prefix);
+        let (origin, base_path) = self.split_endpoint();
+        let path = format!(
+            "{}/{}/{}",
+            uri_encode(base_path),
+            self.config.bucket,
+            uri_encode(&key)
+        );
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex(&Sha256::digest(&body));
+        let host = self.host();
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "PUT\n{path}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}"
+        );
+
+        let scope = format!("{date}/{}/s3/aws4_request", self.config.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let signing_key = [self.config.region.as_str(), "s3", "aws4_request"]
+            .iter()
+            .fold(
+                hmac(
+                    format!("AWS4{}", self.config.secret_access_key).as_bytes(),
+                    date.as_bytes(),
+                ),
+                |key, part| hmac(&key, part.as_bytes()),
+            );
+        let signature = hex(&hmac(&signing_key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.config.access_key_id
+        );
+
+        let url = format!("{origin}{path}");
+        ureq::put(&url)
+            .set("x-amz-content-sha256", &payload_hash)
+            .set("x-amz-date", &amz_date)
+            .set("Authorization", &authorization)
+            .send_bytes(&body)
+            .map_err(|e| anyhow!("PUT {url} failed: {e}"))?;
+
+        Ok(())
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any size key");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn uri_encode(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
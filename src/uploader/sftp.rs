@@ -0,0 +1,69 @@
+use anyhow::{anyhow, Error};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use super::Sink;
+use crate::config::SftpUpload;
+
+/*
+Everything that speaks SFTP already has an ssh setup (keys, known_hosts, ~/.ssh/config aliases) that
+we'd be foolish to try and reimplement, so lean on the system's sftp in batch mode. Batch mode also
+means it'll fail instead of sitting there waiting for a password nobody's going to type.
+*/
+
+pub struct SftpSink {
+    config: SftpUpload,
+}
+
+impl SftpSink {
+    pub fn new(config: &SftpUpload) -> SftpSink {
+        SftpSink {
+            config: config.clone(),
+        }
+    }
+}
+
+impl Sink for SftpSink {
+    fn name(&self) -> &'static str {
+        "sftp"
+    }
+
+    fn upload(&self, local: &Path, remote_name: &str) -> Result<(), Error> {
+        let mut cmd = Command::new(&self.config.sftp);
+        cmd.args(["-b", "-", "-o", "BatchMode=yes"]);
+        if let Some(port) = self.config.port {
+            cmd.args(["-P", &port.to_string()]);
+        }
+        if let Some(identity_file) = &self.config.identity_file {
+            cmd.args(["-i", identity_file]);
+        }
+        cmd.arg(&self.config.host)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+
+        let mut child = cmd.spawn()?;
+
+        // Upload under a temporary name and rename, so a half-sent movie never looks finished.
+        let remote_dir = self.config.remote_dir.trim_end_matches('/');
+        let remote = format!("{remote_dir}/{remote_name}");
+        let partial = format!("{remote}.partial");
+        let batch = format!(
+            "put \"{}\" \"{partial}\"\n-rm \"{remote}\"\nrename \"{partial}\" \"{remote}\"\n",
+            local.to_string_lossy()
+        );
+        child.stdin.take().unwrap().write_all(batch.as_bytes())?;
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "sftp to {} failed: {}",
+                self.config.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(())
+    }
+}
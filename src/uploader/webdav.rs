@@ -0,0 +1,43 @@
+use anyhow::{anyhow, Error};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::fs::File;
+use std::path::Path;
+
+use super::Sink;
+use crate::config::WebDavUpload;
+
+/// Plain HTTP PUT into a WebDAV collection (Nextcloud, a Synology, Apache mod_dav, ...).
+pub struct WebDavSink {
+    config: WebDavUpload,
+}
+
+impl WebDavSink {
+    pub fn new(config: &WebDavUpload) -> WebDavSink {
+        WebDavSink {
+            config: config.clone(),
+        }
+    }
+}
+
+impl Sink for WebDavSink {
+    fn name(&self) -> &'static str {
+        "webdav"
+    }
+
+    fn upload(&self, local: &Path, remote_name: &str) -> Result<(), Error> {
+        let url = format!("{}/{remote_name}", self.config.url.trim_end_matches('/'));
+        let credentials =
+            STANDARD.encode(format!("{}:{}", self.config.username, self.config.password));
+        let file = File::open(local)?;
+        let length = file.metadata()?.len();
+
+        ureq::put(&url)
+            .set("Authorization", &format!("Basic {credentials}"))
+            .set("Content-Length", &length.to_string())
+            .send(file)
+            .map_err(|e| anyhow!("PUT {url} failed: {e}"))?;
+
+        Ok(())
+    }
+}
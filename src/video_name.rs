@@ -0,0 +1,160 @@
+use chrono::{Datelike, NaiveDate};
+use glob::Pattern;
+
+/*
+What to call each day's movie, from video_name_template. Everything that goes looking for movies
+uses this too, so a custom name doesn't make it look like days are missing their movie.
+*/
+
+pub const DEFAULT_TEMPLATE: &str = "ompd-{year}-{month}-{day}.{ext}";
+
+/// Containers a day's movie might be in, for finding movies made back when video_type was
+/// something else.
+pub const VIDEO_TYPES: &[&str] = &["mkv", "mp4", "mov", "m4v", "webm", "avi", "gif", "webp"];
+
+/// Everything a template can have in it, besides plain text.
+pub const PLACEHOLDERS: &[&str] = &["{hostname}", "{year}", "{month}", "{day}", "{ext}"];
+
+#[derive(Debug)]
+enum Part {
+    Text(String),
+    Hostname,
+    Year,
+    Month,
+    Day,
+    Ext,
+}
+
+#[derive(Clone, Debug)]
+pub struct VideoName {
+    template: String,
+    hostname: String,
+    ext: String,
+}
+
+impl VideoName {
+    pub fn new(template: &str, hostname: &str, ext: &str) -> VideoName {
+        VideoName {
+            template: template.to_string(),
+            hostname: hostname.to_string(),
+            ext: ext.to_string(),
+        }
+    }
+
+    /// The same name, for movies in a different container.
+    pub fn with_ext(&self, ext: &str) -> VideoName {
+        VideoName {
+            ext: ext.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// Another name for the same host and container, like a year review's.
+    pub fn with_template(&self, template: &str) -> VideoName {
+        VideoName {
+            template: template.to_string(),
+            ..self.clone()
+        }
+    }
+
+    fn parts(&self) -> Vec<Part> {
+        let mut parts = Vec::new();
+        let mut rest = self.template.as_str();
+
+        while !rest.is_empty() {
+            let next = PLACEHOLDERS
+                .iter()
+                .filter_map(|p| rest.find(p).map(|at| (at, *p)))
+                .min();
+            let Some((at, placeholder)) = next else {
+                parts.push(Part::Text(rest.to_string()));
+                break;
+            };
+
+            if at > 0 {
+                parts.push(Part::Text(rest[..at].to_string()));
+            }
+            parts.push(match placeholder {
+                "{hostname}" => Part::Hostname,
+                "{year}" => Part::Year,
+                "{month}" => Part::Month,
+                "{day}" => Part::Day,
+                _ => Part::Ext,
+            });
+            rest = &rest[at + placeholder.len()..];
+        }
+
+        parts
+    }
+
+    pub fn for_day(&self, day: NaiveDate) -> String {
+        self.parts()
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Hostname => self.hostname.clone(),
+                Part::Year => format!("{:04}", day.year()),
+                Part::Month => format!("{:02}", day.month()),
+                Part::Day => format!("{:02}", day.day()),
+                Part::Ext => self.ext.clone(),
+            })
+            .collect()
+    }
+
+    /// Matches every day's movie, and only those.
+    pub fn glob(&self) -> String {
+        self.glob_with_year("[0-9][0-9][0-9][0-9]")
+    }
+
+    /// Matches every day's movie from `year`.
+    pub fn glob_for_year(&self, year: i32) -> String {
+        self.glob_with_year(&format!("{year:04}"))
+    }
+
+    fn glob_with_year(&self, year: &str) -> String {
+        self.parts()
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => Pattern::escape(text),
+                Part::Hostname => Pattern::escape(&self.hostname),
+                Part::Year => year.to_string(),
+                Part::Month | Part::Day => "[0-9][0-9]".to_string(),
+                Part::Ext => Pattern::escape(&self.ext),
+            })
+            .collect()
+    }
+
+    /// Which day a movie's file name is for, if it's one of ours.
+    pub fn parse(&self, file_name: &str) -> Option<NaiveDate> {
+        let mut rest = file_name;
+        let (mut year, mut month, mut day) = (None, None, None);
+
+        for part in self.parts() {
+            let expected = match &part {
+                Part::Text(text) => text.as_str(),
+                Part::Hostname => self.hostname.as_str(),
+                Part::Ext => self.ext.as_str(),
+                Part::Year => {
+                    year = Some(rest.get(..4)?.parse::<i32>().ok()?);
+                    rest = &rest[4..];
+                    continue;
+                }
+                Part::Month | Part::Day => {
+                    let value = rest.get(..2)?.parse::<u32>().ok()?;
+                    match part {
+                        Part::Month => month = Some(value),
+                        _ => day = Some(value),
+                    }
+                    rest = &rest[2..];
+                    continue;
+                }
+            };
+            rest = rest.strip_prefix(expected)?;
+        }
+
+        if !rest.is_empty() {
+            return None;
+        }
+        NaiveDate::from_ymd_opt(year?, month?, day?)
+    }
+}